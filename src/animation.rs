@@ -0,0 +1,181 @@
+//! Keyframed values for animated scenes.
+//!
+//! A [`Track`] holds keys sorted by time and is sampled at the time of every frame, the
+//! interpolation of a key applies to the segment up to the next key. Before the first and
+//! after the last key the track holds the value of that key.
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Interpolation {
+    #[default]
+    Linear,
+    /// Cubic Bezier curve with automatic handles, passing smoothly through the keys. The
+    /// curve eases in and out at the first and last key.
+    Bezier,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Key<T> {
+    pub time: f32,
+    pub value: T,
+    #[serde(default, skip_serializing_if = "is_linear")]
+    pub interpolation: Interpolation,
+}
+
+fn is_linear(interpolation: &Interpolation) -> bool {
+    *interpolation == Interpolation::Linear
+}
+
+/// Values that can be interpolated between keys.
+pub trait Animatable: Copy {
+    /// Weighted sum of the values, the weights sum to one.
+    fn blend(values: [Self; 4], weights: [f32; 4]) -> Self;
+}
+
+impl Animatable for f32 {
+    fn blend(values: [Self; 4], weights: [f32; 4]) -> Self {
+        values.iter().zip(weights).map(|(v, w)| v * w).sum()
+    }
+}
+
+impl<const N: usize> Animatable for [f32; N] {
+    fn blend(values: [Self; 4], weights: [f32; 4]) -> Self {
+        std::array::from_fn(|i| f32::blend(values.map(|v| v[i]), weights))
+    }
+}
+
+/// Keys of a single animated value, sorted by time. It is stored as the list of its keys, which
+/// are sorted when it is loaded.
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+#[serde(
+    from = "Vec<Key<T>>",
+    into = "Vec<Key<T>>",
+    bound(serialize = "T: Clone + Serialize")
+)]
+pub struct Track<T> {
+    keys: Vec<Key<T>>,
+}
+
+impl<T> Track<T> {
+    pub fn new(mut keys: Vec<Key<T>>) -> Self {
+        keys.sort_by(|a, b| a.time.total_cmp(&b.time));
+        Self { keys }
+    }
+
+    pub fn keys(&self) -> &[Key<T>] {
+        &self.keys
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+}
+
+impl<T: Animatable> Track<T> {
+    /// Value at the given time, `None` for a track without keys.
+    pub fn sample(&self, time: f32) -> Option<T> {
+        let keys = &self.keys;
+        let first = keys.first()?;
+        let last = keys.last()?;
+        if time <= first.time {
+            return Some(first.value);
+        }
+        if time >= last.time {
+            return Some(last.value);
+        }
+
+        // segment from key i to key i + 1 containing the time
+        let i = keys.partition_point(|k| k.time <= time) - 1;
+        let (k0, k1) = (&keys[i], &keys[i + 1]);
+        let dt = k1.time - k0.time;
+        let s = (time - k0.time) / dt;
+
+        let prev = i.saturating_sub(1);
+        let next = (i + 2).min(keys.len() - 1);
+        let values = [keys[prev].value, k0.value, k1.value, keys[next].value];
+
+        let weights = match k0.interpolation {
+            Interpolation::Linear => [0.0, 1.0 - s, s, 0.0],
+            Interpolation::Bezier => {
+                // the handles follow the slope between the neighbouring keys, a third of the
+                // segment long, and are flat at the ends of the track
+                let handle = |a: usize, b: usize, end: bool| {
+                    if end {
+                        0.0
+                    } else {
+                        dt / (3.0 * (keys[b].time - keys[a].time))
+                    }
+                };
+                let a = handle(prev, i + 1, prev == i);
+                let b = handle(i, next, next == i + 1);
+
+                let t = 1.0 - s;
+                let (b0, b1, b2, b3) = (t * t * t, 3.0 * t * t * s, 3.0 * t * s * s, s * s * s);
+                [-b1 * a, b0 + b1 + b2 * b, b1 * a + b2 + b3, -b2 * b]
+            }
+        };
+
+        Some(T::blend(values, weights))
+    }
+}
+
+impl<T> From<Vec<Key<T>>> for Track<T> {
+    fn from(keys: Vec<Key<T>>) -> Self {
+        Self::new(keys)
+    }
+}
+
+impl<T> From<Track<T>> for Vec<Key<T>> {
+    fn from(track: Track<T>) -> Self {
+        track.keys
+    }
+}
+
+#[test]
+fn test_track_sample() {
+    fn key<T>(time: f32, value: T, interpolation: Interpolation) -> Key<T> {
+        Key {
+            time,
+            value,
+            interpolation,
+        }
+    }
+
+    let linear = Track::new(vec![
+        key(1.0, [2.0, 0.0], Interpolation::Linear),
+        key(0.0, [0.0, 0.0], Interpolation::Linear),
+    ]);
+    assert_eq!(linear.sample(-1.0), Some([0.0, 0.0]));
+    assert_eq!(linear.sample(0.25), Some([0.5, 0.0]));
+    assert_eq!(linear.sample(2.0), Some([2.0, 0.0]));
+    assert_eq!(Track::<f32>::default().sample(0.0), None);
+
+    let bezier = Track::new(vec![
+        key(0.0, 0.0, Interpolation::Bezier),
+        key(1.0, 1.0, Interpolation::Bezier),
+        key(2.0, 2.0, Interpolation::Bezier),
+    ]);
+    // passes through the keys, eases in and out at the ends and keeps the slope in between
+    for (time, value) in [(0.0, 0.0), (1.0, 1.0), (2.0, 2.0)] {
+        assert!((bezier.sample(time).unwrap() - value).abs() < 1e-6);
+    }
+    assert!((bezier.sample(0.5).unwrap() - 0.375).abs() < 1e-6);
+    assert!((bezier.sample(1.5).unwrap() - 1.625).abs() < 1e-6);
+    assert!((bezier.sample(1.01).unwrap() - 1.01).abs() < 1e-4);
+
+    // keys are sorted when loaded, and saved as a plain list
+    let track: Track<f32> = serde_json::from_str(
+        r#"[{"time": 2.0, "value": 2.0}, {"time": 0.0, "value": 0.0}, {"time": 1.0, "value": 1.0}]"#,
+    )
+    .unwrap();
+    assert_eq!(track.sample(0.5), Some(0.5));
+    assert_eq!(track.sample(1.5), Some(1.5));
+    let json = serde_json::to_string(&track).unwrap();
+    assert!(
+        json.starts_with(r#"[{"time":0.0,"value":0.0},"#),
+        "{}",
+        json
+    );
+}
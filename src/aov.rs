@@ -0,0 +1,205 @@
+//! Arbitrary output variables, passes rendered alongside the beauty image for compositing.
+//!
+//! The first hit of every camera ray records its geometry and shading data, and the
+//! integrator splits the light into the emission seen directly, the direct lighting reflected
+//! at the first hit and the remaining indirect lighting. The three add up to the beauty image.
+
+use crate::hit::HitRecord;
+use nalgebra::Vector3;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Aov {
+    /// Distance from the camera to the closest hit in the pixel, 0 without a hit.
+    Depth,
+    /// World space shading normal, facing the camera.
+    Normal,
+    /// Attenuation of the first scattering event.
+    Albedo,
+    /// Id of the top level scene object, see [`crate::instance::ObjectId`].
+    ObjectId,
+    /// Id of the material, see [`crate::material::MaterialId`].
+    MaterialId,
+    /// Texture coordinates in the red and green channels.
+    Uv,
+    /// Light reflected at the first hit, coming straight from an emitter or the background.
+    Direct,
+    /// Light reflected at the first hit after more than one bounce.
+    Indirect,
+    /// Emission of the first hit, or the background for rays without a hit.
+    Emission,
+    /// Number of valid samples of the pixel.
+    SampleCount,
+}
+
+impl Aov {
+    pub const ALL: [Aov; 10] = [
+        Aov::Depth,
+        Aov::Normal,
+        Aov::Albedo,
+        Aov::ObjectId,
+        Aov::MaterialId,
+        Aov::Uv,
+        Aov::Direct,
+        Aov::Indirect,
+        Aov::Emission,
+        Aov::SampleCount,
+    ];
+
+    /// Name of the pass, used for the layers and file names.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Aov::Depth => "depth",
+            Aov::Normal => "normal",
+            Aov::Albedo => "albedo",
+            Aov::ObjectId => "object_id",
+            Aov::MaterialId => "material_id",
+            Aov::Uv => "uv",
+            Aov::Direct => "direct",
+            Aov::Indirect => "indirect",
+            Aov::Emission => "emission",
+            Aov::SampleCount => "sample_count",
+        }
+    }
+}
+
+/// Passes recorded along a single camera ray.
+#[derive(Debug, Copy, Clone, Default)]
+pub struct AovSample {
+    /// Distance to the first hit, `None` for rays without a hit.
+    pub depth: Option<f32>,
+    pub normal: Vector3<f32>,
+    pub albedo: Vector3<f32>,
+    pub object_id: u32,
+    pub material_id: u32,
+    pub uv: [f32; 2],
+    pub direct: Vector3<f32>,
+    pub indirect: Vector3<f32>,
+    pub emission: Vector3<f32>,
+}
+
+impl AovSample {
+    /// Record the geometry of the first hit along a ray with the given direction.
+    pub fn record_hit(&mut self, hit: &HitRecord, direction: &Vector3<f32>) {
+        self.depth = Some(hit.t * direction.norm());
+        self.normal = hit.n;
+        self.object_id = hit.object_id;
+        self.material_id = hit.m.id();
+        self.uv = [hit.u, hit.v];
+    }
+}
+
+/// Passes of a pixel, accumulated over its samples.
+///
+/// The light and shading passes are averaged like the beauty image. The depth is the closest
+/// one, and the ids are taken from the first sample with a hit, so they are never blended
+/// across object edges.
+#[derive(Debug, Copy, Clone, Default)]
+pub struct AovPixel {
+    samples: u32,
+    depth: Option<f32>,
+    normal: Vector3<f32>,
+    albedo: Vector3<f32>,
+    ids: Option<(u32, u32)>,
+    uv: [f32; 2],
+    direct: Vector3<f32>,
+    indirect: Vector3<f32>,
+    emission: Vector3<f32>,
+}
+
+impl AovPixel {
+    pub fn add(&mut self, sample: &AovSample) {
+        self.samples += 1;
+        if let Some(depth) = sample.depth {
+            self.depth = Some(self.depth.map_or(depth, |d| d.min(depth)));
+            self.ids
+                .get_or_insert((sample.object_id, sample.material_id));
+        }
+        self.normal += sample.normal;
+        self.albedo += sample.albedo;
+        self.uv[0] += sample.uv[0];
+        self.uv[1] += sample.uv[1];
+        self.direct += sample.direct;
+        self.indirect += sample.indirect;
+        self.emission += sample.emission;
+    }
+
+    /// Value of a pass, with the lighting passes scaled by the exposure.
+    pub fn value(&self, aov: Aov, exposure: f32) -> [f32; 3] {
+        let n = self.samples.max(1) as f32;
+        let gray = |v: f32| [v; 3];
+        let color = |v: Vector3<f32>| (v / n).into();
+        let (object_id, material_id) = self.ids.unwrap_or_default();
+
+        match aov {
+            Aov::Depth => gray(self.depth.unwrap_or(0.0)),
+            Aov::Normal => {
+                let normal = self.normal.try_normalize(1e-6).unwrap_or_default();
+                normal.into()
+            }
+            Aov::Albedo => color(self.albedo),
+            Aov::ObjectId => gray(object_id as f32),
+            Aov::MaterialId => gray(material_id as f32),
+            Aov::Uv => [self.uv[0] / n, self.uv[1] / n, 0.0],
+            Aov::Direct => color(self.direct * exposure),
+            Aov::Indirect => color(self.indirect * exposure),
+            Aov::Emission => color(self.emission * exposure),
+            Aov::SampleCount => gray(self.samples as f32),
+        }
+    }
+}
+
+#[test]
+fn test_aovs() {
+    use crate::geometry::sphere::Sphere;
+    use crate::hit::World;
+    use crate::instance::ObjectId;
+    use crate::material::{DiffuseLight, Lambertian, MaterialId};
+    use crate::ray::Ray;
+    use crate::texture::SolidColor;
+
+    let gray = MaterialId::new(Lambertian::new(SolidColor::new(0.5, 0.5, 0.5)), 3);
+    let light = || DiffuseLight::new(SolidColor::new(4.0, 4.0, 4.0));
+    let mut world = World::new();
+    world.push(ObjectId::new(
+        Sphere::new(Vector3::new(0.0, 0.0, -2.0), 0.5, gray),
+        7,
+    ));
+    world.push(Sphere::new(Vector3::new(0.0, 2.0, -2.0), 0.5, light()));
+    let mut lights = World::new();
+    lights.push(Sphere::new(Vector3::new(0.0, 2.0, -2.0), 0.5, light()));
+    let background = Vector3::new(0.2, 0.2, 0.2);
+
+    // first hit on the diffuse sphere, the passes add up to the color
+    let ray = Ray::new(Vector3::zeros(), Vector3::new(0.0, 0.0, -2.0));
+    for _ in 0..16 {
+        let mut sample = AovSample::default();
+        let color = ray.color_with_aovs(&background, &world, &lights, 8, &mut sample);
+        assert!((sample.depth.unwrap() - 1.5).abs() < 1e-4);
+        assert!((sample.normal - Vector3::new(0.0, 0.0, 1.0)).norm() < 1e-4);
+        assert_eq!((sample.object_id, sample.material_id), (7, 3));
+        assert_eq!(sample.albedo, Vector3::new(0.5, 0.5, 0.5));
+        assert_eq!(sample.emission, Vector3::zeros());
+        let sum = sample.emission + sample.direct + sample.indirect;
+        assert!((color - sum).norm() < 1e-4 * color.norm().max(1.0));
+    }
+
+    // looking at the light and into the background, everything is emission
+    for (direction, depth) in [(Vector3::new(0.0, 1.0, -1.0), true), (Vector3::y(), false)] {
+        let mut sample = AovSample::default();
+        let color = Ray::new(Vector3::zeros(), direction).color_with_aovs(
+            &background,
+            &world,
+            &lights,
+            8,
+            &mut sample,
+        );
+        assert_eq!(sample.depth.is_some(), depth);
+        assert_eq!(sample.emission, color);
+        assert_eq!(sample.direct + sample.indirect, Vector3::zeros());
+    }
+
+    let mut pixel = AovPixel::default();
+    pixel.add(&AovSample::default());
+    assert_eq!(pixel.value(Aov::SampleCount, 1.0), [1.0; 3]);
+    assert_eq!(pixel.value(Aov::Depth, 1.0), [0.0; 3]);
+}
@@ -0,0 +1,609 @@
+//! Bidirectional path tracing.
+//!
+//! Every camera sample traces a path from the camera and another one from a point sampled on
+//! the lights, and connects each vertex of the one to each vertex of the other with a shadow
+//! ray. Every connection is a separate strategy to sample the same light path, and the
+//! strategies are weighted by the balance heuristic over the densities of all strategies that
+//! could have produced the path. Light paths connected straight to the lens land on other
+//! pixels and are returned as splats.
+//!
+//! The lights of the scene only provide the geometry to sample, the light leaving a sampled
+//! point is the emission of the world surface found there. Specular materials can not be
+//! connected to and are only crossed by extending the paths, and cameras that are not
+//! [connectable](crate::camera::Camera::connectable) skip the strategies ending on the lens.
+
+use crate::{
+    aov::AovSample,
+    hit::HitRecord,
+    integrator::{Scene, Splat},
+    material::{random_cosine_direction, ScatterRecord},
+    pdf::{Onb, Pdf},
+    ray::{offset_origin, Ray},
+    sampler,
+};
+use nalgebra::Vector3;
+use rand::Rng;
+use std::f32::consts::PI;
+
+enum Kind<'a> {
+    Camera,
+    /// Point sampled on the lights, with the radiance emitted to the side of the normal and
+    /// to the opposite side.
+    Light {
+        front: Vector3<f32>,
+        back: Vector3<f32>,
+    },
+    Surface {
+        hit: HitRecord<'a>,
+        scattering: Scattering<'a>,
+    },
+}
+
+enum Scattering<'a> {
+    Absorb,
+    Diffuse {
+        attenuation: Vector3<f32>,
+        pdf: Pdf<'a>,
+    },
+    Isotropic {
+        attenuation: Vector3<f32>,
+    },
+    Specular {
+        attenuation: Vector3<f32>,
+    },
+}
+
+struct Vertex<'a> {
+    kind: Kind<'a>,
+    p: Vector3<f32>,
+    /// Unit normal of surfaces, zero for the camera and inside media, which take no cosine.
+    n: Vector3<f32>,
+    /// Light or importance carried to the vertex, divided by the pdf of its subpath.
+    beta: Vector3<f32>,
+    /// Area density of sampling the vertex from the previous vertex of its subpath.
+    pdf_fwd: f32,
+    /// Area density of sampling the vertex from the next vertex, the other way round.
+    pdf_rev: f32,
+    /// Scattered by a specular material.
+    delta: bool,
+}
+
+impl<'a> Vertex<'a> {
+    fn camera(p: Vector3<f32>) -> Self {
+        Self {
+            kind: Kind::Camera,
+            p,
+            n: Vector3::zeros(),
+            beta: Vector3::repeat(1.0),
+            pdf_fwd: 0.0,
+            pdf_rev: 0.0,
+            delta: false,
+        }
+    }
+
+    fn connectable(&self) -> bool {
+        match &self.kind {
+            Kind::Camera | Kind::Light { .. } => true,
+            Kind::Surface { scattering, .. } => matches!(
+                scattering,
+                Scattering::Diffuse { .. } | Scattering::Isotropic { .. }
+            ),
+        }
+    }
+
+    /// Fraction of the light scattered, or radiance emitted, in the direction, times the
+    /// cosine at surfaces.
+    fn f(&self, direction: &Vector3<f32>) -> Vector3<f32> {
+        match &self.kind {
+            Kind::Camera => Vector3::zeros(),
+            Kind::Light { front, back } => {
+                let cosine = self.n.dot(&direction.normalize());
+                (if cosine > 0.0 { front } else { back }) * cosine.abs()
+            }
+            Kind::Surface { hit, scattering } => match scattering {
+                Scattering::Diffuse { attenuation, .. } => {
+                    attenuation * hit.m.scattering_pdf(hit, &Ray::new(self.p, *direction))
+                }
+                Scattering::Isotropic { attenuation } => attenuation / (4.0 * PI),
+                Scattering::Absorb | Scattering::Specular { .. } => Vector3::zeros(),
+            },
+        }
+    }
+
+    /// Solid angle density of sampling the direction at the vertex.
+    fn pdf(&self, scene: &Scene, direction: &Vector3<f32>) -> f32 {
+        match &self.kind {
+            Kind::Camera => scene.camera.direction_pdf(self.p, *direction) / scene.image_area,
+            Kind::Light { front, back } => emission_pdf(&self.n, front, back, direction),
+            Kind::Surface { scattering, .. } => match scattering {
+                Scattering::Diffuse { pdf, .. } => pdf.value(*direction),
+                Scattering::Isotropic { .. } => 1.0 / (4.0 * PI),
+                Scattering::Absorb | Scattering::Specular { .. } => 0.0,
+            },
+        }
+    }
+
+    /// Area density of sampling the next vertex from this one.
+    fn pdf_area(&self, scene: &Scene, next: &Vertex) -> f32 {
+        area_density(self.pdf(scene, &(next.p - self.p)), self, next)
+    }
+}
+
+/// Convert a solid angle density at `from` into an area density at `to`.
+fn area_density(pdf: f32, from: &Vertex, to: &Vertex) -> f32 {
+    let d = to.p - from.p;
+    let distance_squared = d.norm_squared();
+    let cosine = if to.n == Vector3::zeros() {
+        1.0
+    } else {
+        to.n.dot(&d).abs() / distance_squared.sqrt()
+    };
+    pdf * cosine / distance_squared
+}
+
+/// Density of the emitted directions, cosine weighted on a random emitting side.
+fn emission_pdf(
+    n: &Vector3<f32>,
+    front: &Vector3<f32>,
+    back: &Vector3<f32>,
+    direction: &Vector3<f32>,
+) -> f32 {
+    let cosine = n.dot(&direction.normalize());
+    let emitted = if cosine > 0.0 { front } else { back };
+    if *emitted == Vector3::zeros() {
+        return 0.0;
+    }
+    let sides = (*front != Vector3::zeros()) as u8 + (*back != Vector3::zeros()) as u8;
+    cosine.abs() / (PI * sides as f32)
+}
+
+/// Radiance emitted at a hit to the side of the normal and to the opposite side.
+fn emission(hit: &HitRecord) -> (Vector3<f32>, Vector3<f32>) {
+    let flipped = HitRecord {
+        n: -hit.n,
+        front_face: !hit.front_face,
+        ..*hit
+    };
+    (hit.m.emitted(hit), hit.m.emitted(&flipped))
+}
+
+/// Point sampled on the lights, `None` when no world surface emits light there.
+fn sample_light<'a>(scene: &Scene<'a>, time: f32) -> Option<Vertex<'a>> {
+    let sample = scene.lights.sample_surface()?;
+    if sample.pdf.is_nan() || sample.pdf <= 0.0 {
+        return None;
+    }
+
+    // look at the point from both sides, from close enough to not see anything else
+    let offset = 1.0e-3 * (1.0 + sample.p.amax());
+    let emitted = |side: f32| {
+        let ray = Ray::with_time(sample.p + side * offset * sample.n, -side * sample.n, time);
+        scene
+            .world
+            .hit(&ray, 0.0, 2.0 * offset)
+            .map_or(Vector3::zeros(), |hit| hit.m.emitted(&hit))
+    };
+    let (front, back) = (emitted(1.0), emitted(-1.0));
+    if front == Vector3::zeros() && back == Vector3::zeros() {
+        return None;
+    }
+
+    Some(Vertex {
+        kind: Kind::Light { front, back },
+        p: sample.p,
+        n: sample.n,
+        beta: Vector3::repeat(1.0 / sample.pdf),
+        pdf_fwd: sample.pdf,
+        pdf_rev: 0.0,
+        delta: false,
+    })
+}
+
+/// Direction emitted from a light vertex, see [`emission_pdf`].
+fn sample_emission(light: &Vertex) -> Vector3<f32> {
+    let Kind::Light { front, back } = &light.kind else {
+        unreachable!("emission from a vertex that is not on a light");
+    };
+    let side = if *back == Vector3::zeros()
+        || (*front != Vector3::zeros() && sampler::rng().gen::<bool>())
+    {
+        1.0
+    } else {
+        -1.0
+    };
+    Onb::build_from_w(side * light.n).local(&random_cosine_direction())
+}
+
+/// Ray leaving a light vertex in a direction sampled from its emission, with the light it
+/// carries divided by the density of its origin and direction, and the solid angle density of
+/// the direction.
+fn emit(scene: &Scene, light: &Vertex, time: f32) -> (Ray, Vector3<f32>, f32) {
+    let direction = sample_emission(light);
+    let pdf = light.pdf(scene, &direction);
+    let beta = light.beta.component_mul(&light.f(&direction)) / pdf;
+    let origin = offset_origin(light.p, light.n, &direction);
+    (Ray::with_time(origin, direction, time), beta, pdf)
+}
+
+/// Ray leaving a point sampled on the lights, with the light it carries divided by the density
+/// of sampling it, `None` when no light is found. The photons of [`crate::photon`] start
+/// along these rays.
+pub(crate) fn sample_emitted_ray(scene: &Scene, time: f32) -> Option<(Ray, Vector3<f32>)> {
+    let light = sample_light(scene, time)?;
+    let (ray, beta, pdf) = emit(scene, &light, time);
+    (pdf > 0.0).then_some((ray, beta))
+}
+
+/// Extend the path along the ray, which was sampled with the given solid angle density, until
+/// it has `max_vertices` vertices or stops scattering. Returns the throughput of a ray that
+/// leaves the scene.
+fn random_walk<'a>(
+    scene: &Scene<'a>,
+    mut ray: Ray,
+    mut beta: Vector3<f32>,
+    mut pdf: f32,
+    max_vertices: usize,
+    path: &mut Vec<Vertex<'a>>,
+) -> Option<Vector3<f32>> {
+    while path.len() < max_vertices {
+        let Some(hit) = scene.world.hit(&ray, 0.001, f32::MAX) else {
+            return Some(beta);
+        };
+
+        // next direction, with the scattered fraction of the light and the densities of
+        // sampling it and of sampling the way back
+        let (scattering, next) = match hit.m.scatter(&ray, &hit) {
+            None => (Scattering::Absorb, None),
+            Some(ScatterRecord::Scatter { attenuation, pdf }) => {
+                let direction = pdf.generate();
+                let value = attenuation * hit.m.scattering_pdf(&hit, &Ray::new(hit.p, direction));
+                let next = (direction, value, pdf.value(direction), pdf.value(-ray.dir));
+                (Scattering::Diffuse { attenuation, pdf }, Some(next))
+            }
+            Some(ScatterRecord::Isotropic {
+                attenuation,
+                scattered_ray,
+            }) => {
+                let value = attenuation / (4.0 * PI);
+                let next = (scattered_ray.dir, value, 1.0 / (4.0 * PI), 1.0 / (4.0 * PI));
+                (Scattering::Isotropic { attenuation }, Some(next))
+            }
+            Some(ScatterRecord::Specular {
+                attenuation,
+                specular_ray,
+            }) => {
+                // the attenuation is the whole weight, with no density in either direction
+                let next = (specular_ray.dir, attenuation, 0.0, 0.0);
+                (Scattering::Specular { attenuation }, Some(next))
+            }
+        };
+
+        let delta = matches!(scattering, Scattering::Specular { .. });
+        let n = match scattering {
+            Scattering::Isotropic { .. } => Vector3::zeros(),
+            _ => hit.n,
+        };
+        let mut vertex = Vertex {
+            kind: Kind::Surface { hit, scattering },
+            p: hit.p,
+            n,
+            beta,
+            pdf_fwd: 0.0,
+            pdf_rev: 0.0,
+            delta,
+        };
+        vertex.pdf_fwd = area_density(pdf, path.last().unwrap(), &vertex);
+        path.push(vertex);
+
+        let Some((direction, value, pdf_fwd, pdf_rev)) = next else {
+            break;
+        };
+        if delta {
+            beta.component_mul_assign(&value);
+        } else if pdf_fwd > 0.0 {
+            beta.component_mul_assign(&(value / pdf_fwd));
+        } else {
+            break;
+        }
+        if beta == Vector3::zeros() {
+            break;
+        }
+
+        let n = path.len();
+        path[n - 2].pdf_rev = area_density(pdf_rev, &path[n - 1], &path[n - 2]);
+        ray = Ray::with_time(
+            offset_origin(hit.p, path[n - 1].n, &direction),
+            direction,
+            ray.time,
+        );
+        pdf = pdf_fwd;
+    }
+
+    None
+}
+
+/// Whether nothing blocks the segment between two vertices, smoke blocks it at random.
+fn visible(scene: &Scene, a: &Vertex, b: &Vertex, time: f32) -> bool {
+    let from = offset_origin(a.p, a.n, &(b.p - a.p));
+    let d = offset_origin(b.p, b.n, &(a.p - b.p)) - from;
+    let distance = d.norm();
+    let ray = Ray::with_time(from, d / distance, time);
+    scene.world.hit(&ray, 0.001, distance - 0.001).is_none()
+}
+
+/// Balance heuristic weight of the strategy connecting `s` light and `t` camera vertices.
+///
+/// The densities of the other strategies follow from the ratios of the forward and reverse
+/// densities of the vertices, with the ones next to the connection updated for it. `sampled`
+/// replaces the first camera vertex for `t = 1`, and the first light vertex for `s = 1`.
+fn mis_weight(
+    scene: &Scene,
+    camera: &[Vertex],
+    light: &[Vertex],
+    sampled: Option<&Vertex>,
+    s: usize,
+    t: usize,
+) -> f32 {
+    if s + t == 2 {
+        return 1.0;
+    }
+
+    let light_vertex = |i: usize| match sampled {
+        Some(vertex) if s == 1 && t > 1 => vertex,
+        _ => &light[i],
+    };
+    let pt = match sampled {
+        Some(vertex) if t == 1 => vertex,
+        _ => &camera[t - 1],
+    };
+    let qs = (s > 0).then(|| light_vertex(s - 1));
+    let pt_minus = (t > 1).then(|| &camera[t - 2]);
+    let qs_minus = (s > 1).then(|| &light[s - 2]);
+
+    // reverse densities of the connected vertices and of the ones before them
+    let pt_rev = match qs {
+        Some(qs) => qs.pdf_area(scene, pt),
+        None => {
+            // a path that hit an emitter, which other strategies can only start from when the
+            // lights can sample it
+            let pt_minus = pt_minus.unwrap();
+            let pdf = scene.lights.surface_pdf(pt_minus.p, pt.p - pt_minus.p);
+            if pdf.is_nan() || pdf <= 0.0 {
+                return 1.0;
+            }
+            pdf
+        }
+    };
+    let pt_minus_rev = pt_minus.map(|pt_minus| match (qs, &pt.kind) {
+        (Some(_), _) => pt.pdf_area(scene, pt_minus),
+        (None, Kind::Surface { hit, .. }) => {
+            let (front, back) = emission(hit);
+            let pdf = emission_pdf(&hit.n, &front, &back, &(pt_minus.p - pt.p));
+            area_density(pdf, pt, pt_minus)
+        }
+        (None, _) => 0.0,
+    });
+    let qs_rev = qs.map(|qs| pt.pdf_area(scene, qs));
+    let qs_minus_rev = qs_minus.map(|qs_minus| qs.unwrap().pdf_area(scene, qs_minus));
+
+    let remap = |pdf: f32| if pdf != 0.0 { pdf } else { 1.0 };
+    let mut sum = 0.0;
+
+    // strategies with fewer camera vertices, down to a light path reaching the lens
+    let camera_rev = |i: usize| match t - i {
+        1 => pt_rev,
+        2 => pt_minus_rev.unwrap(),
+        _ => camera[i].pdf_rev,
+    };
+    let camera_delta = |i: usize| i + 1 != t && camera[i].delta;
+    let mut ratio = 1.0;
+    for i in (1..t).rev() {
+        ratio *= remap(camera_rev(i)) / remap(camera[i].pdf_fwd);
+        if camera_delta(i) || camera_delta(i - 1) {
+            continue;
+        }
+        if i == 1 && !scene.camera.connectable() {
+            break;
+        }
+        sum += ratio;
+    }
+
+    // strategies with fewer light vertices, down to a camera path hitting the light
+    let light_rev = |i: usize| match s - i {
+        1 => qs_rev.unwrap(),
+        2 => qs_minus_rev.unwrap(),
+        _ => light[i].pdf_rev,
+    };
+    let light_delta = |i: usize| i + 1 != s && light[i].delta;
+    let mut ratio = 1.0;
+    for i in (0..s).rev() {
+        ratio *= remap(light_rev(i)) / remap(light_vertex(i).pdf_fwd);
+        if light_delta(i) || (i > 0 && light_delta(i - 1)) {
+            continue;
+        }
+        sum += ratio;
+    }
+
+    1.0 / (1.0 + sum)
+}
+
+/// Weighted light of the strategy connecting `s` light and `t >= 2` camera vertices.
+fn connect(
+    scene: &Scene,
+    camera: &[Vertex],
+    light: &[Vertex],
+    s: usize,
+    t: usize,
+    time: f32,
+) -> Vector3<f32> {
+    let pt = &camera[t - 1];
+
+    if s == 0 {
+        // the camera path hit an emitter, specular surfaces only pass on the light they reflect
+        let Kind::Surface { hit, .. } = &pt.kind else {
+            return Vector3::zeros();
+        };
+        let emitted = hit.m.emitted(hit);
+        if pt.delta || emitted == Vector3::zeros() {
+            return Vector3::zeros();
+        }
+        return pt.beta.component_mul(&emitted) * mis_weight(scene, camera, light, None, s, t);
+    }
+
+    if !pt.connectable() {
+        return Vector3::zeros();
+    }
+    // a fresh point on the lights for every camera vertex
+    let sampled = if s == 1 {
+        match sample_light(scene, time) {
+            Some(vertex) => Some(vertex),
+            None => return Vector3::zeros(),
+        }
+    } else {
+        None
+    };
+    let qs = sampled.as_ref().unwrap_or_else(|| &light[s - 1]);
+    if !qs.connectable() {
+        return Vector3::zeros();
+    }
+
+    let d = qs.p - pt.p;
+    let value = qs
+        .beta
+        .component_mul(&qs.f(&-d))
+        .component_mul(&pt.f(&d))
+        .component_mul(&pt.beta)
+        / d.norm_squared();
+    if value == Vector3::zeros() || !visible(scene, pt, qs, time) {
+        return Vector3::zeros();
+    }
+
+    value * mis_weight(scene, camera, light, sampled.as_ref(), s, t)
+}
+
+/// Weighted light of the strategy connecting `s` light vertices to a point on the lens.
+fn connect_camera(
+    scene: &Scene,
+    camera: &[Vertex],
+    light: &[Vertex],
+    s: usize,
+    time: f32,
+) -> Option<Splat> {
+    let qs = &light[s - 1];
+    if !qs.connectable() {
+        return None;
+    }
+    let (ray, u, v) = scene.camera.connect(qs.p, time)?;
+    let lens = Vertex::camera(ray.ori);
+
+    // the importance of the camera is the density of its rays, so every camera sample weighs 1
+    let d = qs.p - lens.p;
+    let value = qs.beta.component_mul(&qs.f(&-d)) * lens.pdf(scene, &d) / d.norm_squared();
+    if value == Vector3::zeros() || !visible(scene, &lens, qs, time) {
+        return None;
+    }
+
+    let color = value * mis_weight(scene, camera, light, Some(&lens), s, 1);
+    Some(Splat { u, v, color })
+}
+
+/// Color along a camera ray by bidirectional path tracing, with at most `max_depth` hits
+/// along every path. Light paths connected to the lens are added to the splats.
+///
+/// The passes split only the light of the paths through the pixel, not the splats.
+pub fn color(
+    scene: &Scene,
+    ray: &Ray,
+    splats: &mut Vec<Splat>,
+    aovs: Option<&mut AovSample>,
+) -> Vector3<f32> {
+    let max_depth = scene.max_depth as usize;
+
+    let mut camera = vec![Vertex::camera(ray.ori)];
+    let pdf = camera[0].pdf(scene, &ray.dir);
+    let escaped = random_walk(
+        scene,
+        *ray,
+        Vector3::repeat(1.0),
+        pdf,
+        max_depth + 1,
+        &mut camera,
+    );
+
+    let mut light = Vec::new();
+    if let Some(vertex) = sample_light(scene, ray.time) {
+        let (emitted, beta, pdf) = emit(scene, &vertex, ray.time);
+        light.push(vertex);
+        if pdf > 0.0 {
+            random_walk(scene, emitted, beta, pdf, max_depth, &mut light);
+        }
+    }
+
+    // light by the number of bounces before it reached the camera path, for the passes
+    let mut color = Vector3::zeros();
+    let mut emission = Vector3::zeros();
+    let mut direct = Vector3::zeros();
+    let mut add = |bounces: usize, light: Vector3<f32>| {
+        color += light;
+        match bounces {
+            0 => emission += light,
+            1 => direct += light,
+            _ => (),
+        }
+    };
+
+    if let Some(beta) = escaped {
+        add(camera.len() - 1, beta.component_mul(scene.background));
+    }
+    for t in 2..=camera.len() {
+        for s in 0..=light.len().min(max_depth + 1 - t) {
+            add(s + t - 2, connect(scene, &camera, &light, s, t, ray.time));
+        }
+    }
+    // the lights seen directly are left to the camera paths, which weigh them by 1
+    for s in 2..=light.len().min(max_depth) {
+        splats.extend(connect_camera(scene, &camera, &light, s, ray.time));
+    }
+
+    if let Some(aovs) = aovs {
+        if let Some(Kind::Surface { hit, scattering }) = camera.get(1).map(|v| &v.kind) {
+            aovs.record_hit(hit, &ray.dir);
+            aovs.albedo = match scattering {
+                Scattering::Absorb => Vector3::zeros(),
+                Scattering::Diffuse { attenuation, .. }
+                | Scattering::Isotropic { attenuation }
+                | Scattering::Specular { attenuation } => *attenuation,
+            };
+        }
+        aovs.emission = emission;
+        aovs.direct = direct;
+        aovs.indirect = color - emission - direct;
+    }
+
+    color
+}
+
+#[test]
+fn test_bidirectional() {
+    use crate::integrator::{test_render_mean, Integrator};
+    use crate::scene::scene_description;
+
+    let (camera, ..) = scene_description(7).build().unwrap();
+
+    // the lens connections land on the pixels the camera rays of the same point go through
+    let p = Vector3::new(300.0, 200.0, 300.0);
+    let (ray, u, v) = camera.connect(p, 0.0).unwrap();
+    let camera_ray = camera.ray(u, v);
+    assert!((camera_ray.dir.normalize() - ray.dir.normalize()).norm() < 1e-4);
+    assert!(camera.direction_pdf(ray.ori, ray.dir) > 0.0);
+
+    // same mean as the path tracer with the light of the splats
+    let path = test_render_mean(Integrator::Path(Default::default()), 1 << 20, 32);
+    let bidirectional = test_render_mean(Integrator::Bidirectional, 2 << 20, 32);
+    assert!(
+        (bidirectional - path).abs() < 0.03 * path,
+        "{} != {}",
+        bidirectional,
+        path
+    );
+}
@@ -1,7 +1,133 @@
 use crate::bounding_box::AABB;
 use crate::hit::*;
 use crate::ray::Ray;
-use std::cmp::Ordering;
+use nalgebra::Vector3;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt,
+    time::{Duration, Instant},
+};
+
+/// How the objects of a node are divided over its two children.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SplitMethod {
+    /// Split at the median object after sorting on the largest axis of the node bounds.
+    #[default]
+    Median,
+    /// Binned surface area heuristic.
+    Sah,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BvhSettings {
+    pub split: SplitMethod,
+    /// Nodes with at most this many objects may become leaves. The median split always splits
+    /// larger nodes; the SAH split also splits smaller nodes when that is cheaper.
+    pub max_leaf_size: usize,
+    /// Number of bins per axis for the SAH split.
+    pub bins: usize,
+    /// Relative cost of traversing a node, for the SAH.
+    pub traversal_cost: f32,
+    /// Relative cost of intersecting an object, for the SAH.
+    pub intersection_cost: f32,
+    /// A refit tree is rebuilt when its SAH cost exceeds the cost after the last full build by
+    /// this factor.
+    pub rebuild_threshold: f32,
+}
+
+impl Default for BvhSettings {
+    fn default() -> Self {
+        Self {
+            split: SplitMethod::Median,
+            max_leaf_size: 1,
+            bins: 16,
+            traversal_cost: 1.0,
+            intersection_cost: 1.0,
+            rebuild_threshold: 1.5,
+        }
+    }
+}
+
+impl BvhSettings {
+    /// SAH split with leaves of up to four objects.
+    pub fn sah() -> Self {
+        Self {
+            split: SplitMethod::Sah,
+            max_leaf_size: 4,
+            ..Default::default()
+        }
+    }
+}
+
+/// Duration of the build phases.
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+pub struct BvhBuildTimes {
+    /// Evaluating the bounds of all objects.
+    pub bounds: Duration,
+    /// Splitting the top levels of the tree.
+    pub top_levels: Duration,
+    /// Building the subtrees in parallel.
+    pub subtrees: Duration,
+    /// Merging the subtrees and reordering the objects.
+    pub reorder: Duration,
+    /// Updating the node bounds of a refit tree, which skips the other phases but the bounds.
+    pub refit: Duration,
+}
+
+impl BvhBuildTimes {
+    pub fn total(&self) -> Duration {
+        self.bounds + self.top_levels + self.subtrees + self.reorder + self.refit
+    }
+}
+
+impl fmt::Display for BvhBuildTimes {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let ms = |d: Duration| d.as_secs_f32() * 1.0e3;
+        write!(
+            f,
+            "built in {:.2} [ms] (bounds {:.2}, top levels {:.2}, subtrees {:.2}, reorder {:.2}, refit {:.2})",
+            ms(self.total()),
+            ms(self.bounds),
+            ms(self.top_levels),
+            ms(self.subtrees),
+            ms(self.reorder),
+            ms(self.refit)
+        )
+    }
+}
+
+/// Statistics of a built tree, to compare the split methods.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct BvhStats {
+    pub nodes: usize,
+    pub leaves: usize,
+    pub depth: usize,
+    pub average_leaf_size: f32,
+    /// Expected cost of a ray traversal, relative to the root bounds.
+    pub sah_cost: f32,
+    /// Number of refits since the last full build.
+    pub refits: usize,
+    pub build_times: BvhBuildTimes,
+}
+
+impl fmt::Display for BvhStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} nodes, {} leaves, depth {}, {:.2} objects per leaf, SAH cost {:.2}, {} refits, {}",
+            self.nodes,
+            self.leaves,
+            self.depth,
+            self.average_leaf_size,
+            self.sah_cost,
+            self.refits,
+            self.build_times
+        )
+    }
+}
 
 #[derive(Debug)]
 struct Node {
@@ -9,134 +135,521 @@ struct Node {
     child_index: usize,
     min_index: usize,
     max_index: usize,
+    /// Axis along which the objects of the children are ordered, the left child holds the
+    /// objects with the lower coordinates. After a refit this only decides the traversal order.
+    axis: usize,
 }
 
 pub struct Bvh {
     nodes: Vec<Node>,
     objects: Vec<Box<dyn Hittable>>,
+    settings: BvhSettings,
+    /// Number of levels of the tree, to size the traversal stack.
+    depth: usize,
+    /// Index of every object in the list the tree was built from.
+    order: Vec<usize>,
+    /// SAH cost after the last full build, to decide when a refit tree is rebuilt.
+    built_cost: f32,
+    refits: usize,
+    build_times: BvhBuildTimes,
 }
 
-impl Node {
-    fn aabb_compare(axis: usize) -> impl FnMut(&Box<dyn Hittable>, &Box<dyn Hittable>) -> Ordering {
-        move |a, b| {
-            if let (Some(a), Some(b)) = (a.bounding_box(), b.bounding_box()) {
-                let ac = a.sort_value_axis(axis);
-                let bc = b.sort_value_axis(axis);
-                ac.partial_cmp(&bc).unwrap()
-            } else {
-                panic!["no bounding box in bvh node"]
-            }
-        }
-    }
+/// Nodes with more objects are split before the subtrees below them are built in parallel.
+const SUBTREE_SIZE: usize = 4096;
 
-    fn new(objects: &mut Vec<Box<dyn Hittable>>, min_index: usize, max_index: usize) -> Self {
-        // Find the total bounding box of all hittable objects in the current node
-        let aabb = objects[min_index..max_index]
-            .iter()
-            .fold(AABB::default(), |a, b| {
-                a.extend_box(&b.bounding_box().unwrap())
-            });
+/// Traversal stack size for trees that fit on the stack, deeper trees use a heap allocation.
+const STACK_SIZE: usize = 64;
 
-        // Determine the largest axis
-        let axis = aabb.max_axis();
+/// Bounds of an object, evaluated once before building the tree.
+struct Primitive {
+    aabb: AABB,
+    centroid: Vector3<f32>,
+}
 
-        objects[min_index..max_index].sort_by(Self::aabb_compare(axis));
+impl Node {
+    fn new(primitives: &[Primitive], order: &[usize], min_index: usize, max_index: usize) -> Self {
+        // Find the total bounding box of all hittable objects in the current node
+        let aabb = order[min_index..max_index]
+            .iter()
+            .fold(AABB::default(), |a, &i| a.extend_box(&primitives[i].aabb));
 
         Node {
             aabb,
             child_index: 0,
             min_index,
             max_index,
+            axis: 0,
         }
     }
 }
 
+#[derive(Copy, Clone, Default)]
+struct Bin {
+    aabb: AABB,
+    count: usize,
+}
+
 impl Bvh {
     pub fn new(hittable: Vec<Box<dyn Hittable>>) -> Bvh {
-        let objects = hittable;
-        let count = objects.len();
+        Self::with_settings(hittable, BvhSettings::default())
+    }
+
+    /// Build the tree over the objects. The top levels are split serially, the subtrees below
+    /// them are built in parallel and merged into a single flat node list.
+    pub fn with_settings(hittable: Vec<Box<dyn Hittable>>, settings: BvhSettings) -> Bvh {
+        let start = Instant::now();
+        let primitives: Vec<Primitive> = hittable
+            .par_iter()
+            .map(|h| {
+                let aabb = h.bounding_box().expect("no bounding box in bvh node");
+                Primitive {
+                    aabb,
+                    centroid: aabb.centroid(),
+                }
+            })
+            .collect();
+        let bounds_time = start.elapsed();
+
+        // The tree is built on a permutation of the object indices, the objects are reordered
+        // to match afterwards
+        let count = primitives.len();
+        let mut order: Vec<usize> = (0..count).collect();
+
+        // Generate the root node
+        let mut nodes = vec![Node::new(&primitives, &order, 0, count)];
+        let mut node_depth = vec![1];
+
+        // Split the top levels of the tree, until the nodes are small enough to be built as
+        // independent subtrees
+        let start = Instant::now();
+        let mut subtrees = vec![];
+        let mut node_idx = 0;
+        while node_idx < nodes.len() {
+            let node = &nodes[node_idx];
+            if node.max_index - node.min_index > SUBTREE_SIZE {
+                Self::split_node(&mut nodes, &primitives, &mut order, node_idx, &settings);
+                node_depth.resize(nodes.len(), node_depth[node_idx] + 1);
+            } else {
+                subtrees.push(node_idx);
+            }
+            node_idx += 1;
+        }
+        let top_levels_time = start.elapsed();
+
+        // Build the subtrees in parallel, each on its own range of the object order
+        let start = Instant::now();
+        subtrees.sort_by_key(|&i| nodes[i].min_index);
+        let mut ranges = vec![];
+        let mut rest = order.as_mut_slice();
+        let mut offset = 0;
+        for &i in subtrees.iter() {
+            let (min_index, max_index) = (nodes[i].min_index, nodes[i].max_index);
+            let (_, tail) = rest.split_at_mut(min_index - offset);
+            let (range, tail) = tail.split_at_mut(max_index - min_index);
+            ranges.push((i, min_index, range));
+            rest = tail;
+            offset = max_index;
+        }
+
+        let built: Vec<(usize, usize, Vec<Node>, Vec<usize>)> = ranges
+            .into_par_iter()
+            .map(|(root, offset, range)| {
+                let (subtree, depth) = Self::build_subtree(&primitives, range, &settings);
+                (root, offset, subtree, depth)
+            })
+            .collect();
+        let subtrees_time = start.elapsed();
+
+        // Splice the subtrees into the flat node list, the subtree root replaces the node it
+        // was built for and the other nodes are appended
+        let start = Instant::now();
+        for (root, offset, subtree, depth) in built {
+            let base = nodes.len();
+            for (k, mut node) in subtree.into_iter().enumerate() {
+                node.min_index += offset;
+                node.max_index += offset;
+                if node.child_index != 0 {
+                    node.child_index += base - 1;
+                }
+
+                if k == 0 {
+                    nodes[root] = node;
+                } else {
+                    nodes.push(node);
+                    node_depth.push(node_depth[root] + depth[k] - 1);
+                }
+            }
+        }
+
+        let mut objects: Vec<Option<Box<dyn Hittable>>> = hittable.into_iter().map(Some).collect();
+        let objects = order.iter().map(|&i| objects[i].take().unwrap()).collect();
+        let reorder_time = start.elapsed();
 
         let mut bvh = Bvh {
-            objects: objects,
-            nodes: vec![],
+            nodes,
+            objects,
+            settings,
+            depth: node_depth.into_iter().max().unwrap(),
+            order,
+            built_cost: 0.0,
+            refits: 0,
+            build_times: BvhBuildTimes {
+                bounds: bounds_time,
+                top_levels: top_levels_time,
+                subtrees: subtrees_time,
+                reorder: reorder_time,
+                refit: Duration::ZERO,
+            },
         };
+        bvh.built_cost = bvh.stats().sah_cost;
+        bvh
+    }
 
-        // Generate the root node
-        bvh.nodes.push(Node::new(&mut bvh.objects, 0, count));
+    /// Replace the objects, given in the order the tree was built from, and recompute the node
+    /// bounds bottom up while keeping the topology of the tree. This is much cheaper than a
+    /// rebuild when objects only move a little, e.g. between the frames of an animation.
+    ///
+    /// The quality of the tree degrades as the objects move away from where it was built, when
+    /// its SAH cost exceeds the cost after the last full build by the `rebuild_threshold` of the
+    /// settings, the tree is rebuilt instead, as it is when the number of objects changed.
+    /// Returns whether the tree was rebuilt.
+    pub fn refit(&mut self, objects: Vec<Box<dyn Hittable>>) -> bool {
+        if objects.len() != self.objects.len() {
+            *self = Bvh::with_settings(objects, self.settings);
+            return true;
+        }
+
+        let start = Instant::now();
+        let mut objects: Vec<Option<Box<dyn Hittable>>> = objects.into_iter().map(Some).collect();
+        self.objects = self
+            .order
+            .iter()
+            .map(|&i| objects[i].take().unwrap())
+            .collect();
+        let bounds: Vec<AABB> = self
+            .objects
+            .par_iter()
+            .map(|h| h.bounding_box().expect("no bounding box in bvh node"))
+            .collect();
+        let bounds_time = start.elapsed();
+
+        // children are always stored after their parent, so a reverse pass updates the
+        // children of every node before the node itself
+        let start = Instant::now();
+        for i in (0..self.nodes.len()).rev() {
+            let node = &self.nodes[i];
+            let aabb = if node.child_index == 0 {
+                bounds[node.min_index..node.max_index]
+                    .iter()
+                    .fold(AABB::default(), |a, b| a.extend_box(b))
+            } else {
+                let (left, right) = (
+                    &self.nodes[node.child_index],
+                    &self.nodes[node.child_index + 1],
+                );
+                left.aabb.extend_box(&right.aabb)
+            };
+            self.nodes[i].aabb = aabb;
+        }
+        let refit_time = start.elapsed();
+
+        if self.stats().sah_cost > self.settings.rebuild_threshold * self.built_cost {
+            let mut objects: Vec<Option<Box<dyn Hittable>>> =
+                (0..self.objects.len()).map(|_| None).collect();
+            for (object, &i) in self.objects.drain(..).zip(&self.order) {
+                objects[i] = Some(object);
+            }
+            let objects = objects.into_iter().map(Option::unwrap).collect();
+            *self = Bvh::with_settings(objects, self.settings);
+            return true;
+        }
+
+        self.refits += 1;
+        self.build_times = BvhBuildTimes {
+            bounds: bounds_time,
+            refit: refit_time,
+            ..Default::default()
+        };
+        false
+    }
+
+    /// Build the tree over a range of the objects breadth first, the node object indices are
+    /// relative to the range. Returns the nodes and their depth.
+    fn build_subtree(
+        primitives: &[Primitive],
+        order: &mut [usize],
+        settings: &BvhSettings,
+    ) -> (Vec<Node>, Vec<usize>) {
+        let mut nodes = vec![Node::new(primitives, order, 0, order.len())];
+        let mut node_depth = vec![1];
 
         // Recursively build the node tree, by constantly splitting the nodes
         let mut node_idx = 0;
-        while node_idx < bvh.nodes.len() {
-            bvh.split_node(node_idx);
+        while node_idx < nodes.len() {
+            Self::split_node(&mut nodes, primitives, order, node_idx, settings);
+            node_depth.resize(nodes.len(), node_depth[node_idx] + 1);
             node_idx += 1;
         }
 
-        bvh
+        (nodes, node_depth)
     }
 
-    fn split_node(&mut self, node_index: usize) {
-        let min_index = self.nodes[node_index].min_index;
-        let max_index = self.nodes[node_index].max_index;
+    fn split_node(
+        nodes: &mut Vec<Node>,
+        primitives: &[Primitive],
+        order: &mut [usize],
+        node_index: usize,
+        settings: &BvhSettings,
+    ) {
+        let min_index = nodes[node_index].min_index;
+        let max_index = nodes[node_index].max_index;
+        let count = max_index - min_index;
 
-        if (max_index - min_index) < 2 {
+        if count <= settings.max_leaf_size.max(1) && settings.split == SplitMethod::Median {
+            return;
+        }
+        if count < 2 {
             return;
         }
 
-        let mid_index = min_index + (max_index - min_index) / 2;
+        let objects = &mut order[min_index..max_index];
+        let split = match settings.split {
+            SplitMethod::Median => None,
+            SplitMethod::Sah => match Self::sah_split(primitives, objects, settings) {
+                // SAH found no split cheaper than a leaf
+                None if count <= settings.max_leaf_size => return,
+                split => split,
+            },
+        };
+
+        let (mid_index, axis) = match split {
+            Some((mid, axis)) => (min_index + mid, axis),
+            None => {
+                // Determine the largest axis and split at the median
+                let axis = nodes[node_index].aabb.max_axis();
+                objects.sort_by(|&a, &b| {
+                    let ac = primitives[a].aabb.sort_value_axis(axis);
+                    let bc = primitives[b].aabb.sort_value_axis(axis);
+                    ac.partial_cmp(&bc).unwrap()
+                });
+                (min_index + count / 2, axis)
+            }
+        };
 
         // Calculate the child inxdex of the current node
         // This is the index of the left node; the right node is the next index
-        let child_index = self.nodes.len();
+        let child_index = nodes.len();
 
         // Build and push the left side node
-        self.nodes
-            .push(Node::new(&mut self.objects, min_index, mid_index));
+        nodes.push(Node::new(primitives, order, min_index, mid_index));
 
         // Build and push the right side node
-        self.nodes
-            .push(Node::new(&mut self.objects, mid_index, max_index));
+        nodes.push(Node::new(primitives, order, mid_index, max_index));
 
         // Set the current node child index
-        self.nodes[node_index].child_index = child_index;
+        nodes[node_index].child_index = child_index;
+        nodes[node_index].axis = axis;
     }
-}
 
-impl Hittable for Bvh {
-    fn hit(&self, r: &Ray, t_min: f32, mut t_max: f32) -> Option<HitRecord> {
-        // let mut hitlist = vec![];
-        let mut hit: Option<HitRecord> = None;
+    /// Find the cheapest split plane over the bins of the centroid bounds and partition the
+    /// objects on it, returns the number of objects on the left side and the split axis.
+    ///
+    /// Returns `None` when no split is cheaper than a leaf or all centroids coincide.
+    fn sah_split(
+        primitives: &[Primitive],
+        objects: &mut [usize],
+        settings: &BvhSettings,
+    ) -> Option<(usize, usize)> {
+        let bins = settings.bins.max(2);
+
+        let (centroid_min, centroid_max) = objects.iter().fold(
+            (
+                Vector3::repeat(f32::INFINITY),
+                Vector3::repeat(f32::NEG_INFINITY),
+            ),
+            |(min, max), &i| {
+                let c = primitives[i].centroid;
+                (min.inf(&c), max.sup(&c))
+            },
+        );
+        let extent = centroid_max - centroid_min;
+
+        let node_area = objects
+            .iter()
+            .fold(AABB::default(), |a, &i| a.extend_box(&primitives[i].aabb))
+            .surface_area();
+
+        let bin_index = |i: usize, axis: usize| {
+            let offset = (primitives[i].centroid[axis] - centroid_min[axis]) / extent[axis];
+            ((offset * bins as f32) as usize).min(bins - 1)
+        };
+
+        let mut best: Option<(f32, usize, usize)> = None;
+        for axis in 0..3 {
+            if extent[axis] <= 0.0 {
+                continue;
+            }
+
+            let mut bin = vec![Bin::default(); bins];
+            for &i in objects.iter() {
+                let b = &mut bin[bin_index(i, axis)];
+                b.aabb = b.aabb.extend_box(&primitives[i].aabb);
+                b.count += 1;
+            }
+
+            // sweep from the right to get the area and count right of every plane
+            let mut right = vec![(0.0, 0); bins];
+            let mut acc = Bin::default();
+            for b in (1..bins).rev() {
+                acc.aabb = acc.aabb.extend_box(&bin[b].aabb);
+                acc.count += bin[b].count;
+                right[b] = (acc.aabb.surface_area(), acc.count);
+            }
+
+            let mut acc = Bin::default();
+            for b in 1..bins {
+                acc.aabb = acc.aabb.extend_box(&bin[b - 1].aabb);
+                acc.count += bin[b - 1].count;
+
+                let (right_area, right_count) = right[b];
+                if acc.count == 0 || right_count == 0 {
+                    continue;
+                }
+
+                let cost = settings.traversal_cost
+                    + settings.intersection_cost
+                        * (acc.aabb.surface_area() * acc.count as f32
+                            + right_area * right_count as f32)
+                        / node_area;
+
+                if best.is_none_or(|(best_cost, _, _)| cost < best_cost) {
+                    best = Some((cost, axis, b));
+                }
+            }
+        }
+
+        let (cost, axis, split) = best?;
+        let leaf_cost = settings.intersection_cost * objects.len() as f32;
+        if cost >= leaf_cost && objects.len() <= settings.max_leaf_size {
+            return None;
+        }
 
-        // hitlist.push(0);
+        // partition the objects left and right of the split plane
+        let mut mid = 0;
+        for k in 0..objects.len() {
+            if bin_index(objects[k], axis) < split {
+                objects.swap(k, mid);
+                mid += 1;
+            }
+        }
+
+        Some((mid, axis))
+    }
+
+    pub fn settings(&self) -> &BvhSettings {
+        &self.settings
+    }
+
+    pub fn stats(&self) -> BvhStats {
+        let root_area = self.nodes[0].aabb.surface_area();
+
+        let mut stats = BvhStats {
+            nodes: self.nodes.len(),
+            leaves: 0,
+            depth: 0,
+            average_leaf_size: 0.0,
+            sah_cost: 0.0,
+            refits: self.refits,
+            build_times: self.build_times,
+        };
+
+        let mut stack = vec![(0, 1)];
+        while let Some((index, depth)) = stack.pop() {
+            let node = &self.nodes[index];
+            let area = if root_area > 0.0 {
+                node.aabb.surface_area() / root_area
+            } else {
+                1.0
+            };
+
+            stats.depth = stats.depth.max(depth);
+            if node.child_index == 0 {
+                let count = node.max_index - node.min_index;
+                stats.leaves += 1;
+                stats.sah_cost += self.settings.intersection_cost * area * count as f32;
+            } else {
+                stats.sah_cost += self.settings.traversal_cost * area;
+                stack.push((node.child_index, depth + 1));
+                stack.push((node.child_index + 1, depth + 1));
+            }
+        }
+
+        stats.average_leaf_size = self.objects.len() as f32 / stats.leaves as f32;
+        stats
+    }
+
+    /// Closest hit along the ray, together with the index of the object that was hit.
+    pub(crate) fn closest_hit(
+        &self,
+        r: &Ray,
+        t_min: f32,
+        t_max: f32,
+    ) -> Option<(usize, HitRecord<'_>)> {
+        // the stack holds at most one pending sibling per level besides the current node
+        if self.depth < STACK_SIZE {
+            self.traverse(&mut [(0, 0.0); STACK_SIZE], r, t_min, t_max)
+        } else {
+            self.traverse(&mut vec![(0, 0.0); self.depth + 1], r, t_min, t_max)
+        }
+    }
 
-        let mut stack: [usize; 64] = [0; 64];
+    /// Depth first traversal, visiting the nearest child first. The stack holds the node
+    /// indices with the ray distance at which their bounds are entered.
+    fn traverse(
+        &self,
+        stack: &mut [(usize, f32)],
+        r: &Ray,
+        t_min: f32,
+        mut t_max: f32,
+    ) -> Option<(usize, HitRecord<'_>)> {
+        let mut hit: Option<(usize, HitRecord)> = None;
+
+        stack[0] = (0, self.nodes[0].aabb.intersect(r, t_min, t_max)?);
         let mut size: usize = 1;
 
         while size > 0 {
             size -= 1;
-            let node = &self.nodes[stack[size]];
-            // let node = &self.nodes[hitlist.pop().unwrap()];
+            let (node_index, entry) = stack[size];
+
+            // skip nodes entered beyond a hit found after they were pushed
+            if entry > t_max {
+                continue;
+            }
+
+            let node = &self.nodes[node_index];
 
             if node.child_index == 0 {
                 // object hit test
                 for i in node.min_index..node.max_index {
-                    match self.objects[i].hit(r, t_min, t_max) {
-                        Some(h) => {
-                            hit = Some(h);
-                            t_max = h.t;
-                        }
-                        None => {}
+                    if let Some(h) = self.objects[i].hit(r, t_min, t_max) {
+                        hit = Some((i, h));
+                        t_max = h.t;
                     }
                 }
             } else {
-                if self.nodes[node.child_index + 0].aabb.hit(r, t_min, t_max) {
-                    // hitlist.push(node.child_index + 0);
-                    stack[size] = node.child_index + 0;
-                    size += 1;
-                }
-                if self.nodes[node.child_index + 1].aabb.hit(r, t_min, t_max) {
-                    // hitlist.push(node.child_index + 1);
-                    stack[size] = node.child_index + 1;
-                    size += 1;
+                // the left child holds the lower coordinates along the split axis
+                let (near, far) = if r.sign(node.axis) == 0 {
+                    (node.child_index, node.child_index + 1)
+                } else {
+                    (node.child_index + 1, node.child_index)
+                };
+
+                // push the far child first, so the near child is popped first
+                for child in [far, near] {
+                    if let Some(t) = self.nodes[child].aabb.intersect(r, t_min, t_max) {
+                        stack[size] = (child, t);
+                        size += 1;
+                    }
                 }
             }
         }
@@ -144,7 +657,170 @@ impl Hittable for Bvh {
         hit
     }
 
+    pub(crate) fn object(&self, index: usize) -> &dyn Hittable {
+        self.objects[index].as_ref()
+    }
+}
+
+impl Hittable for Bvh {
+    fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        self.closest_hit(r, t_min, t_max).map(|(_, h)| h)
+    }
+
     fn bounding_box(&self) -> Option<AABB> {
         Some(self.nodes[0].aabb)
     }
 }
+
+#[test]
+fn test_sah_matches_median() {
+    use crate::geometry::sphere::Sphere;
+    use crate::material::Lambertian;
+    use crate::texture::SolidColor;
+
+    // a dense cluster of small spheres next to a few large ones
+    let objects = || -> Vec<Box<dyn Hittable>> {
+        let mut objects: Vec<Box<dyn Hittable>> = vec![];
+        for i in 0..100 {
+            let center = Vector3::new((i % 10) as f32 * 0.2, (i / 10) as f32 * 0.2, 0.0);
+            let material = Lambertian::new(SolidColor::new(0.5, 0.5, 0.5));
+            objects.push(Box::new(Sphere::new(center, 0.05, material)));
+        }
+        for i in 0..4 {
+            let center = Vector3::new(10.0 + 5.0 * i as f32, 0.0, 0.0);
+            let material = Lambertian::new(SolidColor::new(0.5, 0.5, 0.5));
+            objects.push(Box::new(Sphere::new(center, 2.0, material)));
+        }
+        objects
+    };
+
+    let median = Bvh::new(objects());
+    let sah = Bvh::with_settings(objects(), BvhSettings::sah());
+    assert!(sah.stats().sah_cost < median.stats().sah_cost);
+    assert_eq!(median.stats().average_leaf_size, 1.0);
+
+    for i in 0..100 {
+        let target = Vector3::new((i % 10) as f32 * 0.2, (i / 10) as f32 * 0.2, 0.0);
+        let r = Ray::new(
+            Vector3::new(1.0, 1.0, 5.0),
+            target - Vector3::new(1.0, 1.0, 5.0),
+        );
+        let a = median.hit(&r, 0.001, f32::MAX).map(|h| h.t);
+        let b = sah.hit(&r, 0.001, f32::MAX).map(|h| h.t);
+        assert_eq!(a, b);
+    }
+}
+
+#[test]
+fn test_traversal_stack_size() {
+    use crate::geometry::sphere::Sphere;
+    use crate::material::Lambertian;
+    use crate::texture::SolidColor;
+
+    // exponentially spaced spheres give a deep, unbalanced SAH tree
+    let position = |i: i32| 1.5f32.powi(i);
+    let objects: Vec<Box<dyn Hittable>> = (0..40)
+        .map(|i| {
+            let material = Lambertian::new(SolidColor::new(0.5, 0.5, 0.5));
+            let center = Vector3::new(position(i), 0.0, 0.0);
+            Box::new(Sphere::new(center, 0.1 * position(i), material)) as Box<dyn Hittable>
+        })
+        .collect();
+
+    let bvh = Bvh::with_settings(objects, BvhSettings::sah());
+    assert!(bvh.depth > 10);
+
+    // a stack of depth + 1 entries, as used for trees deeper than the fixed size stack,
+    // must be large enough for every ray
+    for i in 0..40 {
+        let target = Vector3::new(position(i), 0.0, 0.0);
+        let origin = target + Vector3::new(0.0, position(i), 0.0);
+        let r = Ray::new(origin, target - origin);
+        let hit = bvh.traverse(&mut vec![(0, 0.0); bvh.depth + 1], &r, 0.001, f32::MAX);
+        assert!((hit.unwrap().1.t - 0.9).abs() < 1e-3);
+    }
+}
+
+#[test]
+fn test_parallel_build() {
+    use crate::geometry::sphere::Sphere;
+    use crate::material::Lambertian;
+    use crate::texture::SolidColor;
+
+    // enough objects to split the top levels and build several subtrees in parallel
+    let grid = |i: usize| Vector3::new((i % 25) as f32, (i / 25 % 20) as f32, (i / 500) as f32);
+    let spheres = || -> Vec<Box<dyn Hittable>> {
+        (0..10_000)
+            .map(|i| {
+                let material = Lambertian::new(SolidColor::new(0.5, 0.5, 0.5));
+                Box::new(Sphere::new(grid(i), 0.3, material)) as Box<dyn Hittable>
+            })
+            .collect()
+    };
+
+    for settings in [BvhSettings::default(), BvhSettings::sah()] {
+        let bvh = Bvh::with_settings(spheres(), settings);
+        let stats = bvh.stats();
+        assert_eq!(stats.nodes, 2 * stats.leaves - 1);
+        assert!((stats.average_leaf_size * stats.leaves as f32 - 10_000.0).abs() < 0.5);
+
+        // every object stays reachable: a ray down each column hits its top sphere
+        for i in (0..500).step_by(7) {
+            let target = grid(i) + Vector3::new(0.0, 0.0, 19.0);
+            let r = Ray::new(
+                target + Vector3::new(0.1, 0.1, 10.0),
+                Vector3::new(0.0, 0.0, -1.0),
+            );
+            let hit = bvh.hit(&r, 0.001, f32::MAX).unwrap();
+            assert!((hit.p.z - target.z - 0.28).abs() < 0.05);
+        }
+    }
+}
+
+#[test]
+fn test_refit() {
+    use crate::geometry::sphere::Sphere;
+    use crate::material::Lambertian;
+    use crate::texture::SolidColor;
+
+    let spheres = |position: &dyn Fn(usize) -> Vector3<f32>| -> Vec<Box<dyn Hittable>> {
+        (0..64)
+            .map(|i| {
+                let material = Lambertian::new(SolidColor::new(0.5, 0.5, 0.5));
+                Box::new(Sphere::new(position(i), 0.3, material)) as Box<dyn Hittable>
+            })
+            .collect()
+    };
+    let down = Vector3::new(0.0, -1.0, 0.0);
+    let assert_hits = |bvh: &Bvh, position: &dyn Fn(usize) -> Vector3<f32>| {
+        for i in 0..64 {
+            let r = Ray::new(position(i) + Vector3::new(0.0, 5.0, 0.0), down);
+            let hit = bvh.hit(&r, 0.001, f32::MAX).unwrap();
+            assert!((hit.p.y - position(i).y - 0.3).abs() < 1e-4);
+        }
+    };
+
+    let line = |i: usize| Vector3::new(i as f32, 0.0, 0.0);
+    let mut bvh = Bvh::with_settings(spheres(&line), BvhSettings::sah());
+    let nodes = bvh.stats().nodes;
+
+    // a small motion keeps the topology
+    let wave = |i: usize| Vector3::new(i as f32, (i as f32).sin(), 0.0);
+    assert!(!bvh.refit(spheres(&wave)));
+    assert_eq!(bvh.stats().refits, 1);
+    assert_eq!(bvh.stats().nodes, nodes);
+    assert_hits(&bvh, &wave);
+
+    // shuffling the objects along the line makes every node span the whole line
+    let shuffled = |i: usize| Vector3::new((i * 37 % 64) as f32, 0.0, 0.0);
+    assert!(bvh.refit(spheres(&shuffled)));
+    assert_eq!(bvh.stats().refits, 0);
+    assert_hits(&bvh, &shuffled);
+
+    // a changed number of objects rebuilds the tree
+    let mut fewer = spheres(&line);
+    fewer.truncate(32);
+    assert!(bvh.refit(fewer));
+    assert_eq!(bvh.stats().refits, 0);
+    assert!(bvh.stats().nodes < nodes);
+}
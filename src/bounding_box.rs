@@ -2,6 +2,7 @@ use crate::ray::Ray;
 use nalgebra::Vector3;
 use std::ops::Index;
 
+#[allow(clippy::upper_case_acronyms)]
 #[derive(Debug, Copy, Clone)]
 pub struct AABB {
     min: Vector3<f32>,
@@ -20,17 +21,20 @@ impl Index<usize> for AABB {
     }
 }
 
-impl AABB {
-    pub fn new(min: Vector3<f32>, max: Vector3<f32>) -> Self {
-        Self { min: min, max: max }
-    }
-
-    pub fn default() -> Self {
+impl Default for AABB {
+    /// An empty box, extending it with any other box results in that box.
+    fn default() -> Self {
         Self {
             min: Vector3::new(f32::INFINITY, f32::INFINITY, f32::INFINITY),
             max: Vector3::new(f32::NEG_INFINITY, f32::NEG_INFINITY, f32::NEG_INFINITY),
         }
     }
+}
+
+impl AABB {
+    pub fn new(min: Vector3<f32>, max: Vector3<f32>) -> Self {
+        Self { min, max }
+    }
 
     pub fn min(&self) -> Vector3<f32> {
         self.min
@@ -41,7 +45,7 @@ impl AABB {
     }
 
     pub fn diff(&self) -> Vector3<f32> {
-        return self.max - self.min;
+        self.max - self.min
     }
 
     pub fn max_axis(&self) -> usize {
@@ -49,21 +53,24 @@ impl AABB {
         let cmp_a = diff[0] >= diff[1];
         let cmp_b = diff[1] >= diff[2];
         if cmp_a && cmp_b {
-            return 0;
+            0
         } else {
             if cmp_b {
-                return 1;
+                1
             } else {
-                return 2;
+                2
             }
         }
     }
 
-    /*
     pub fn centroid(&self) -> Vector3<f32> {
         (self.min + self.max) * 0.5
     }
-    */
+
+    pub fn surface_area(&self) -> f32 {
+        let d = self.diff();
+        2.0 * (d.x * d.y + d.y * d.z + d.z * d.x)
+    }
 
     pub fn sort_value_axis(&self, axis: usize) -> f32 {
         self.min[axis] + self.max[axis]
@@ -82,13 +89,35 @@ impl AABB {
             f32::max(self.max.z, aabb.max.z),
         );
 
-        return AABB::new(small, big);
+        AABB::new(small, big)
+    }
+
+    /// Bounds of the eight corners of the box after mapping them through `f`.
+    pub fn transform(&self, f: impl Fn(Vector3<f32>) -> Vector3<f32>) -> Self {
+        let mut min = Vector3::repeat(f32::INFINITY);
+        let mut max = Vector3::repeat(f32::NEG_INFINITY);
+        for i in 0..8 {
+            let p = f(Vector3::new(
+                self[i & 1].x,
+                self[(i >> 1) & 1].y,
+                self[i >> 2].z,
+            ));
+            min = min.inf(&p);
+            max = max.sup(&p);
+        }
+        AABB::new(min, max)
     }
 
     pub fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> bool {
+        self.intersect(r, t_min, t_max).is_some()
+    }
+
+    /// Ray distance at which the box is entered, clamped to `t_min`, if the ray hits the box
+    /// within the interval.
+    pub fn intersect(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<f32> {
         // Algorithm from: https://github.com/svenstaro/bvh
-        let mut ray_min = (&self[r.sign_x].x - r.ori.x) * r.inv_dir.x;
-        let mut ray_max = (&self[1 - r.sign_x].x - r.ori.x) * r.inv_dir.x;
+        let mut ray_min = (self[r.sign_x].x - r.ori.x) * r.inv_dir.x;
+        let mut ray_max = (self[1 - r.sign_x].x - r.ori.x) * r.inv_dir.x;
 
         let y_min = (self[r.sign_y].y - r.ori.y) * r.inv_dir.y;
         let y_max = (self[1 - r.sign_y].y - r.ori.y) * r.inv_dir.y;
@@ -102,7 +131,12 @@ impl AABB {
         ray_min = f32::max(ray_min, z_min);
         ray_max = f32::min(ray_max, z_max);
 
-        f32::max(ray_min, t_min) <= f32::min(ray_max, t_max)
+        let entry = f32::max(ray_min, t_min);
+        if entry <= f32::min(ray_max, t_max) {
+            Some(entry)
+        } else {
+            None
+        }
 
         /*
         for i in 0..3 {
@@ -1,10 +1,12 @@
-use crate::ray::Ray;
+use crate::{ray::Ray, sampler};
+use image::{GrayImage, ImageError};
 use nalgebra::{Vector2, Vector3};
-use rand::{distributions::Uniform, prelude::Distribution};
-use std::f32::consts::PI;
+use rand::{distributions::Uniform, prelude::Distribution, Rng};
+use serde::{Deserialize, Serialize};
+use std::{f32::consts::PI, path::Path, sync::Arc};
 
 pub fn random_in_unit_circle() -> Vector2<f32> {
-    let mut rng = rand::thread_rng();
+    let mut rng = sampler::rng();
 
     const MIN: f32 = -1.0;
     const MAX: f32 = 1.0;
@@ -20,7 +22,7 @@ pub fn random_in_unit_circle() -> Vector2<f32> {
     }
 
     /*
-    let mut rng = rand::thread_rng();
+    let mut rng = sampler::rng();
 
     let a = rng.gen::<f32>();
     let b = rng.gen::<f32>();
@@ -35,6 +37,211 @@ pub fn random_in_unit_circle() -> Vector2<f32> {
     */
 }
 
+/// Physical lens and exposure settings, lengths in millimeters.
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PhysicalLens {
+    pub focal_length: f32,
+    pub f_number: f32,
+    /// Horizontal extent of the sensor, 36 mm for full frame.
+    #[serde(default = "default_sensor_width")]
+    pub sensor_width: f32,
+    #[serde(default = "default_iso")]
+    pub iso: f32,
+    /// Shutter speed in seconds.
+    #[serde(default = "default_shutter_speed")]
+    pub shutter_speed: f32,
+    /// Length of a meter in world units, to size the aperture.
+    #[serde(default = "default_units_per_meter")]
+    pub units_per_meter: f32,
+}
+
+fn default_sensor_width() -> f32 {
+    36.0
+}
+
+fn default_iso() -> f32 {
+    100.0
+}
+
+fn default_shutter_speed() -> f32 {
+    0.01
+}
+
+fn default_units_per_meter() -> f32 {
+    1.0
+}
+
+impl PhysicalLens {
+    /// Vertical field of view in degrees, the sensor width spans the image width.
+    pub fn vfov(&self, aspect_ratio: f32) -> f32 {
+        let sensor_height = self.sensor_width / aspect_ratio;
+        2.0 * (sensor_height / (2.0 * self.focal_length))
+            .atan()
+            .to_degrees()
+    }
+
+    /// Diameter of the entrance pupil in world units.
+    pub fn aperture(&self) -> f32 {
+        self.focal_length / self.f_number * 1.0e-3 * self.units_per_meter
+    }
+
+    /// Exposure multiplier, normalized so that the "sunny 16" rule (f/16 at 1/100 s and
+    /// ISO 100) gives 1.
+    pub fn exposure(&self) -> f32 {
+        let ratio = 16.0 / self.f_number;
+        (self.iso / 100.0) * (self.shutter_speed / 0.01) * ratio * ratio
+    }
+}
+
+/// Shape of the aperture, visible in the out of focus highlights.
+#[derive(Clone, Default)]
+pub enum ApertureShape {
+    #[default]
+    Circle,
+    /// Regular polygon inscribed in the aperture circle, rotation in degrees.
+    Polygon {
+        blades: u32,
+        rotation: f32,
+    },
+    Mask(Arc<ApertureMask>),
+}
+
+/// Grayscale image of the aperture, stretched over the square around the aperture circle. The
+/// lens is sampled proportional to the brightness of the pixels.
+pub struct ApertureMask {
+    width: u32,
+    height: u32,
+    /// Cumulative pixel brightness, row by row from the top.
+    cdf: Vec<f32>,
+}
+
+impl ApertureMask {
+    /// Returns `None` for an image without any bright pixel.
+    pub fn new(image: &GrayImage) -> Option<Self> {
+        let cdf: Vec<f32> = image
+            .pixels()
+            .scan(0.0, |total, p| {
+                *total += p.0[0] as f32;
+                Some(*total)
+            })
+            .collect();
+
+        if cdf.last().is_none_or(|&total| total <= 0.0) {
+            return None;
+        }
+
+        Some(Self {
+            width: image.width(),
+            height: image.height(),
+            cdf,
+        })
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Option<Self>, ImageError> {
+        Ok(Self::new(&image::open(path)?.into_luma8()))
+    }
+
+    /// Random point in [-1, 1] x [-1, 1].
+    fn sample(&self) -> Vector2<f32> {
+        let mut rng = sampler::rng();
+        let x = rng.gen::<f32>() * self.cdf.last().unwrap();
+        let i = self
+            .cdf
+            .partition_point(|&c| c <= x)
+            .min(self.cdf.len() - 1) as u32;
+
+        let (px, py) = (i % self.width, i / self.width);
+        let u = (px as f32 + rng.gen::<f32>()) / self.width as f32;
+        let v = (py as f32 + rng.gen::<f32>()) / self.height as f32;
+        Vector2::new(2.0 * u - 1.0, 1.0 - 2.0 * v)
+    }
+}
+
+impl ApertureShape {
+    /// Random point on the aperture, within the unit circle, or within the square around it
+    /// for a mask.
+    fn sample(&self) -> Vector2<f32> {
+        match self {
+            ApertureShape::Circle => random_in_unit_circle(),
+            ApertureShape::Polygon { blades, rotation } => {
+                // uniform point in a random triangle between the center and two corners
+                let mut rng = sampler::rng();
+                let blades = (*blades).max(3);
+                let step = 2.0 * PI / blades as f32;
+                let angle = rotation.to_radians() + rng.gen_range(0..blades) as f32 * step;
+                let a = Vector2::new(angle.cos(), angle.sin());
+                let b = Vector2::new((angle + step).cos(), (angle + step).sin());
+
+                let (mut s, mut t) = rng.gen::<(f32, f32)>();
+                if s + t > 1.0 {
+                    (s, t) = (1.0 - s, 1.0 - t);
+                }
+                s * a + t * b
+            }
+            ApertureShape::Mask(mask) => mask.sample(),
+        }
+    }
+}
+
+/// Mapping of the image plane onto ray directions.
+#[derive(Debug, Copy, Clone, PartialEq, Default, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Projection {
+    /// Thin lens perspective with the vertical field of view of the camera.
+    #[default]
+    Perspective,
+    /// Parallel rays along the view direction, `height` is the visible height in world units.
+    Orthographic { height: f32 },
+    /// Equidistant fisheye, `fov` (in degrees) spans the image height. Outside of the image
+    /// circle the mapping continues up to the backward direction.
+    Fisheye { fov: f32 },
+    /// Full 360 by 180 degree panorama around the up vector, centered on the view direction.
+    /// Use an aspect ratio of 2.
+    Equirectangular,
+}
+
+/// How the eyes of a stereo rig are aimed.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Convergence {
+    /// Parallel view directions with image windows shifted to meet at the convergence distance,
+    /// free of vertical parallax.
+    #[default]
+    OffAxis,
+    /// Both eyes rotated towards the point at the convergence distance.
+    ToeIn,
+}
+
+/// Arrangement of the two eye images in the output image.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StereoLayout {
+    /// Left eye on the left, right eye on the right.
+    #[default]
+    SideBySide,
+    /// Left eye on the top, right eye on the bottom.
+    OverUnder,
+}
+
+/// Two eyes to the left and right of the camera, rendered into a single image.
+///
+/// Perspective cameras converge the eyes at the convergence distance. Equirectangular cameras
+/// render an omni-directional stereo panorama, with the eyes on a circle of the interpupillary
+/// distance, and the other projections only offset the eyes.
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StereoRig {
+    /// Interpupillary distance in world units.
+    pub ipd: f32,
+    /// Distance of the zero parallax plane, the focus distance by default.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub convergence_distance: Option<f32>,
+    #[serde(default)]
+    pub convergence: Convergence,
+    #[serde(default)]
+    pub layout: StereoLayout,
+}
+
+#[derive(Clone)]
 pub struct Camera {
     // focal_length: f32,
     pub aspect_ratio: f32,
@@ -44,8 +251,20 @@ pub struct Camera {
     lower_left_corner: Vector3<f32>,
     u: Vector3<f32>,
     v: Vector3<f32>,
-    // w: Vector3<f32>,
+    w: Vector3<f32>,
     lens_radius: f32,
+    /// Shutter open and close times, the rays are cast at random times in between.
+    time0: f32,
+    time1: f32,
+    projection: Projection,
+    aperture_shape: ApertureShape,
+    /// Shift of the clipping circle of the lens barrel at the image corners, for cat's-eye
+    /// shaped bokeh towards the image borders.
+    cat_eye: f32,
+    exposure: f32,
+    stereo: Option<StereoRig>,
+    /// Signed distance of the eye from the center, for omni-directional stereo panoramas.
+    ods_offset: f32,
 }
 
 impl Camera {
@@ -64,7 +283,7 @@ impl Camera {
         let viewport_height = 2.0 * h;
         let viewport_width = aspect_ratio * viewport_height;
 
-        let w: Vector3<f32> = (&lookfrom - &lookat).normalize();
+        let w: Vector3<f32> = (lookfrom - lookat).normalize();
         let u: Vector3<f32> = (Vector3::cross(&vup, &w)).normalize();
         let v: Vector3<f32> = Vector3::cross(&w, &u);
 
@@ -77,26 +296,443 @@ impl Camera {
 
         Self {
             // focal_length: focal_length,
-            aspect_ratio: aspect_ratio,
-            origin: origin,
-            horizontal: horizontal,
-            vertical: vertical,
-            lower_left_corner: lower_left_corner,
-            u: u,
-            v: v,
-            // w: w,
-            lens_radius: lens_radius,
+            aspect_ratio,
+            origin,
+            horizontal,
+            vertical,
+            lower_left_corner,
+            u,
+            v,
+            w,
+            lens_radius,
+            time0: 0.0,
+            time1: 0.0,
+            projection: Projection::Perspective,
+            aperture_shape: ApertureShape::Circle,
+            cat_eye: 0.0,
+            exposure: 1.0,
+            stereo: None,
+            ods_offset: 0.0,
+        }
+    }
+
+    /// Camera with the field of view, aperture and exposure of a physical lens.
+    pub fn physical(
+        lookfrom: Vector3<f32>,
+        lookat: Vector3<f32>,
+        vup: Vector3<f32>,
+        aspect_ratio: f32,
+        focus_dist: f32,
+        lens: &PhysicalLens,
+    ) -> Self {
+        Self::new(
+            lookfrom,
+            lookat,
+            vup,
+            lens.vfov(aspect_ratio),
+            aspect_ratio,
+            lens.aperture(),
+            focus_dist,
+        )
+        .with_exposure(lens.exposure())
+    }
+
+    pub fn with_aperture_shape(mut self, shape: ApertureShape) -> Self {
+        self.aperture_shape = shape;
+        self
+    }
+
+    /// Clip the aperture by a circle shifted towards the image borders, 0 disables it and 1
+    /// closes the aperture at the image corners.
+    pub fn with_cat_eye(mut self, strength: f32) -> Self {
+        self.cat_eye = strength;
+        self
+    }
+
+    /// Multiplier of the rendered colors.
+    pub fn with_exposure(mut self, exposure: f32) -> Self {
+        self.exposure = exposure;
+        self
+    }
+
+    pub fn exposure(&self) -> f32 {
+        self.exposure
+    }
+
+    pub fn with_stereo(mut self, rig: StereoRig) -> Self {
+        self.stereo = Some(rig);
+        self
+    }
+
+    pub fn stereo(&self) -> Option<StereoRig> {
+        self.stereo
+    }
+
+    /// Cameras of the left and right eye of the stereo rig.
+    pub fn stereo_eyes(&self) -> Option<[Camera; 2]> {
+        let rig = self.stereo?;
+        Some([self.eye(&rig, -1.0), self.eye(&rig, 1.0)])
+    }
+
+    /// Camera of one eye, `side` is -1 for the left and 1 for the right eye.
+    fn eye(&self, rig: &StereoRig, side: f32) -> Camera {
+        let offset = side * rig.ipd / 2.0;
+        let mut camera = self.clone();
+        camera.stereo = None;
+
+        match self.projection {
+            Projection::Perspective => {}
+            Projection::Equirectangular => {
+                camera.ods_offset = offset;
+                return camera;
+            }
+            Projection::Orthographic { .. } | Projection::Fisheye { .. } => {
+                camera.origin += offset * self.u;
+                return camera;
+            }
+        }
+
+        let center = self.lower_left_corner + self.horizontal / 2.0 + self.vertical / 2.0;
+        let focus_dist = (self.origin - center).norm();
+        let convergence_distance = rig.convergence_distance.unwrap_or(focus_dist);
+        camera.origin = self.origin + offset * self.u;
+
+        match rig.convergence {
+            Convergence::OffAxis => {
+                // shift the window so the rays through it meet the rays of the center camera at
+                // the convergence distance
+                camera.lower_left_corner = self.lower_left_corner
+                    + offset * (1.0 - focus_dist / convergence_distance) * self.u;
+            }
+            Convergence::ToeIn => {
+                let target = self.origin - convergence_distance * self.w;
+                let w = (camera.origin - target).normalize();
+                let u = self.v.cross(&w).normalize();
+
+                camera.horizontal = self.horizontal.norm() * u;
+                camera.lower_left_corner =
+                    camera.origin - camera.horizontal / 2.0 - self.vertical / 2.0 - focus_dist * w;
+                camera.u = u;
+                camera.w = w;
+            }
+        }
+
+        camera
+    }
+
+    /// Random point on the lens for the image position, in units of the lens radius.
+    fn lens_sample(&self, u: f32, v: f32) -> Vector2<f32> {
+        if self.cat_eye <= 0.0 {
+            return self.aperture_shape.sample();
+        }
+
+        // image position relative to the center, 1 at the corners
+        let corner = (self.aspect_ratio * self.aspect_ratio + 1.0).sqrt();
+        let shift = self.cat_eye * Vector2::new((2.0 * u - 1.0) * self.aspect_ratio, 2.0 * v - 1.0)
+            / corner;
+
+        // resample until the point is inside the barrel
+        for _ in 0..32 {
+            let p = self.aperture_shape.sample();
+            if (p + shift).norm_squared() <= 1.0 {
+                return p;
+            }
+        }
+
+        // give up for nearly closed apertures, with the point of the barrel closest to the
+        // center of the aperture
+        -shift * (1.0 - 1.0 / shift.norm().max(1.0))
+    }
+
+    /// Whether light paths can be connected to the lens, see [`Camera::connect`]. Only
+    /// perspective cameras without the cat's-eye effect map scene points back onto the image.
+    pub fn connectable(&self) -> bool {
+        self.projection == Projection::Perspective && self.cat_eye <= 0.0
+    }
+
+    /// Ray from a random point on the lens to the scene point `p`, with the image plane
+    /// position (u, v) of the camera rays along it. `None` for points behind the camera and for
+    /// cameras that are not connectable.
+    pub fn connect(&self, p: Vector3<f32>, time: f32) -> Option<(Ray, f32, f32)> {
+        if !self.connectable() {
+            return None;
+        }
+
+        let rd: Vector2<f32> = if self.lens_radius > 0.0 {
+            self.lens_radius * self.aperture_shape.sample()
+        } else {
+            Vector2::zeros()
+        };
+        let origin = self.origin + self.u * rd.x + self.v * rd.y;
+        let direction = p - origin;
+
+        let offset = self.image_plane_hit(origin, direction)? - self.lower_left_corner;
+        let u = offset.dot(&self.horizontal) / self.horizontal.norm_squared();
+        let v = offset.dot(&self.vertical) / self.vertical.norm_squared();
+        Some((Ray::with_time(origin, direction, time), u, v))
+    }
+
+    /// Density of the directions of camera rays from a point on the lens, per unit solid angle,
+    /// for image plane positions picked with a density of 1. 0 for directions away from the
+    /// image plane and for cameras that are not connectable.
+    pub fn direction_pdf(&self, origin: Vector3<f32>, direction: Vector3<f32>) -> f32 {
+        if !self.connectable() {
+            return 0.0;
+        }
+        let Some(p) = self.image_plane_hit(origin, direction) else {
+            return 0.0;
+        };
+
+        // the image plane spans |h x v| per unit of (u, v)
+        let normal = self.horizontal.cross(&self.vertical);
+        let cosine = direction.normalize().dot(&normal).abs() / normal.norm();
+        (p - origin).norm_squared() / (normal.norm() * cosine)
+    }
+
+    /// Point where a ray from the lens crosses the image plane in the focus distance.
+    fn image_plane_hit(
+        &self,
+        origin: Vector3<f32>,
+        direction: Vector3<f32>,
+    ) -> Option<Vector3<f32>> {
+        let normal = self.horizontal.cross(&self.vertical);
+        let t = (self.lower_left_corner - origin).dot(&normal) / direction.dot(&normal);
+        (t > 0.0 && t.is_finite()).then(|| origin + t * direction)
+    }
+
+    /// Keep the shutter open from `time0` to `time1`, for motion blur.
+    pub fn with_shutter(mut self, time0: f32, time1: f32) -> Self {
+        self.time0 = time0;
+        self.time1 = time1;
+        self
+    }
+
+    /// Random time while the shutter is open, the time of the camera rays.
+    pub fn sample_time(&self) -> f32 {
+        if self.time1 > self.time0 {
+            self.time0 + sampler::rng().gen::<f32>() * (self.time1 - self.time0)
+        } else {
+            self.time0
         }
     }
 
+    pub fn with_projection(mut self, projection: Projection) -> Self {
+        self.projection = projection;
+        self
+    }
+
+    pub fn projection(&self) -> Projection {
+        self.projection
+    }
+
+    /// Ray through the image plane position (u, v), both in [0, 1] from the lower left corner.
     pub fn ray(&self, u: f32, v: f32) -> Ray {
-        let rd: Vector2<f32> = self.lens_radius * random_in_unit_circle();
-        let offset: Vector3<f32> = self.u * rd.x + self.v * rd.y;
+        let time = self.sample_time();
+
+        match self.projection {
+            Projection::Perspective => {
+                let rd: Vector2<f32> = if self.lens_radius > 0.0 {
+                    self.lens_radius * self.lens_sample(u, v)
+                } else {
+                    Vector2::zeros()
+                };
+                let offset: Vector3<f32> = self.u * rd.x + self.v * rd.y;
+
+                Ray::with_time(
+                    self.origin + offset,
+                    self.lower_left_corner + u * self.horizontal + v * self.vertical
+                        - (self.origin + offset),
+                    time,
+                )
+            }
+            Projection::Orthographic { height } => {
+                let offset =
+                    (u - 0.5) * self.aspect_ratio * height * self.u + (v - 0.5) * height * self.v;
+                Ray::with_time(self.origin + offset, -self.w, time)
+            }
+            Projection::Fisheye { fov } => {
+                // polar coordinates around the image center, radius 1 at the top edge
+                let x = (2.0 * u - 1.0) * self.aspect_ratio;
+                let y = 2.0 * v - 1.0;
+                let r = (x * x + y * y).sqrt();
+                let theta = (r * fov.to_radians() / 2.0).min(PI);
+                let (sin_phi, cos_phi) = if r > 0.0 { (y / r, x / r) } else { (0.0, 1.0) };
+
+                let direction =
+                    theta.sin() * (cos_phi * self.u + sin_phi * self.v) - theta.cos() * self.w;
+                Ray::with_time(self.origin, direction, time)
+            }
+            Projection::Equirectangular => {
+                let longitude = (u - 0.5) * 2.0 * PI;
+                let latitude = (v - 0.5) * PI;
+
+                let direction = latitude.cos()
+                    * (longitude.sin() * self.u - longitude.cos() * self.w)
+                    + latitude.sin() * self.v;
+
+                // stereo eyes sit on a circle, offset perpendicular to the horizontal direction
+                let side = longitude.cos() * self.u + longitude.sin() * self.w;
+                Ray::with_time(self.origin + self.ods_offset * side, direction, time)
+            }
+        }
+    }
+}
+
+#[test]
+fn test_projections() {
+    let camera = Camera::new(
+        Vector3::new(0.0, 0.0, 0.0),
+        Vector3::new(0.0, 0.0, -1.0),
+        Vector3::new(0.0, 1.0, 0.0),
+        90.0,
+        2.0,
+        0.0,
+        1.0,
+    );
+    let direction = |camera: &Camera, u, v| camera.ray(u, v).nrm_dir;
+    let close = |a: Vector3<f32>, b: Vector3<f32>| (a - b).norm() < 1e-5;
+
+    // parallel rays, offset over the visible area
+    let ortho = camera.with_projection(Projection::Orthographic { height: 4.0 });
+    let r = ortho.ray(1.0, 0.0);
+    assert!(close(r.ori, Vector3::new(4.0, -2.0, 0.0)));
+    assert!(close(r.nrm_dir, Vector3::new(0.0, 0.0, -1.0)));
 
-        Ray::new(
-            self.origin + offset,
-            self.lower_left_corner + u * self.horizontal + v * self.vertical
-                - (self.origin + offset),
+    // the top edge of the image is 90 degrees off axis
+    let camera = ortho.with_projection(Projection::Fisheye { fov: 180.0 });
+    assert!(close(
+        direction(&camera, 0.5, 0.5),
+        Vector3::new(0.0, 0.0, -1.0)
+    ));
+    assert!(close(
+        direction(&camera, 0.5, 1.0),
+        Vector3::new(0.0, 1.0, 0.0)
+    ));
+    assert!(close(
+        direction(&camera, 0.25, 0.5),
+        Vector3::new(-1.0, 0.0, 0.0)
+    ));
+
+    let camera = camera.with_projection(Projection::Equirectangular);
+    assert!(close(
+        direction(&camera, 0.5, 0.5),
+        Vector3::new(0.0, 0.0, -1.0)
+    ));
+    assert!(close(
+        direction(&camera, 0.75, 0.5),
+        Vector3::new(1.0, 0.0, 0.0)
+    ));
+    assert!(close(
+        direction(&camera, 0.0, 0.5),
+        Vector3::new(0.0, 0.0, 1.0)
+    ));
+    assert!(close(
+        direction(&camera, 0.3, 1.0),
+        Vector3::new(0.0, 1.0, 0.0)
+    ));
+}
+
+#[test]
+fn test_physical_camera() {
+    // 50 mm lens on a full frame sensor in landscape
+    let lens = PhysicalLens {
+        focal_length: 50.0,
+        f_number: 16.0,
+        sensor_width: 36.0,
+        iso: 100.0,
+        shutter_speed: 0.01,
+        units_per_meter: 1.0,
+    };
+    assert!((lens.vfov(1.5) - 26.99).abs() < 0.01);
+    assert!((lens.aperture() - 0.003125).abs() < 1e-6);
+    assert!((lens.exposure() - 1.0).abs() < 1e-6);
+
+    // one stop less light for every halved shutter speed
+    let lens = PhysicalLens {
+        shutter_speed: 0.005,
+        ..lens
+    };
+    assert!((lens.exposure() - 0.5).abs() < 1e-6);
+
+    // polygon samples stay inside the hexagon
+    let hexagon = ApertureShape::Polygon {
+        blades: 6,
+        rotation: 0.0,
+    };
+    let apothem = (PI / 6.0).cos();
+    for _ in 0..100 {
+        let p = hexagon.sample();
+        let angle = p.y.atan2(p.x).rem_euclid(PI / 3.0) - PI / 6.0;
+        assert!(p.norm() * angle.cos() <= apothem + 1e-5);
+    }
+
+    // a mask with only the top right quarter lit
+    let image = GrayImage::from_fn(4, 4, |x, y| {
+        image::Luma([if x >= 2 && y < 2 { 255 } else { 0 }])
+    });
+    let mask = ApertureShape::Mask(Arc::new(ApertureMask::new(&image).unwrap()));
+    for _ in 0..100 {
+        let p = mask.sample();
+        assert!(p.x >= 0.0 && p.y >= 0.0);
+    }
+    assert!(ApertureMask::new(&GrayImage::new(4, 4)).is_none());
+
+    // lens samples stay inside the barrel, also when it misses the aperture in the corners
+    for strength in [0.8, 3.0] {
+        let camera = Camera::new(
+            Vector3::zeros(),
+            Vector3::new(0.0, 0.0, -1.0),
+            Vector3::y(),
+            40.0,
+            1.5,
+            0.1,
+            1.0,
         )
+        .with_cat_eye(strength);
+        let shift = strength * Vector2::new(1.5, 1.0) / 3.25f32.sqrt();
+        for _ in 0..100 {
+            assert!((camera.lens_sample(1.0, 1.0) + shift).norm() <= 1.0 + 1e-5);
+        }
+    }
+}
+
+#[test]
+fn test_stereo_eyes() {
+    let camera = Camera::new(
+        Vector3::new(0.0, 0.0, 0.0),
+        Vector3::new(0.0, 0.0, -1.0),
+        Vector3::new(0.0, 1.0, 0.0),
+        60.0,
+        1.5,
+        0.0,
+        2.0,
+    );
+    let rig = StereoRig {
+        ipd: 0.064,
+        convergence_distance: Some(4.0),
+        convergence: Convergence::OffAxis,
+        layout: StereoLayout::SideBySide,
+    };
+    let target = Vector3::new(0.0, 0.0, -4.0);
+
+    // the center rays of both eyes meet at the convergence distance
+    for convergence in [Convergence::OffAxis, Convergence::ToeIn] {
+        let camera = camera.clone().with_stereo(StereoRig { convergence, ..rig });
+        let [left, right] = camera.stereo_eyes().unwrap();
+        for (eye, x) in [(left, -0.032), (right, 0.032)] {
+            let r = eye.ray(0.5, 0.5);
+            assert!((r.ori - Vector3::new(x, 0.0, 0.0)).norm() < 1e-6);
+            assert!((r.ori + r.nrm_dir * (target - r.ori).norm() - target).norm() < 1e-5);
+        }
     }
+
+    // omni-directional stereo: the eyes are offset perpendicular to every direction
+    let panorama = camera
+        .with_projection(Projection::Equirectangular)
+        .with_stereo(rig);
+    let [left, right] = panorama.stereo_eyes().unwrap();
+    let (l, r) = (left.ray(0.75, 0.5), right.ray(0.75, 0.5));
+    assert!((l.ori - Vector3::new(0.0, 0.0, -0.032)).norm() < 1e-6);
+    assert!((r.ori - Vector3::new(0.0, 0.0, 0.032)).norm() < 1e-6);
+    assert!((l.nrm_dir - Vector3::new(1.0, 0.0, 0.0)).norm() < 1e-6);
 }
@@ -0,0 +1,1465 @@
+use crate::{
+    animation::Track,
+    bhv::{Bvh, BvhSettings, BvhStats},
+    camera::{ApertureMask, ApertureShape, Camera, PhysicalLens, Projection, StereoRig},
+    geometry::{
+        cube::Cube,
+        mesh::{MeshData, TriangleMesh},
+        rectangle::{Plane, RectAA},
+        sphere::{MovingSphere, Sphere},
+        triangle::Triangle,
+    },
+    hit::{Hittable, World},
+    instance::{
+        FlipFace, Instance, Keyframe, MovingInstance, ObjectId, Rotate, Transform, Translate,
+    },
+    loader::{
+        obj::{self, ObjError},
+        ply::{self, PlyError},
+        stl::{self, StlError},
+    },
+    material::{Dielectric, DiffuseLight, Isotropic, Lambertian, Material, MaterialId, Metal},
+    medium::Constant,
+    texture::{Checker, Image, Noise, SolidColor, Texture, VertexColor},
+};
+use nalgebra::{Matrix4, Similarity3, Translation3, UnitQuaternion, Vector2, Vector3};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, HashMap},
+    error::Error,
+    fmt, fs, io,
+    path::Path,
+    sync::Arc,
+};
+
+/// Camera, world, lights and background color of a built scene.
+pub type BuiltScene = (Camera, Box<dyn Hittable>, Box<dyn Hittable>, Vector3<f32>);
+
+/// Serializable description of a complete scene.
+///
+/// Textures and materials can either be declared inline or registered by name in the
+/// `textures`/`materials` tables and referenced by that name. Named entries are built once
+/// and shared between all objects referring to them. Geometry in the `geometry` table is placed
+/// in the world with `instance` objects, which all share a single copy of it.
+///
+/// An `animation` keyframes the camera, and `animated` objects keyframe their placement, over
+/// the frames of an image sequence, see [`SceneDescription::sequence`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SceneDescription {
+    pub camera: CameraDescription,
+    pub background: [f32; 3],
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub textures: BTreeMap<String, TextureDescription>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub materials: BTreeMap<String, MaterialDescription>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub geometry: BTreeMap<String, ObjectDescription>,
+    pub world: Vec<ObjectDescription>,
+    #[serde(default)]
+    pub lights: Vec<ObjectDescription>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub animation: Option<AnimationDescription>,
+}
+
+/// Timeline of an animated scene, with the keys of the camera in seconds.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AnimationDescription {
+    pub frames: u32,
+    #[serde(default = "default_fps")]
+    pub fps: f32,
+    #[serde(default, skip_serializing_if = "CameraAnimation::is_empty")]
+    pub camera: CameraAnimation,
+}
+
+/// Keyframed camera parameters, replacing the values of the camera description.
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub struct CameraAnimation {
+    #[serde(default, skip_serializing_if = "Track::is_empty")]
+    pub lookfrom: Track<[f32; 3]>,
+    #[serde(default, skip_serializing_if = "Track::is_empty")]
+    pub lookat: Track<[f32; 3]>,
+    #[serde(default, skip_serializing_if = "Track::is_empty")]
+    pub vfov: Track<f32>,
+    #[serde(default, skip_serializing_if = "Track::is_empty")]
+    pub focus_dist: Track<f32>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CameraDescription {
+    pub lookfrom: [f32; 3],
+    pub lookat: [f32; 3],
+    #[serde(default = "default_vup")]
+    pub vup: [f32; 3],
+    #[serde(default = "default_vfov")]
+    pub vfov: f32,
+    pub aspect_ratio: f32,
+    #[serde(default)]
+    pub aperture: f32,
+    pub focus_dist: f32,
+    /// Shutter interval, rays are cast at random times in between for motion blur.
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub time0: f32,
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub time1: f32,
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub projection: Projection,
+    /// Physical lens, replaces `vfov` and `aperture` and sets the exposure.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lens: Option<PhysicalLens>,
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub aperture_shape: ApertureDescription,
+    /// Strength of the cat's-eye vignetting of the bokeh towards the image corners, in [0, 1].
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub cat_eye: f32,
+    /// Render left and right eye images into a single image.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stereo: Option<StereoRig>,
+}
+
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ApertureDescription {
+    #[default]
+    Circle,
+    /// Regular polygon with a blade per corner, rotation in degrees.
+    Polygon {
+        blades: u32,
+        #[serde(default)]
+        rotation: f32,
+    },
+    /// Grayscale image of the aperture.
+    Mask { path: String },
+}
+
+fn default_vup() -> [f32; 3] {
+    [0.0, 1.0, 0.0]
+}
+
+fn default_vfov() -> f32 {
+    40.0
+}
+
+fn default_fps() -> f32 {
+    24.0
+}
+
+fn default_scale() -> f32 {
+    1.0
+}
+
+fn is_unit_scale(scale: &f32) -> bool {
+    *scale == 1.0
+}
+
+fn default_time1() -> f32 {
+    1.0
+}
+
+fn is_default<T: Default + PartialEq>(v: &T) -> bool {
+    *v == T::default()
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum TextureDescription {
+    Solid {
+        color: [f32; 3],
+    },
+    Checker {
+        odd: Box<TextureReference>,
+        even: Box<TextureReference>,
+    },
+    Image {
+        path: String,
+    },
+    Noise {
+        scale: f32,
+    },
+    /// Vertex colors of meshes, objects without vertex colors use the fallback texture
+    /// (light gray by default).
+    VertexColor {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        fallback: Option<Box<TextureReference>>,
+    },
+}
+
+/// Either the name of an entry in the `textures` table or an inline texture.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum TextureReference {
+    Named(String),
+    Inline(TextureDescription),
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum MaterialDescription {
+    Lambertian { albedo: TextureReference },
+    Metal { albedo: TextureReference, fuzz: f32 },
+    Dielectric { refraction_index: f32 },
+    DiffuseLight { emit: TextureReference },
+    Isotropic { albedo: TextureReference },
+}
+
+/// Either the name of an entry in the `materials` table or an inline material.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum MaterialReference {
+    Named(String),
+    Inline(MaterialDescription),
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ObjectDescription {
+    Sphere {
+        center: [f32; 3],
+        radius: f32,
+        material: MaterialReference,
+    },
+    /// Sphere moving linearly from `center0` at `time0` to `center1` at `time1`.
+    MovingSphere {
+        center0: [f32; 3],
+        center1: [f32; 3],
+        #[serde(default)]
+        time0: f32,
+        #[serde(default = "default_time1")]
+        time1: f32,
+        radius: f32,
+        material: MaterialReference,
+    },
+    Rect {
+        plane: Plane,
+        a0: f32,
+        a1: f32,
+        b0: f32,
+        b1: f32,
+        k: f32,
+        material: MaterialReference,
+    },
+    Cube {
+        min: [f32; 3],
+        max: [f32; 3],
+        material: MaterialReference,
+    },
+    Triangle {
+        vertices: [[f32; 3]; 3],
+        material: MaterialReference,
+    },
+    /// Indexed triangle mesh, the optional normals and uvs are indexed like the vertices.
+    Mesh {
+        vertices: Vec<[f32; 3]>,
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        normals: Vec<[f32; 3]>,
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        uvs: Vec<[f32; 2]>,
+        indices: Vec<[usize; 3]>,
+        material: MaterialReference,
+    },
+    /// Wavefront OBJ model, faces without an MTL material use `material`.
+    Obj {
+        path: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        material: Option<MaterialReference>,
+    },
+    /// PLY mesh, use a `vertex_color` texture to shade with its vertex colors.
+    Ply {
+        path: String,
+        material: MaterialReference,
+    },
+    Stl {
+        path: String,
+        material: MaterialReference,
+    },
+    ConstantMedium {
+        boundary: Box<ObjectDescription>,
+        density: f32,
+        texture: TextureReference,
+    },
+    Translate {
+        offset: [f32; 3],
+        object: Box<ObjectDescription>,
+    },
+    /// Rotation about the Y axis, angle in degrees.
+    Rotate {
+        angle: f32,
+        object: Box<ObjectDescription>,
+    },
+    FlipFace {
+        object: Box<ObjectDescription>,
+    },
+    /// Affine transform, the steps are applied in order.
+    Transform {
+        transforms: Vec<TransformDescription>,
+        object: Box<ObjectDescription>,
+    },
+    /// Placement of an entry of the `geometry` table, rotated about the X, Y and Z axes (in
+    /// degrees and in that order), scaled and then translated.
+    Instance {
+        geometry: String,
+        #[serde(default, skip_serializing_if = "is_default")]
+        translate: [f32; 3],
+        #[serde(default, skip_serializing_if = "is_default")]
+        rotate: [f32; 3],
+        #[serde(default = "default_scale", skip_serializing_if = "is_unit_scale")]
+        scale: f32,
+    },
+    /// Entry of the `geometry` table moving through keyframed placements, interpolated
+    /// between the keyframes.
+    MovingInstance {
+        geometry: String,
+        keyframes: Vec<KeyframeDescription>,
+    },
+    /// Object with a keyframed placement, rotated about the X, Y and Z axes (in degrees and in
+    /// that order), scaled and then translated like an `instance`. The object is built once and
+    /// only its placement changes between frames.
+    Animated {
+        #[serde(default, skip_serializing_if = "Track::is_empty")]
+        translate: Track<[f32; 3]>,
+        #[serde(default, skip_serializing_if = "Track::is_empty")]
+        rotate: Track<[f32; 3]>,
+        #[serde(default, skip_serializing_if = "Track::is_empty")]
+        scale: Track<f32>,
+        object: Box<ObjectDescription>,
+    },
+    Bvh {
+        objects: Vec<ObjectDescription>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        settings: Option<BvhSettings>,
+    },
+    List {
+        objects: Vec<ObjectDescription>,
+    },
+}
+
+/// Placement of a `moving_instance` at a point in time, like an `instance`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct KeyframeDescription {
+    pub time: f32,
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub translate: [f32; 3],
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub rotate: [f32; 3],
+    #[serde(default = "default_scale", skip_serializing_if = "is_unit_scale")]
+    pub scale: f32,
+}
+
+/// A single step of a `transform` object.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TransformDescription {
+    Translate([f32; 3]),
+    /// Rotation about an axis through the origin, angle in degrees.
+    Rotate {
+        axis: [f32; 3],
+        angle: f32,
+    },
+    Scale([f32; 3]),
+    /// Move the object from the origin to `eye`, turning its +Z axis towards `target`.
+    LookAt {
+        eye: [f32; 3],
+        target: [f32; 3],
+        #[serde(default = "default_vup")]
+        up: [f32; 3],
+    },
+    /// Row-major 4x4 matrix.
+    Matrix([[f32; 4]; 4]),
+}
+
+#[derive(Debug)]
+pub enum SceneError {
+    Io(io::Error),
+    Parse(serde_json::Error),
+    UnknownTexture(String),
+    UnknownMaterial(String),
+    CyclicTexture(String),
+    UnknownGeometry(String),
+    CyclicGeometry(String),
+    InvalidInstance(String),
+    InvalidTransform(String),
+    InvalidCamera(String),
+    InvalidAnimation(String),
+    InvalidMesh(String),
+    Image(String, image::ImageError),
+    Obj(ObjError),
+    Ply(PlyError),
+    Stl(StlError),
+}
+
+impl fmt::Display for SceneError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SceneError::Io(err) => write!(f, "failed to read scene file: {}", err),
+            SceneError::Parse(err) => write!(f, "failed to parse scene file: {}", err),
+            SceneError::UnknownTexture(name) => write!(f, "unknown texture '{}'", name),
+            SceneError::UnknownMaterial(name) => write!(f, "unknown material '{}'", name),
+            SceneError::CyclicTexture(name) => {
+                write!(f, "texture '{}' references itself", name)
+            }
+            SceneError::UnknownGeometry(name) => write!(f, "unknown geometry '{}'", name),
+            SceneError::CyclicGeometry(name) => {
+                write!(f, "geometry '{}' references itself", name)
+            }
+            SceneError::InvalidInstance(reason) => write!(f, "invalid instance: {}", reason),
+            SceneError::InvalidTransform(reason) => write!(f, "invalid transform: {}", reason),
+            SceneError::InvalidCamera(reason) => write!(f, "invalid camera: {}", reason),
+            SceneError::InvalidAnimation(reason) => write!(f, "invalid animation: {}", reason),
+            SceneError::InvalidMesh(reason) => write!(f, "invalid mesh: {}", reason),
+            SceneError::Image(path, err) => {
+                write!(f, "failed to load texture '{}': {}", path, err)
+            }
+            SceneError::Obj(err) => write!(f, "failed to load model: {}", err),
+            SceneError::Ply(err) => write!(f, "failed to load model: {}", err),
+            SceneError::Stl(err) => write!(f, "failed to load model: {}", err),
+        }
+    }
+}
+
+impl Error for SceneError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            SceneError::Io(err) => Some(err),
+            SceneError::Parse(err) => Some(err),
+            SceneError::Image(_, err) => Some(err),
+            SceneError::Obj(err) => Some(err),
+            SceneError::Ply(err) => Some(err),
+            SceneError::Stl(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for SceneError {
+    fn from(err: io::Error) -> Self {
+        SceneError::Io(err)
+    }
+}
+
+impl From<serde_json::Error> for SceneError {
+    fn from(err: serde_json::Error) -> Self {
+        SceneError::Parse(err)
+    }
+}
+
+impl From<ObjError> for SceneError {
+    fn from(err: ObjError) -> Self {
+        SceneError::Obj(err)
+    }
+}
+
+impl From<PlyError> for SceneError {
+    fn from(err: PlyError) -> Self {
+        SceneError::Ply(err)
+    }
+}
+
+impl From<StlError> for SceneError {
+    fn from(err: StlError) -> Self {
+        SceneError::Stl(err)
+    }
+}
+
+impl From<&str> for TextureReference {
+    fn from(name: &str) -> Self {
+        TextureReference::Named(name.to_string())
+    }
+}
+
+impl From<TextureDescription> for TextureReference {
+    fn from(texture: TextureDescription) -> Self {
+        TextureReference::Inline(texture)
+    }
+}
+
+impl From<&str> for MaterialReference {
+    fn from(name: &str) -> Self {
+        MaterialReference::Named(name.to_string())
+    }
+}
+
+impl From<MaterialDescription> for MaterialReference {
+    fn from(material: MaterialDescription) -> Self {
+        MaterialReference::Inline(material)
+    }
+}
+
+impl TextureDescription {
+    pub fn solid(r: f32, g: f32, b: f32) -> Self {
+        TextureDescription::Solid { color: [r, g, b] }
+    }
+
+    pub fn checker(odd: impl Into<TextureReference>, even: impl Into<TextureReference>) -> Self {
+        TextureDescription::Checker {
+            odd: Box::new(odd.into()),
+            even: Box::new(even.into()),
+        }
+    }
+}
+
+impl MaterialDescription {
+    pub fn lambertian(albedo: impl Into<TextureReference>) -> Self {
+        MaterialDescription::Lambertian {
+            albedo: albedo.into(),
+        }
+    }
+
+    pub fn metal(albedo: impl Into<TextureReference>, fuzz: f32) -> Self {
+        MaterialDescription::Metal {
+            albedo: albedo.into(),
+            fuzz,
+        }
+    }
+
+    pub fn dielectric(refraction_index: f32) -> Self {
+        MaterialDescription::Dielectric { refraction_index }
+    }
+
+    pub fn diffuse_light(emit: impl Into<TextureReference>) -> Self {
+        MaterialDescription::DiffuseLight { emit: emit.into() }
+    }
+}
+
+impl ObjectDescription {
+    pub fn sphere(center: [f32; 3], radius: f32, material: impl Into<MaterialReference>) -> Self {
+        ObjectDescription::Sphere {
+            center,
+            radius,
+            material: material.into(),
+        }
+    }
+
+    pub fn rect(
+        plane: Plane,
+        (a0, a1): (f32, f32),
+        (b0, b1): (f32, f32),
+        k: f32,
+        material: impl Into<MaterialReference>,
+    ) -> Self {
+        ObjectDescription::Rect {
+            plane,
+            a0,
+            a1,
+            b0,
+            b1,
+            k,
+            material: material.into(),
+        }
+    }
+
+    pub fn cube(min: [f32; 3], max: [f32; 3], material: impl Into<MaterialReference>) -> Self {
+        ObjectDescription::Cube {
+            min,
+            max,
+            material: material.into(),
+        }
+    }
+
+    pub fn constant_medium(
+        boundary: ObjectDescription,
+        density: f32,
+        texture: impl Into<TextureReference>,
+    ) -> Self {
+        ObjectDescription::ConstantMedium {
+            boundary: Box::new(boundary),
+            density,
+            texture: texture.into(),
+        }
+    }
+
+    pub fn translate(self, offset: [f32; 3]) -> Self {
+        ObjectDescription::Translate {
+            offset,
+            object: Box::new(self),
+        }
+    }
+
+    pub fn rotate(self, angle: f32) -> Self {
+        ObjectDescription::Rotate {
+            angle,
+            object: Box::new(self),
+        }
+    }
+
+    pub fn transform(self, transforms: Vec<TransformDescription>) -> Self {
+        ObjectDescription::Transform {
+            transforms,
+            object: Box::new(self),
+        }
+    }
+
+    pub fn flip_face(self) -> Self {
+        ObjectDescription::FlipFace {
+            object: Box::new(self),
+        }
+    }
+
+    pub fn instance(geometry: &str, translate: [f32; 3], rotate: [f32; 3], scale: f32) -> Self {
+        ObjectDescription::Instance {
+            geometry: geometry.to_string(),
+            translate,
+            rotate,
+            scale,
+        }
+    }
+
+    pub fn bvh(objects: Vec<ObjectDescription>) -> Self {
+        ObjectDescription::Bvh {
+            objects,
+            settings: None,
+        }
+    }
+
+    /// Call `f` on this object and all objects nested in it.
+    fn visit_mut(&mut self, f: &mut impl FnMut(&mut ObjectDescription)) {
+        f(self);
+        match self {
+            ObjectDescription::ConstantMedium {
+                boundary: object, ..
+            }
+            | ObjectDescription::Translate { object, .. }
+            | ObjectDescription::Rotate { object, .. }
+            | ObjectDescription::FlipFace { object }
+            | ObjectDescription::Transform { object, .. }
+            | ObjectDescription::Animated { object, .. } => object.visit_mut(f),
+            ObjectDescription::Bvh { objects, .. } | ObjectDescription::List { objects } => {
+                objects.iter_mut().for_each(|o| o.visit_mut(f))
+            }
+            _ => {}
+        }
+    }
+
+    /// Whether this object or an object nested in it is `animated`.
+    fn is_animated(&self) -> bool {
+        match self {
+            ObjectDescription::Animated { .. } => true,
+            ObjectDescription::ConstantMedium {
+                boundary: object, ..
+            }
+            | ObjectDescription::Translate { object, .. }
+            | ObjectDescription::Rotate { object, .. }
+            | ObjectDescription::FlipFace { object }
+            | ObjectDescription::Transform { object, .. } => object.is_animated(),
+            ObjectDescription::Bvh { objects, .. } | ObjectDescription::List { objects } => {
+                objects.iter().any(|o| o.is_animated())
+            }
+            _ => false,
+        }
+    }
+}
+
+impl CameraAnimation {
+    pub fn is_empty(&self) -> bool {
+        self.lookfrom.is_empty()
+            && self.lookat.is_empty()
+            && self.vfov.is_empty()
+            && self.focus_dist.is_empty()
+    }
+
+    /// Replace the animated parameters of the camera with their values at the given time.
+    fn apply(&self, camera: &mut CameraDescription, time: f32) {
+        if let Some(lookfrom) = self.lookfrom.sample(time) {
+            camera.lookfrom = lookfrom;
+        }
+        if let Some(lookat) = self.lookat.sample(time) {
+            camera.lookat = lookat;
+        }
+        if let Some(vfov) = self.vfov.sample(time) {
+            camera.vfov = vfov;
+        }
+        if let Some(focus_dist) = self.focus_dist.sample(time) {
+            camera.focus_dist = focus_dist;
+        }
+    }
+}
+
+impl CameraDescription {
+    pub fn build(&self) -> Result<Camera, SceneError> {
+        let (lookfrom, lookat, vup) = (
+            Vector3::from(self.lookfrom),
+            Vector3::from(self.lookat),
+            Vector3::from(self.vup),
+        );
+        let camera = match &self.lens {
+            Some(lens) => {
+                if lens.focal_length <= 0.0 || lens.f_number <= 0.0 || lens.sensor_width <= 0.0 {
+                    return Err(SceneError::InvalidCamera(
+                        "focal length, f-number and sensor width must be positive".to_string(),
+                    ));
+                }
+                Camera::physical(
+                    lookfrom,
+                    lookat,
+                    vup,
+                    self.aspect_ratio,
+                    self.focus_dist,
+                    lens,
+                )
+            }
+            None => Camera::new(
+                lookfrom,
+                lookat,
+                vup,
+                self.vfov,
+                self.aspect_ratio,
+                self.aperture,
+                self.focus_dist,
+            ),
+        };
+
+        let aperture_shape = match &self.aperture_shape {
+            ApertureDescription::Circle => ApertureShape::Circle,
+            ApertureDescription::Polygon { blades, rotation } => {
+                if *blades < 3 {
+                    return Err(SceneError::InvalidCamera(format!(
+                        "aperture with {} blades",
+                        blades
+                    )));
+                }
+                ApertureShape::Polygon {
+                    blades: *blades,
+                    rotation: *rotation,
+                }
+            }
+            ApertureDescription::Mask { path } => {
+                let mask = ApertureMask::load(path)
+                    .map_err(|err| SceneError::Image(path.clone(), err))?
+                    .ok_or_else(|| {
+                        SceneError::InvalidCamera(format!("aperture mask '{}' is black", path))
+                    })?;
+                ApertureShape::Mask(Arc::new(mask))
+            }
+        };
+
+        let camera = camera
+            .with_shutter(self.time0, self.time1)
+            .with_projection(self.projection)
+            .with_aperture_shape(aperture_shape)
+            .with_cat_eye(self.cat_eye);
+
+        match self.stereo {
+            None => Ok(camera),
+            Some(rig) => {
+                if rig.ipd < 0.0 || rig.convergence_distance.is_some_and(|d| d <= 0.0) {
+                    return Err(SceneError::InvalidCamera(
+                        "stereo rig needs a non-negative ipd and a positive convergence distance"
+                            .to_string(),
+                    ));
+                }
+                Ok(camera.with_stereo(rig))
+            }
+        }
+    }
+}
+
+impl SceneDescription {
+    /// Read a scene description from a JSON file.
+    ///
+    /// Relative image paths inside the scene are resolved against the working directory.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, SceneError> {
+        let data = fs::read_to_string(path)?;
+        Self::from_json(&data)
+    }
+
+    pub fn from_json(data: &str) -> Result<Self, SceneError> {
+        Ok(serde_json::from_str(data)?)
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).unwrap()
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), SceneError> {
+        Ok(fs::write(path, self.to_json())?)
+    }
+
+    /// Use the given settings for all bvh objects in the scene.
+    pub fn set_bvh_settings(&mut self, bvh_settings: BvhSettings) {
+        let objects = self.world.iter_mut().chain(self.lights.iter_mut());
+        for object in objects.chain(self.geometry.values_mut()) {
+            object.visit_mut(&mut |o| {
+                if let ObjectDescription::Bvh { settings, .. } = o {
+                    *settings = Some(bvh_settings);
+                }
+            });
+        }
+    }
+
+    /// Build the camera, world, lights and background color for rendering.
+    pub fn build(&self) -> Result<BuiltScene, SceneError> {
+        Ok(self.build_with_stats()?.0)
+    }
+
+    /// Build the scene and return the statistics of the bvh objects in it.
+    ///
+    /// Animated scenes are built at their first frame.
+    pub fn build_with_stats(&self) -> Result<(BuiltScene, Vec<BvhStats>), SceneError> {
+        Builder::new(self).build(0.0)
+    }
+
+    /// Number of frames of the animation, a scene without animation has a single frame.
+    pub fn frame_count(&self) -> u32 {
+        self.animation.as_ref().map_or(1, |a| a.frames)
+    }
+
+    /// Time of a frame in seconds.
+    pub fn frame_time(&self, frame: u32) -> f32 {
+        self.animation
+            .as_ref()
+            .map_or(0.0, |a| frame as f32 / a.fps)
+    }
+
+    /// Builder for the frames of an animation, sharing everything that does not move between
+    /// the frames.
+    pub fn sequence(&self) -> Result<Sequence<'_>, SceneError> {
+        if let Some(animation) = &self.animation {
+            if animation.frames == 0 || animation.fps <= 0.0 {
+                return Err(SceneError::InvalidAnimation(format!(
+                    "{} frames at {} fps",
+                    animation.frames, animation.fps
+                )));
+            }
+        }
+
+        let mut builder = Builder::new(self);
+        builder.reuse_statics = true;
+        Ok(Sequence { builder })
+    }
+}
+
+/// Builds the frames of an animated scene.
+///
+/// Objects without animated parts, including their bvhs, are built with the first frame and
+/// reused by the following ones. Animated objects only get a new placement of their shared
+/// object, and the bvhs containing them are refit, see [`Bvh::refit`]. A frame can only refit
+/// the bvhs of the previous frame once that has been dropped, otherwise they are rebuilt.
+pub struct Sequence<'a> {
+    builder: Builder<'a>,
+}
+
+impl Sequence<'_> {
+    pub fn frame_count(&self) -> u32 {
+        self.builder.scene.frame_count()
+    }
+
+    /// Build a frame and return the statistics of the bvh objects built for it.
+    pub fn frame(&mut self, frame: u32) -> Result<(BuiltScene, Vec<BvhStats>), SceneError> {
+        let time = self.builder.scene.frame_time(frame);
+        self.builder.build(time)
+    }
+}
+
+/// Turns descriptions into hittables, caching named textures and materials.
+struct Builder<'a> {
+    scene: &'a SceneDescription,
+    textures: BTreeMap<String, Arc<dyn Texture>>,
+    materials: BTreeMap<String, Arc<dyn Material>>,
+    geometry: BTreeMap<String, Arc<dyn Hittable>>,
+    resolving: Vec<String>,
+    resolving_geometry: Vec<String>,
+    bvh_stats: Vec<BvhStats>,
+    /// Shutter interval of the frame being built.
+    shutter: (f32, f32),
+    /// Keep objects without animated parts for the following frames.
+    reuse_statics: bool,
+    /// Objects without animated parts, by the address of their description.
+    statics: HashMap<*const ObjectDescription, Arc<dyn Hittable>>,
+    /// Whether the object being built is part of a static object.
+    building_static: bool,
+    /// Bvhs with animated objects of the last frame, refit for the next one.
+    moving_bvhs: HashMap<*const ObjectDescription, Arc<Bvh>>,
+    /// Ids of the inline materials, numbered after the named ones.
+    material_ids: HashMap<*const MaterialDescription, u32>,
+}
+
+impl<'a> Builder<'a> {
+    fn new(scene: &'a SceneDescription) -> Self {
+        Self {
+            scene,
+            textures: BTreeMap::new(),
+            materials: BTreeMap::new(),
+            geometry: BTreeMap::new(),
+            resolving: Vec::new(),
+            resolving_geometry: Vec::new(),
+            bvh_stats: Vec::new(),
+            shutter: (0.0, 0.0),
+            reuse_statics: false,
+            statics: HashMap::new(),
+            building_static: false,
+            moving_bvhs: HashMap::new(),
+            material_ids: HashMap::new(),
+        }
+    }
+
+    /// Build the scene at the given time in seconds.
+    fn build(&mut self, time: f32) -> Result<(BuiltScene, Vec<BvhStats>), SceneError> {
+        let mut camera = self.scene.camera.clone();
+        if let Some(animation) = &self.scene.animation {
+            animation.camera.apply(&mut camera, time);
+        }
+        // the shutter opens relative to the frame
+        camera.time0 += time;
+        camera.time1 += time;
+        self.shutter = (camera.time0, camera.time1);
+
+        // top level objects are numbered in order for the object id pass
+        let mut world = World::new();
+        for (id, object) in (1..).zip(&self.scene.world) {
+            world.push(ObjectId::new(self.object(object)?, id));
+        }
+
+        let mut lights = World::new();
+        for object in &self.scene.lights {
+            lights.push(self.object(object)?);
+        }
+
+        let scene = (
+            camera.build()?,
+            Box::new(world) as Box<dyn Hittable>,
+            Box::new(lights) as Box<dyn Hittable>,
+            Vector3::from(self.scene.background),
+        );
+
+        Ok((scene, std::mem::take(&mut self.bvh_stats)))
+    }
+
+    fn texture(&mut self, texture: &TextureReference) -> Result<Arc<dyn Texture>, SceneError> {
+        match texture {
+            TextureReference::Inline(description) => self.texture_description(description),
+            TextureReference::Named(name) => {
+                if let Some(texture) = self.textures.get(name) {
+                    return Ok(texture.clone());
+                }
+                if self.resolving.contains(name) {
+                    return Err(SceneError::CyclicTexture(name.clone()));
+                }
+                let description = self
+                    .scene
+                    .textures
+                    .get(name)
+                    .ok_or_else(|| SceneError::UnknownTexture(name.clone()))?;
+
+                self.resolving.push(name.clone());
+                let texture = self.texture_description(description);
+                self.resolving.pop();
+
+                let texture = texture?;
+                self.textures.insert(name.clone(), texture.clone());
+                Ok(texture)
+            }
+        }
+    }
+
+    fn texture_description(
+        &mut self,
+        texture: &TextureDescription,
+    ) -> Result<Arc<dyn Texture>, SceneError> {
+        Ok(match texture {
+            TextureDescription::Solid { color } => {
+                Arc::new(SolidColor::new(color[0], color[1], color[2]))
+            }
+            TextureDescription::Checker { odd, even } => {
+                Arc::new(Checker::new(self.texture(odd)?, self.texture(even)?))
+            }
+            TextureDescription::Image { path } => {
+                Arc::new(Image::load(path).map_err(|err| SceneError::Image(path.clone(), err))?)
+            }
+            TextureDescription::Noise { scale } => Arc::new(Noise::new(*scale)),
+            TextureDescription::VertexColor { fallback } => {
+                let fallback: Arc<dyn Texture> = match fallback {
+                    Some(fallback) => self.texture(fallback)?,
+                    None => Arc::new(SolidColor::new(0.8, 0.8, 0.8)),
+                };
+                Arc::new(VertexColor::new(fallback))
+            }
+        })
+    }
+
+    /// Build a material, tagged with its id for the material id pass. Named materials are
+    /// numbered in the order of the table, inline ones after them.
+    fn material(&mut self, material: &MaterialReference) -> Result<Arc<dyn Material>, SceneError> {
+        match material {
+            MaterialReference::Inline(description) => {
+                let next = (self.scene.materials.len() + self.material_ids.len() + 1) as u32;
+                let id = *self
+                    .material_ids
+                    .entry(description as *const _)
+                    .or_insert(next);
+                let material = self.material_description(description)?;
+                Ok(Arc::new(MaterialId::new(material, id)))
+            }
+            MaterialReference::Named(name) => {
+                if let Some(material) = self.materials.get(name) {
+                    return Ok(material.clone());
+                }
+                let (index, description) = self
+                    .scene
+                    .materials
+                    .iter()
+                    .enumerate()
+                    .find_map(|(i, (key, description))| (key == name).then_some((i, description)))
+                    .ok_or_else(|| SceneError::UnknownMaterial(name.clone()))?;
+
+                let material = self.material_description(description)?;
+                let material: Arc<dyn Material> =
+                    Arc::new(MaterialId::new(material, index as u32 + 1));
+                self.materials.insert(name.clone(), material.clone());
+                Ok(material)
+            }
+        }
+    }
+
+    fn material_description(
+        &mut self,
+        material: &MaterialDescription,
+    ) -> Result<Arc<dyn Material>, SceneError> {
+        Ok(match material {
+            MaterialDescription::Lambertian { albedo } => {
+                Arc::new(Lambertian::new(self.texture(albedo)?))
+            }
+            MaterialDescription::Metal { albedo, fuzz } => {
+                Arc::new(Metal::new(self.texture(albedo)?, *fuzz))
+            }
+            MaterialDescription::Dielectric { refraction_index } => {
+                Arc::new(Dielectric::new(*refraction_index))
+            }
+            MaterialDescription::DiffuseLight { emit } => {
+                Arc::new(DiffuseLight::new(self.texture(emit)?))
+            }
+            MaterialDescription::Isotropic { albedo } => {
+                Arc::new(Isotropic::new(self.texture(albedo)?))
+            }
+        })
+    }
+
+    fn geometry(&mut self, name: &str) -> Result<Arc<dyn Hittable>, SceneError> {
+        if let Some(geometry) = self.geometry.get(name) {
+            return Ok(geometry.clone());
+        }
+        if self.resolving_geometry.iter().any(|n| n == name) {
+            return Err(SceneError::CyclicGeometry(name.to_string()));
+        }
+        let description = self
+            .scene
+            .geometry
+            .get(name)
+            .ok_or_else(|| SceneError::UnknownGeometry(name.to_string()))?;
+
+        if description.is_animated() {
+            return Err(SceneError::InvalidAnimation(format!(
+                "geometry '{}' contains an animated object",
+                name
+            )));
+        }
+
+        self.resolving_geometry.push(name.to_string());
+        let geometry = self.static_object(description);
+        self.resolving_geometry.pop();
+
+        let geometry: Arc<dyn Hittable> = Arc::from(geometry?);
+        self.geometry.insert(name.to_string(), geometry.clone());
+        Ok(geometry)
+    }
+
+    fn reusable(&self, object: &ObjectDescription) -> bool {
+        self.reuse_statics && !self.building_static && !object.is_animated()
+    }
+
+    fn object(&mut self, object: &ObjectDescription) -> Result<Box<dyn Hittable>, SceneError> {
+        if self.reusable(object) {
+            Ok(Box::new(self.shared(object)?))
+        } else {
+            self.object_description(object)
+        }
+    }
+
+    /// Build an object that is referenced from other objects, a static object of a sequence is
+    /// only built once.
+    fn shared(&mut self, object: &ObjectDescription) -> Result<Arc<dyn Hittable>, SceneError> {
+        if !self.reusable(object) {
+            return Ok(Arc::from(self.object_description(object)?));
+        }
+
+        let key = object as *const ObjectDescription;
+        if let Some(object) = self.statics.get(&key) {
+            return Ok(object.clone());
+        }
+        let built: Arc<dyn Hittable> = Arc::from(self.static_object(object)?);
+        self.statics.insert(key, built.clone());
+        Ok(built)
+    }
+
+    /// Build an object without animated parts, its nested objects are not shared on their own.
+    fn static_object(
+        &mut self,
+        object: &ObjectDescription,
+    ) -> Result<Box<dyn Hittable>, SceneError> {
+        let building_static = std::mem::replace(&mut self.building_static, true);
+        let built = self.object_description(object);
+        self.building_static = building_static;
+        built
+    }
+
+    fn object_description(
+        &mut self,
+        object: &ObjectDescription,
+    ) -> Result<Box<dyn Hittable>, SceneError> {
+        Ok(match object {
+            ObjectDescription::Sphere {
+                center,
+                radius,
+                material,
+            } => Box::new(Sphere::new(
+                Vector3::from(*center),
+                *radius,
+                self.material(material)?,
+            )),
+            ObjectDescription::MovingSphere {
+                center0,
+                center1,
+                time0,
+                time1,
+                radius,
+                material,
+            } => Box::new(MovingSphere::new(
+                (Vector3::from(*center0), *time0),
+                (Vector3::from(*center1), *time1),
+                *radius,
+                self.material(material)?,
+            )),
+            ObjectDescription::Rect {
+                plane,
+                a0,
+                a1,
+                b0,
+                b1,
+                k,
+                material,
+            } => Box::new(RectAA::new(
+                *plane,
+                *a0,
+                *a1,
+                *b0,
+                *b1,
+                *k,
+                self.material(material)?,
+            )),
+            ObjectDescription::Cube { min, max, material } => Box::new(Cube::new(
+                Vector3::from(*min),
+                Vector3::from(*max),
+                self.material(material)?,
+            )),
+            ObjectDescription::Triangle { vertices, material } => Box::new(Triangle::new(
+                Vector3::from(vertices[0]),
+                Vector3::from(vertices[1]),
+                Vector3::from(vertices[2]),
+                self.material(material)?,
+            )),
+            ObjectDescription::Mesh {
+                vertices,
+                normals,
+                uvs,
+                indices,
+                material,
+            } => Box::new(
+                TriangleMesh::new(
+                    mesh_data(vertices, normals, uvs, indices)?,
+                    self.material(material)?,
+                )
+                .ok_or_else(|| SceneError::InvalidMesh("no faces".to_string()))?,
+            ),
+            ObjectDescription::Obj { path, material } => {
+                let material = match material {
+                    Some(material) => self.material(material)?,
+                    None => Arc::new(Lambertian::new(SolidColor::new(0.8, 0.8, 0.8))),
+                };
+                Box::new(obj::load(path, material)?)
+            }
+            ObjectDescription::Ply { path, material } => {
+                Box::new(ply::load(path, self.material(material)?)?)
+            }
+            ObjectDescription::Stl { path, material } => {
+                Box::new(stl::load(path, self.material(material)?)?)
+            }
+            ObjectDescription::ConstantMedium {
+                boundary,
+                density,
+                texture,
+            } => Box::new(Constant::new(
+                self.object(boundary)?,
+                *density,
+                self.texture(texture)?,
+            )),
+            ObjectDescription::Translate { offset, object } => {
+                Box::new(Translate::new(self.object(object)?, Vector3::from(*offset)))
+            }
+            ObjectDescription::Rotate { angle, object } => {
+                Box::new(Rotate::new(self.object(object)?, *angle))
+            }
+            ObjectDescription::FlipFace { object } => Box::new(FlipFace::new(self.object(object)?)),
+            ObjectDescription::Transform { transforms, object } => {
+                let mut transform = Transform::new(self.object(object)?);
+                for step in transforms {
+                    transform = transform_step(transform, step)?;
+                }
+                Box::new(transform)
+            }
+            ObjectDescription::Instance {
+                geometry,
+                translate,
+                rotate,
+                scale,
+            } => {
+                let transform = placement(geometry, translate, rotate, *scale)?;
+                Box::new(Instance::new(self.geometry(geometry)?, transform))
+            }
+            ObjectDescription::MovingInstance {
+                geometry,
+                keyframes,
+            } => {
+                if keyframes.is_empty() {
+                    return Err(SceneError::InvalidInstance(format!(
+                        "no keyframes for '{}'",
+                        geometry
+                    )));
+                }
+                let keyframes = keyframes
+                    .iter()
+                    .map(|k| {
+                        Ok(Keyframe {
+                            time: k.time,
+                            transform: placement(geometry, &k.translate, &k.rotate, k.scale)?,
+                        })
+                    })
+                    .collect::<Result<_, SceneError>>()?;
+                Box::new(MovingInstance::new(self.geometry(geometry)?, keyframes))
+            }
+            ObjectDescription::Animated {
+                translate,
+                rotate,
+                scale,
+                object,
+            } => {
+                let placement_at = |time| {
+                    placement(
+                        "animated object",
+                        &translate.sample(time).unwrap_or_default(),
+                        &rotate.sample(time).unwrap_or_default(),
+                        scale.sample(time).unwrap_or(1.0),
+                    )
+                };
+                let object = self.shared(object)?;
+                let (time0, time1) = self.shutter;
+                if time1 > time0 {
+                    // moves while the shutter is open
+                    Box::new(MovingInstance::linear(
+                        object,
+                        Keyframe {
+                            time: time0,
+                            transform: placement_at(time0)?,
+                        },
+                        Keyframe {
+                            time: time1,
+                            transform: placement_at(time1)?,
+                        },
+                    ))
+                } else {
+                    Box::new(Instance::new(object, placement_at(time0)?))
+                }
+            }
+            ObjectDescription::Bvh { objects, settings } => {
+                let objects = objects
+                    .iter()
+                    .map(|o| self.object(o))
+                    .collect::<Result<_, _>>()?;
+
+                if !self.reuse_statics || !object.is_animated() {
+                    let bvh = Bvh::with_settings(objects, settings.unwrap_or_default());
+                    self.bvh_stats.push(bvh.stats());
+                    return Ok(Box::new(bvh));
+                }
+
+                // refit the tree of the previous frame, once it is no longer rendered
+                let key = object as *const ObjectDescription;
+                let bvh = match self.moving_bvhs.remove(&key).map(Arc::try_unwrap) {
+                    Some(Ok(mut bvh)) => {
+                        bvh.refit(objects);
+                        bvh
+                    }
+                    _ => Bvh::with_settings(objects, settings.unwrap_or_default()),
+                };
+                self.bvh_stats.push(bvh.stats());
+
+                let bvh = Arc::new(bvh);
+                self.moving_bvhs.insert(key, bvh.clone());
+                Box::new(bvh)
+            }
+            ObjectDescription::List { objects } => {
+                let mut world = World::new();
+                for object in objects {
+                    world.push(self.object(object)?);
+                }
+                Box::new(world)
+            }
+        })
+    }
+}
+
+/// Transform of an instance, rotated about the X, Y and Z axes, scaled and translated.
+fn placement(
+    geometry: &str,
+    translate: &[f32; 3],
+    rotate: &[f32; 3],
+    scale: f32,
+) -> Result<Similarity3<f32>, SceneError> {
+    if scale <= 0.0 {
+        return Err(SceneError::InvalidInstance(format!(
+            "scale {} of '{}' is not positive",
+            scale, geometry
+        )));
+    }
+    let [x, y, z] = rotate.map(f32::to_radians);
+    Ok(Similarity3::from_parts(
+        Translation3::from(Vector3::from(*translate)),
+        UnitQuaternion::from_euler_angles(x, y, z),
+        scale,
+    ))
+}
+
+fn transform_step(
+    transform: Transform<Box<dyn Hittable>>,
+    step: &TransformDescription,
+) -> Result<Transform<Box<dyn Hittable>>, SceneError> {
+    Ok(match step {
+        TransformDescription::Translate(offset) => transform.translate(Vector3::from(*offset)),
+        TransformDescription::Rotate { axis, angle } => {
+            if *axis == [0.0; 3] {
+                return Err(SceneError::InvalidTransform(
+                    "zero rotation axis".to_string(),
+                ));
+            }
+            transform.rotate(Vector3::from(*axis), *angle)
+        }
+        TransformDescription::Scale(factors) => transform
+            .then(Matrix4::new_nonuniform_scaling(&Vector3::from(*factors)))
+            .ok_or_else(|| SceneError::InvalidTransform(format!("scale {:?}", factors)))?,
+        TransformDescription::LookAt { eye, target, up } => transform.look_at(
+            Vector3::from(*eye),
+            Vector3::from(*target),
+            Vector3::from(*up),
+        ),
+        TransformDescription::Matrix(rows) => {
+            transform
+                .then(Matrix4::from_fn(|i, j| rows[i][j]))
+                .ok_or_else(|| SceneError::InvalidTransform("singular matrix".to_string()))?
+        }
+    })
+}
+
+fn mesh_data(
+    vertices: &[[f32; 3]],
+    normals: &[[f32; 3]],
+    uvs: &[[f32; 2]],
+    indices: &[[usize; 3]],
+) -> Result<MeshData, SceneError> {
+    if indices.is_empty() {
+        return Err(SceneError::InvalidMesh("no faces".to_string()));
+    }
+    if !normals.is_empty() && normals.len() != vertices.len() {
+        return Err(SceneError::InvalidMesh(format!(
+            "{} normals for {} vertices",
+            normals.len(),
+            vertices.len()
+        )));
+    }
+    if !uvs.is_empty() && uvs.len() != vertices.len() {
+        return Err(SceneError::InvalidMesh(format!(
+            "{} uvs for {} vertices",
+            uvs.len(),
+            vertices.len()
+        )));
+    }
+    if let Some(i) = indices.iter().flatten().find(|&&i| i >= vertices.len()) {
+        return Err(SceneError::InvalidMesh(format!(
+            "index {} out of range for {} vertices",
+            i,
+            vertices.len()
+        )));
+    }
+
+    Ok(MeshData {
+        vertices: vertices.iter().map(|&v| Vector3::from(v)).collect(),
+        normals: normals
+            .iter()
+            .map(|&n| Vector3::from(n).normalize())
+            .collect(),
+        uvs: uvs.iter().map(|&uv| Vector2::from(uv)).collect(),
+        colors: vec![],
+        indices: indices.to_vec(),
+    })
+}
+
+#[test]
+fn test_sequence() {
+    use crate::ray::Ray;
+
+    let scene = SceneDescription::from_json(
+        r#"{
+            "camera": {
+                "lookfrom": [0, 0, 10], "lookat": [0, 0, 0], "aspect_ratio": 1, "focus_dist": 10
+            },
+            "background": [0, 0, 0],
+            "world": [
+                {"type": "bvh", "objects": [
+                    {"type": "sphere", "center": [-3, 0, 0], "radius": 1, "material": "gray"},
+                    {"type": "sphere", "center": [3, 0, 0], "radius": 1, "material": "gray"}
+                ]},
+                {"type": "bvh", "objects": [
+                    {"type": "animated",
+                     "translate": [
+                        {"time": 0, "value": [0, -2, 0]},
+                        {"time": 1, "value": [0, 2, 0]}
+                     ],
+                     "object": {"type": "sphere", "center": [0, 0, 0], "radius": 0.5, "material": "gray"}}
+                ]}
+            ],
+            "materials": {"gray": {"type": "lambertian", "albedo": {"type": "solid", "color": [0.5, 0.5, 0.5]}}},
+            "animation": {
+                "frames": 5,
+                "fps": 4,
+                "camera": {"lookfrom": [{"time": 0, "value": [0, 0, 10]}, {"time": 1, "value": [0, 0, 20]}]}
+            }
+        }"#,
+    )
+    .unwrap();
+
+    let mut sequence = scene.sequence().unwrap();
+    assert_eq!(sequence.frame_count(), 5);
+
+    for frame in 0..5 {
+        let ((cam, world, _, _), stats) = sequence.frame(frame).unwrap();
+
+        // the static bvh is only built for the first frame, the one with the animated sphere
+        // is refit for every following frame
+        assert_eq!(stats.len(), if frame == 0 { 2 } else { 1 });
+        assert_eq!(stats.last().unwrap().refits, frame as usize);
+
+        let time = frame as f32 / 4.0;
+        let r = cam.ray(0.5, 0.5);
+        assert!((r.ori.z - (10.0 + 10.0 * time)).abs() < 1e-4);
+
+        let y = -2.0 + 4.0 * time;
+        let down = Vector3::new(0.0, -1.0, 0.0);
+        let hit = world.hit(
+            &Ray::new(Vector3::new(0.0, 5.0, 0.0), down),
+            0.001,
+            f32::MAX,
+        );
+        assert!((hit.unwrap().p.y - (y + 0.5)).abs() < 1e-4);
+        let hit = world.hit(
+            &Ray::new(Vector3::new(3.0, 5.0, 0.0), down),
+            0.001,
+            f32::MAX,
+        );
+        assert!((hit.unwrap().p.y - 1.0).abs() < 1e-4);
+    }
+}
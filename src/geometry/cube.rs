@@ -31,7 +31,7 @@ impl Cube {
         Self {
             box_min: p0,
             box_max: p1,
-            sides: sides,
+            sides,
         }
     }
 }
@@ -41,7 +41,7 @@ impl Hittable for Cube {
         Some(AABB::new(self.box_min, self.box_max))
     }
 
-    fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
+    fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
         self.sides.hit(r, t_min, t_max)
     }
 }
@@ -0,0 +1,318 @@
+use crate::bhv::{Bvh, BvhSettings};
+use crate::bounding_box::AABB;
+use crate::geometry::triangle;
+use crate::hit::*;
+use crate::material::Material;
+use crate::ray::Ray;
+use crate::sampler;
+use nalgebra::{Vector2, Vector3};
+use rand::Rng;
+use std::sync::Arc;
+
+/// Shared vertex buffers of a triangle mesh.
+///
+/// Every face holds three indices into the vertex buffers. The normal, texture coordinate and
+/// color buffers are optional, when they are not empty they are indexed like the positions.
+#[derive(Debug, Clone, Default)]
+pub struct MeshData {
+    pub vertices: Vec<Vector3<f32>>,
+    pub normals: Vec<Vector3<f32>>,
+    pub uvs: Vec<Vector2<f32>>,
+    pub colors: Vec<Vector3<f32>>,
+    pub indices: Vec<[usize; 3]>,
+}
+
+impl MeshData {
+    /// Whether there are faces, all indices are in range and the optional buffers are either
+    /// empty or as long as the positions.
+    fn is_valid(&self) -> bool {
+        let count = self.vertices.len();
+        let optional = |len: usize| len == 0 || len == count;
+        !self.indices.is_empty()
+            && self.indices.iter().flatten().all(|&i| i < count)
+            && optional(self.normals.len())
+            && optional(self.uvs.len())
+            && optional(self.colors.len())
+    }
+
+    fn positions(&self, face: usize) -> [Vector3<f32>; 3] {
+        self.indices[face].map(|i| self.vertices[i])
+    }
+
+    fn normals(&self, face: usize) -> Option<[Vector3<f32>; 3]> {
+        if self.normals.is_empty() {
+            None
+        } else {
+            Some(self.indices[face].map(|i| self.normals[i]))
+        }
+    }
+
+    fn uvs(&self, face: usize) -> Option<[Vector2<f32>; 3]> {
+        if self.uvs.is_empty() {
+            None
+        } else {
+            Some(self.indices[face].map(|i| self.uvs[i]))
+        }
+    }
+
+    fn colors(&self, face: usize) -> Option<[Vector3<f32>; 3]> {
+        if self.colors.is_empty() {
+            None
+        } else {
+            Some(self.indices[face].map(|i| self.colors[i]))
+        }
+    }
+}
+
+/// A single face of a mesh, referencing the shared buffers.
+struct MeshTriangle<M: Material> {
+    mesh: Arc<MeshData>,
+    material: Arc<M>,
+    face: usize,
+    /// Total area of the mesh, used for the light sampling pdf.
+    area: f32,
+}
+
+impl<M: Material> Hittable for MeshTriangle<M> {
+    fn bounding_box(&self) -> Option<AABB> {
+        Some(triangle::bounding_box(&self.mesh.positions(self.face)))
+    }
+
+    fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        let p = self.mesh.positions(self.face);
+        let hit = triangle::intersect(r, &p, t_min, t_max)?;
+        Some(triangle::hit_record(
+            r,
+            &p,
+            self.mesh.normals(self.face),
+            self.mesh.uvs(self.face),
+            self.mesh.colors(self.face),
+            hit,
+            self.material.as_ref(),
+        ))
+    }
+
+    fn pdf_value(&self, origin: Vector3<f32>, direction: Vector3<f32>) -> f32 {
+        let p = self.mesh.positions(self.face);
+        match triangle::intersect(&Ray::new(origin, direction), &p, 0.001, f32::MAX) {
+            None => 0.0,
+            Some((t, _, _)) => triangle::area_pdf(t, &triangle::normal(&p), direction, self.area),
+        }
+    }
+}
+
+/// Indexed triangle mesh with an internal SAH bvh over its faces.
+///
+/// Vertex normals, when present, are interpolated for smooth shading. As a light the mesh is
+/// sampled uniformly by area.
+pub struct TriangleMesh {
+    mesh: Arc<MeshData>,
+    bvh: Bvh,
+    /// Cumulative face areas, used to pick a face proportional to its area.
+    cdf: Vec<f32>,
+}
+
+impl TriangleMesh {
+    /// A mesh of the faces of the data, `None` without faces, with an index out of range or
+    /// with a normal, texture coordinate or color buffer that is neither empty nor as long as
+    /// the positions.
+    pub fn new<M: Material + 'static>(mesh: MeshData, material: M) -> Option<Self> {
+        if !mesh.is_valid() {
+            return None;
+        }
+
+        let mesh = Arc::new(mesh);
+        let material = Arc::new(material);
+
+        let cdf: Vec<f32> = (0..mesh.indices.len())
+            .scan(0.0, |total, face| {
+                *total += triangle::area(&mesh.positions(face));
+                Some(*total)
+            })
+            .collect();
+        let area = *cdf.last().unwrap();
+
+        let faces: Vec<Box<dyn Hittable>> = (0..mesh.indices.len())
+            .map(|face| {
+                Box::new(MeshTriangle {
+                    mesh: mesh.clone(),
+                    material: material.clone(),
+                    face,
+                    area,
+                }) as Box<dyn Hittable>
+            })
+            .collect();
+
+        Some(Self {
+            mesh,
+            bvh: Bvh::with_settings(faces, BvhSettings::sah()),
+            cdf,
+        })
+    }
+
+    pub fn data(&self) -> &MeshData {
+        &self.mesh
+    }
+
+    pub fn area(&self) -> f32 {
+        *self.cdf.last().unwrap()
+    }
+
+    /// Index of a face picked proportional to its area.
+    fn random_face(&self) -> usize {
+        let x = sampler::rng().gen::<f32>() * self.area();
+        self.cdf
+            .partition_point(|&a| a <= x)
+            .min(self.cdf.len() - 1)
+    }
+}
+
+impl Hittable for TriangleMesh {
+    fn bounding_box(&self) -> Option<AABB> {
+        self.bvh.bounding_box()
+    }
+
+    fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        self.bvh.hit(r, t_min, t_max)
+    }
+
+    fn pdf_value(&self, origin: Vector3<f32>, direction: Vector3<f32>) -> f32 {
+        // points are sampled on all faces, also on those behind the closest one, so the
+        // densities of all faces along the ray add up
+        let ray = Ray::new(origin, direction);
+        let mut pdf = 0.0;
+        let mut t_min = 0.001;
+        while let Some((i, hit)) = self.bvh.closest_hit(&ray, t_min, f32::MAX) {
+            pdf += self.bvh.object(i).pdf_value(origin, direction);
+            t_min = hit.t.next_up();
+        }
+        pdf
+    }
+
+    fn random(&self, origin: Vector3<f32>) -> Vector3<f32> {
+        triangle::random_point(&self.mesh.positions(self.random_face())) - origin
+    }
+
+    fn sample_surface(&self) -> Option<SurfaceSample> {
+        let p = self.mesh.positions(self.random_face());
+        Some(SurfaceSample {
+            p: triangle::random_point(&p),
+            n: triangle::normal(&p),
+            pdf: 1.0 / self.area(),
+        })
+    }
+
+    fn surface_pdf(&self, origin: Vector3<f32>, direction: Vector3<f32>) -> f32 {
+        match self.hit(&Ray::new(origin, direction), 0.001, f32::MAX) {
+            None => 0.0,
+            Some(_) => 1.0 / self.area(),
+        }
+    }
+}
+
+#[test]
+fn test_mesh_light_pdf() {
+    use crate::material::{DiffuseLight, Lambertian};
+    use crate::texture::SolidColor;
+
+    // unit quad in the xz plane at y = 1, split in two faces
+    let mesh = MeshData {
+        vertices: vec![
+            Vector3::new(0.0, 1.0, 0.0),
+            Vector3::new(1.0, 1.0, 0.0),
+            Vector3::new(1.0, 1.0, 1.0),
+            Vector3::new(0.0, 1.0, 1.0),
+        ],
+        normals: vec![Vector3::new(0.0, -1.0, 0.0); 4],
+        indices: vec![[0, 1, 2], [0, 2, 3]],
+        ..Default::default()
+    };
+
+    // no faces, an index out of range and buffers shorter than the positions are rejected
+    let invalid = [
+        MeshData::default(),
+        MeshData {
+            indices: vec![[0, 2, 4]],
+            ..mesh.clone()
+        },
+        MeshData {
+            normals: vec![Vector3::new(0.0, -1.0, 0.0); 3],
+            ..mesh.clone()
+        },
+        MeshData {
+            uvs: vec![Vector2::zeros(); 2],
+            ..mesh.clone()
+        },
+        MeshData {
+            colors: vec![Vector3::zeros(); 5],
+            ..mesh.clone()
+        },
+    ];
+    for mesh in invalid {
+        let material = Lambertian::new(SolidColor::new(1.0, 1.0, 1.0));
+        assert!(TriangleMesh::new(mesh, material).is_none());
+    }
+
+    let light = TriangleMesh::new(mesh, DiffuseLight::new(SolidColor::new(1.0, 1.0, 1.0))).unwrap();
+    assert!((light.area() - 1.0).abs() < 1e-6);
+
+    // straight up from below the center: distance 1, cosine 1, area 1
+    let origin = Vector3::new(0.5, 0.0, 0.5);
+    let pdf = light.pdf_value(origin, Vector3::new(0.0, 1.0, 0.0));
+    assert!((pdf - 1.0).abs() < 1e-4);
+
+    for _ in 0..16 {
+        let direction = light.random(origin);
+        assert!((direction.y - 1.0).abs() < 1e-6);
+        assert!(light.pdf_value(origin, direction) > 0.0);
+    }
+}
+
+#[test]
+fn test_closed_mesh_light_pdf() {
+    use crate::material::DiffuseLight;
+    use crate::texture::SolidColor;
+
+    // unit cube, every ray through it crosses two faces
+    let vertices = (0..8)
+        .map(|i| Vector3::new((i & 1) as f32, (i >> 1 & 1) as f32, (i >> 2) as f32))
+        .collect();
+    let indices = vec![
+        [0, 2, 3],
+        [0, 3, 1],
+        [4, 5, 7],
+        [4, 7, 6],
+        [0, 1, 5],
+        [0, 5, 4],
+        [2, 6, 7],
+        [2, 7, 3],
+        [0, 4, 6],
+        [0, 6, 2],
+        [1, 3, 7],
+        [1, 7, 5],
+    ];
+    let mesh = MeshData {
+        vertices,
+        indices,
+        ..Default::default()
+    };
+    let light = TriangleMesh::new(mesh, DiffuseLight::new(SolidColor::new(1.0, 1.0, 1.0))).unwrap();
+    assert!((light.area() - 6.0).abs() < 1e-5);
+
+    // the pdf of the sampled directions integrates to one over all directions, evaluated on a
+    // fibonacci sphere
+    let origin = Vector3::new(0.3, 0.6, -1.5);
+    let n = 100_000;
+    let integral = (0..n)
+        .map(|i| {
+            let z = 1.0 - (2 * i + 1) as f32 / n as f32;
+            let phi = i as f32 * std::f32::consts::PI * (3.0 - 5.0f32.sqrt());
+            let r = (1.0 - z * z).sqrt();
+            light.pdf_value(origin, Vector3::new(r * phi.cos(), r * phi.sin(), z))
+        })
+        .sum::<f32>()
+        * 4.0
+        * std::f32::consts::PI
+        / n as f32;
+    assert!((integral - 1.0).abs() < 0.02, "{}", integral);
+}
@@ -3,7 +3,10 @@ use crate::hit::*;
 use crate::material::Material;
 use crate::ray::Ray;
 use nalgebra::Vector3;
+use serde::{Deserialize, Serialize};
 
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum Plane {
     XY,
     XZ,
@@ -21,17 +24,17 @@ pub struct RectAA<M: Material> {
 }
 
 impl<M: Material> RectAA<M> {
-    // pub fn new(plane: Plane, a0: f32, a1: f32, b0: f32, b1: f32, k: f32, material: M) -> Self {
-    //     Self {
-    //         plane,
-    //         a0,
-    //         a1,
-    //         b0,
-    //         b1,
-    //         k,
-    //         material,
-    //     }
-    // }
+    pub fn new(plane: Plane, a0: f32, a1: f32, b0: f32, b1: f32, k: f32, material: M) -> Self {
+        Self {
+            plane,
+            a0,
+            a1,
+            b0,
+            b1,
+            k,
+            material,
+        }
+    }
 
     pub fn xy(x0: f32, x1: f32, y0: f32, y1: f32, k: f32, material: M) -> Self {
         Self {
@@ -68,6 +71,38 @@ impl<M: Material> RectAA<M> {
             material,
         }
     }
+
+    fn area(&self) -> f32 {
+        (self.a1 - self.a0) * (self.b1 - self.b0)
+    }
+
+    fn normal(&self) -> Vector3<f32> {
+        match &self.plane {
+            Plane::XY => Vector3::new(0.0, 0.0, 1.0),
+            Plane::XZ => Vector3::new(0.0, 1.0, 0.0),
+            Plane::YZ => Vector3::new(1.0, 0.0, 0.0),
+        }
+    }
+
+    fn random_point(&self) -> Vector3<f32> {
+        match &self.plane {
+            Plane::XY => Vector3::new(
+                random_double(self.a0, self.a1),
+                random_double(self.b0, self.b1),
+                self.k,
+            ),
+            Plane::XZ => Vector3::new(
+                random_double(self.a0, self.a1),
+                self.k,
+                random_double(self.b0, self.b1),
+            ),
+            Plane::YZ => Vector3::new(
+                self.k,
+                random_double(self.a0, self.a1),
+                random_double(self.b0, self.b1),
+            ),
+        }
+    }
 }
 
 impl<M: Material> Hittable for RectAA<M> {
@@ -88,7 +123,7 @@ impl<M: Material> Hittable for RectAA<M> {
         }
     }
 
-    fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
+    fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
         let t = match &self.plane {
             Plane::XY => (self.k - r.ori.z) / r.dir.z,
             Plane::XZ => (self.k - r.ori.y) / r.dir.y,
@@ -127,13 +162,15 @@ impl<M: Material> Hittable for RectAA<M> {
         let p = r.point_at(t);
 
         let mut h = HitRecord {
-            t: t,
-            p: p,
+            t,
+            p,
             n: on,
             m: &self.material,
             front_face: false,
             u,
             v,
+            color: None,
+            object_id: 0,
         };
 
         h.set_face_normal(r, &on);
@@ -145,7 +182,7 @@ impl<M: Material> Hittable for RectAA<M> {
         match self.hit(&Ray::new(origin, direction), 0.001, f32::MAX) {
             None => 0.0,
             Some(hit) => {
-                let area = (self.a1 - self.a0) * (self.b1 - self.b0);
+                let area = self.area();
                 let distance_squared = hit.t * hit.t * direction.magnitude_squared();
                 let cosine = direction.dot(&hit.n).abs() / direction.magnitude();
                 distance_squared / (cosine * area)
@@ -154,24 +191,21 @@ impl<M: Material> Hittable for RectAA<M> {
     }
 
     fn random(&self, origin: Vector3<f32>) -> Vector3<f32> {
-        let random_point = match &self.plane {
-            Plane::XY => Vector3::new(
-                random_double(self.a0, self.a1),
-                random_double(self.b0, self.b1),
-                self.k,
-            ),
-            Plane::XZ => Vector3::new(
-                random_double(self.a0, self.a1),
-                self.k,
-                random_double(self.b0, self.b1),
-            ),
-            Plane::YZ => Vector3::new(
-                self.k,
-                random_double(self.a0, self.a1),
-                random_double(self.b0, self.b1),
-            ),
-        };
+        self.random_point() - origin
+    }
+
+    fn sample_surface(&self) -> Option<SurfaceSample> {
+        Some(SurfaceSample {
+            p: self.random_point(),
+            n: self.normal(),
+            pdf: 1.0 / self.area(),
+        })
+    }
 
-        random_point - origin
+    fn surface_pdf(&self, origin: Vector3<f32>, direction: Vector3<f32>) -> f32 {
+        match self.hit(&Ray::new(origin, direction), 0.001, f32::MAX) {
+            None => 0.0,
+            Some(_) => 1.0 / self.area(),
+        }
     }
 }
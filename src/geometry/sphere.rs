@@ -1,8 +1,9 @@
 use crate::bounding_box::AABB;
 use crate::hit::*;
-use crate::material::Material;
+use crate::material::{random_unit_vector, Material};
 use crate::pdf::Onb;
 use crate::ray::Ray;
+use crate::sampler;
 use nalgebra::Vector3;
 use rand::Rng;
 
@@ -21,24 +22,9 @@ impl<M: Material> Sphere<M> {
             material,
         }
     }
-}
-
-impl<M: Material> Sphere<M> {
-    fn get_uv(p: &Vector3<f32>) -> (f32, f32) {
-        // p: a given point on the sphere of radius one, centered at the origin.
-        // u: returned value [0,1] of angle around the Y axis from X=-1.
-        // v: returned value [0,1] of angle from Y=-1 to Y=+1.
-        //     <1 0 0> yields <0.50 0.50>       <-1  0  0> yields <0.00 0.50>
-        //     <0 1 0> yields <0.50 1.00>       < 0 -1  0> yields <0.50 0.00>
-        //     <0 0 1> yields <0.25 0.50>       < 0  0 -1> yields <0.75 0.50>
-
-        let theta = f32::acos(-p.y);
-        let phi = f32::atan2(-p.z, p.x) + std::f32::consts::PI;
-
-        let u = phi / (2.0 * std::f32::consts::PI);
-        let v = theta / std::f32::consts::PI;
 
-        (u, v)
+    fn area(&self) -> f32 {
+        4.0 * std::f32::consts::PI * self.radius * self.radius
     }
 }
 
@@ -50,54 +36,8 @@ impl<M: Material> Hittable for Sphere<M> {
         ))
     }
 
-    fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
-        let oc: Vector3<f32> = r.ori - self.center;
-
-        // calculate the intersections
-        let a: f32 = r.dir.magnitude_squared();
-        let b_half: f32 = oc.dot(&r.dir);
-        let c: f32 = oc.magnitude_squared() - self.radius * self.radius;
-
-        let d = b_half * b_half - a * c;
-
-        if d < 0.0 {
-            return None;
-        }
-
-        let d_sqrt = d.sqrt();
-
-        // find the nearest root that lies in the acceptable range.
-        let mut t = (-b_half - d_sqrt) / a;
-
-        if t > t_max || t < t_min {
-            t = (-b_half + d_sqrt) / a;
-            if t > t_max || t < t_min {
-                return None;
-            }
-        }
-
-        // precalculate outputs
-        let p: Vector3<f32> = r.point_at(t);
-        let on: Vector3<f32> = (p - self.center) / self.radius;
-
-        // get uv coordinates
-        let (u, v) = Sphere::<M>::get_uv(&on);
-
-        let mut h = HitRecord {
-            t,
-            p,
-            n: on,
-            m: &self.material,
-            front_face: false,
-            u,
-            v,
-        };
-
-        h.set_face_normal(r, &on);
-
-        let h = h;
-
-        Some(h)
+    fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        hit_sphere(self.center, self.radius, &self.material, r, t_min, t_max)
     }
 
     fn pdf_value(&self, o: Vector3<f32>, v: Vector3<f32>) -> f32 {
@@ -120,10 +60,159 @@ impl<M: Material> Hittable for Sphere<M> {
         let uvw = Onb::build_from_w(direction);
         uvw.local(&random_to_sphere(self.radius, distance_squared))
     }
+
+    fn sample_surface(&self) -> Option<SurfaceSample> {
+        let n = random_unit_vector();
+        Some(SurfaceSample {
+            p: self.center + self.radius * n,
+            n,
+            pdf: 1.0 / self.area(),
+        })
+    }
+
+    fn surface_pdf(&self, origin: Vector3<f32>, direction: Vector3<f32>) -> f32 {
+        match self.hit(&Ray::new(origin, direction), 0.001, f32::MAX) {
+            None => 0.0,
+            Some(_) => 1.0 / self.area(),
+        }
+    }
+}
+
+/// A sphere moving linearly from `center0` at `time0` to `center1` at `time1`. Before `time0`
+/// and after `time1` the sphere does not move.
+#[derive(Copy, Clone)]
+pub struct MovingSphere<M: Material> {
+    center0: Vector3<f32>,
+    center1: Vector3<f32>,
+    time0: f32,
+    time1: f32,
+    radius: f32,
+    material: M,
+}
+
+impl<M: Material> MovingSphere<M> {
+    pub fn new(
+        (center0, time0): (Vector3<f32>, f32),
+        (center1, time1): (Vector3<f32>, f32),
+        radius: f32,
+        material: M,
+    ) -> Self {
+        Self {
+            center0,
+            center1,
+            time0,
+            time1,
+            radius,
+            material,
+        }
+    }
+
+    pub fn center(&self, time: f32) -> Vector3<f32> {
+        if self.time1 == self.time0 {
+            return self.center0;
+        }
+        let s = ((time - self.time0) / (self.time1 - self.time0)).clamp(0.0, 1.0);
+        self.center0 + s * (self.center1 - self.center0)
+    }
+}
+
+impl<M: Material> Hittable for MovingSphere<M> {
+    fn bounding_box(&self) -> Option<AABB> {
+        // the center never leaves the segment between both centers
+        let radius = Vector3::repeat(self.radius);
+        let box0 = AABB::new(self.center0 - radius, self.center0 + radius);
+        let box1 = AABB::new(self.center1 - radius, self.center1 + radius);
+        Some(box0.extend_box(&box1))
+    }
+
+    fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        hit_sphere(
+            self.center(r.time),
+            self.radius,
+            &self.material,
+            r,
+            t_min,
+            t_max,
+        )
+    }
+}
+
+fn hit_sphere<'a>(
+    center: Vector3<f32>,
+    radius: f32,
+    material: &'a dyn Material,
+    r: &Ray,
+    t_min: f32,
+    t_max: f32,
+) -> Option<HitRecord<'a>> {
+    let oc: Vector3<f32> = r.ori - center;
+
+    // calculate the intersections
+    let a: f32 = r.dir.magnitude_squared();
+    let b_half: f32 = oc.dot(&r.dir);
+    let c: f32 = oc.magnitude_squared() - radius * radius;
+
+    let d = b_half * b_half - a * c;
+
+    if d < 0.0 {
+        return None;
+    }
+
+    let d_sqrt = d.sqrt();
+
+    // find the nearest root that lies in the acceptable range.
+    let mut t = (-b_half - d_sqrt) / a;
+
+    if t > t_max || t < t_min {
+        t = (-b_half + d_sqrt) / a;
+        if t > t_max || t < t_min {
+            return None;
+        }
+    }
+
+    // precalculate outputs
+    let p: Vector3<f32> = r.point_at(t);
+    let on: Vector3<f32> = (p - center) / radius;
+
+    // get uv coordinates
+    let (u, v) = get_uv(&on);
+
+    let mut h = HitRecord {
+        t,
+        p,
+        n: on,
+        m: material,
+        front_face: false,
+        u,
+        v,
+        color: None,
+        object_id: 0,
+    };
+
+    h.set_face_normal(r, &on);
+
+    Some(h)
+}
+
+fn get_uv(p: &Vector3<f32>) -> (f32, f32) {
+    // p: a given point on the sphere of radius one, centered at the origin.
+    // u: returned value [0,1] of angle around the Y axis from X=-1.
+    // v: returned value [0,1] of angle from Y=-1 to Y=+1.
+    //     <1 0 0> yields <0.50 0.50>       <-1  0  0> yields <0.00 0.50>
+    //     <0 1 0> yields <0.50 1.00>       < 0 -1  0> yields <0.50 0.00>
+    //     <0 0 1> yields <0.25 0.50>       < 0  0 -1> yields <0.75 0.50>
+
+    let theta = f32::acos(-p.y);
+    let phi = f32::atan2(-p.z, p.x) + std::f32::consts::PI;
+
+    let u = phi / (2.0 * std::f32::consts::PI);
+    let v = theta / std::f32::consts::PI;
+
+    (u, v)
 }
 
 fn random_to_sphere(radius: f32, distance_squared: f32) -> Vector3<f32> {
-    let mut rng = rand::thread_rng();
+    let mut rng = sampler::rng();
     let (r1, r2) = rng.gen::<(f32, f32)>();
 
     let z = 1.0 + r2 * ((1.0 - radius * radius / distance_squared).sqrt() - 1.0);
@@ -134,3 +223,30 @@ fn random_to_sphere(radius: f32, distance_squared: f32) -> Vector3<f32> {
 
     Vector3::new(x, y, z)
 }
+
+#[test]
+fn test_moving_sphere() {
+    use crate::material::Lambertian;
+    use crate::texture::SolidColor;
+
+    let material = Lambertian::new(SolidColor::new(0.5, 0.5, 0.5));
+    let sphere = MovingSphere::new(
+        (Vector3::new(0.0, 0.0, 0.0), 0.0),
+        (Vector3::new(0.0, 2.0, 0.0), 1.0),
+        0.5,
+        material,
+    );
+    assert_eq!(sphere.center(0.5), Vector3::new(0.0, 1.0, 0.0));
+
+    // outside of the interval, as for the frames of an animation, the sphere rests at its ends
+    // and stays inside the bounds
+    assert_eq!(sphere.center(-1.0), Vector3::new(0.0, 0.0, 0.0));
+    assert_eq!(sphere.center(3.0), Vector3::new(0.0, 2.0, 0.0));
+    let r = Ray::with_time(
+        Vector3::new(0.0, 2.0, 5.0),
+        Vector3::new(0.0, 0.0, -1.0),
+        3.0,
+    );
+    assert!(sphere.hit(&r, 0.001, f32::MAX).is_some());
+    assert!(sphere.bounding_box().unwrap().hit(&r, 0.001, f32::MAX));
+}
@@ -0,0 +1,240 @@
+use crate::bounding_box::AABB;
+use crate::hit::*;
+use crate::material::Material;
+use crate::ray::Ray;
+use crate::sampler;
+use nalgebra::{Vector2, Vector3};
+use rand::Rng;
+
+/// Möller–Trumbore ray/triangle intersection.
+///
+/// Returns the ray parameter and the barycentric coordinates of the second and third vertex.
+pub(crate) fn intersect(
+    r: &Ray,
+    p: &[Vector3<f32>; 3],
+    t_min: f32,
+    t_max: f32,
+) -> Option<(f32, f32, f32)> {
+    let e1 = p[1] - p[0];
+    let e2 = p[2] - p[0];
+
+    let pvec = r.dir.cross(&e2);
+    let det = e1.dot(&pvec);
+
+    // ray parallel to the triangle plane
+    if det == 0.0 {
+        return None;
+    }
+
+    let inv_det = 1.0 / det;
+    let tvec = r.ori - p[0];
+    let b1 = tvec.dot(&pvec) * inv_det;
+    if !(0.0..=1.0).contains(&b1) {
+        return None;
+    }
+
+    let qvec = tvec.cross(&e1);
+    let b2 = r.dir.dot(&qvec) * inv_det;
+    if b2 < 0.0 || b1 + b2 > 1.0 {
+        return None;
+    }
+
+    let t = e2.dot(&qvec) * inv_det;
+    if t < t_min || t > t_max {
+        return None;
+    }
+
+    Some((t, b1, b2))
+}
+
+/// Bounding box of a triangle, padded along flat axes like `RectAA`.
+pub(crate) fn bounding_box(p: &[Vector3<f32>; 3]) -> AABB {
+    let min = p[0].inf(&p[1]).inf(&p[2]);
+    let max = p[0].sup(&p[1]).sup(&p[2]);
+    let pad = (max - min).map(|d| if d < 0.0002 { 0.0001 } else { 0.0 });
+    AABB::new(min - pad, max + pad)
+}
+
+pub(crate) fn area(p: &[Vector3<f32>; 3]) -> f32 {
+    0.5 * (p[1] - p[0]).cross(&(p[2] - p[0])).magnitude()
+}
+
+pub(crate) fn normal(p: &[Vector3<f32>; 3]) -> Vector3<f32> {
+    (p[1] - p[0]).cross(&(p[2] - p[0])).normalize()
+}
+
+/// Uniformly distributed point on the triangle.
+pub(crate) fn random_point(p: &[Vector3<f32>; 3]) -> Vector3<f32> {
+    let mut rng = sampler::rng();
+    let (r1, r2) = rng.gen::<(f32, f32)>();
+    let s = r1.sqrt();
+
+    (1.0 - s) * p[0] + s * (1.0 - r2) * p[1] + s * r2 * p[2]
+}
+
+/// Solid angle pdf of hitting a surface point at ray parameter `t` along `direction`, when
+/// points are picked uniformly on a surface with the given total area.
+pub(crate) fn area_pdf(t: f32, normal: &Vector3<f32>, direction: Vector3<f32>, area: f32) -> f32 {
+    let distance_squared = t * t * direction.magnitude_squared();
+    let cosine = direction.dot(normal).abs() / direction.magnitude();
+    distance_squared / (cosine * area)
+}
+
+/// Fill in a hit record from the barycentric coordinates of a triangle hit.
+///
+/// The face orientation follows the geometric normal; the shading normal is interpolated
+/// from the vertex normals when available.
+pub(crate) fn hit_record<'a>(
+    r: &Ray,
+    p: &[Vector3<f32>; 3],
+    normals: Option<[Vector3<f32>; 3]>,
+    uvs: Option<[Vector2<f32>; 3]>,
+    colors: Option<[Vector3<f32>; 3]>,
+    (t, b1, b2): (f32, f32, f32),
+    m: &'a dyn Material,
+) -> HitRecord<'a> {
+    let b0 = 1.0 - b1 - b2;
+
+    let geometric_normal = normal(p);
+
+    let uv = match uvs {
+        Some(uv) => b0 * uv[0] + b1 * uv[1] + b2 * uv[2],
+        None => Vector2::new(b1, b2),
+    };
+
+    let mut h = HitRecord {
+        t,
+        p: r.point_at(t),
+        n: geometric_normal,
+        m,
+        front_face: false,
+        u: uv.x,
+        v: uv.y,
+        color: colors.map(|c| b0 * c[0] + b1 * c[1] + b2 * c[2]),
+        object_id: 0,
+    };
+
+    h.set_face_normal(r, &geometric_normal);
+
+    if let Some(n) = normals {
+        let shading_normal = (b0 * n[0] + b1 * n[1] + b2 * n[2]).normalize();
+        h.n = if h.front_face {
+            shading_normal
+        } else {
+            -shading_normal
+        };
+    }
+
+    h
+}
+
+pub struct Triangle<M: Material> {
+    vertices: [Vector3<f32>; 3],
+    normals: Option<[Vector3<f32>; 3]>,
+    uvs: Option<[Vector2<f32>; 3]>,
+    material: M,
+}
+
+impl<M: Material> Triangle<M> {
+    /// Flat shaded triangle, the texture coordinates are the barycentric coordinates.
+    pub fn new(v0: Vector3<f32>, v1: Vector3<f32>, v2: Vector3<f32>, material: M) -> Self {
+        Self {
+            vertices: [v0, v1, v2],
+            normals: None,
+            uvs: None,
+            material,
+        }
+    }
+
+    /// Smooth shaded triangle interpolating the given vertex normals.
+    pub fn with_normals(mut self, normals: [Vector3<f32>; 3]) -> Self {
+        self.normals = Some(normals.map(|n| n.normalize()));
+        self
+    }
+
+    /// Interpolate the given texture coordinates instead of using barycentric coordinates.
+    pub fn with_uvs(mut self, uvs: [Vector2<f32>; 3]) -> Self {
+        self.uvs = Some(uvs);
+        self
+    }
+}
+
+impl<M: Material> Hittable for Triangle<M> {
+    fn bounding_box(&self) -> Option<AABB> {
+        Some(bounding_box(&self.vertices))
+    }
+
+    fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        let hit = intersect(r, &self.vertices, t_min, t_max)?;
+        Some(hit_record(
+            r,
+            &self.vertices,
+            self.normals,
+            self.uvs,
+            None,
+            hit,
+            &self.material,
+        ))
+    }
+
+    fn pdf_value(&self, origin: Vector3<f32>, direction: Vector3<f32>) -> f32 {
+        match intersect(
+            &Ray::new(origin, direction),
+            &self.vertices,
+            0.001,
+            f32::MAX,
+        ) {
+            None => 0.0,
+            Some((t, _, _)) => {
+                area_pdf(t, &normal(&self.vertices), direction, area(&self.vertices))
+            }
+        }
+    }
+
+    fn random(&self, origin: Vector3<f32>) -> Vector3<f32> {
+        random_point(&self.vertices) - origin
+    }
+
+    fn sample_surface(&self) -> Option<SurfaceSample> {
+        Some(SurfaceSample {
+            p: random_point(&self.vertices),
+            n: normal(&self.vertices),
+            pdf: 1.0 / area(&self.vertices),
+        })
+    }
+
+    fn surface_pdf(&self, origin: Vector3<f32>, direction: Vector3<f32>) -> f32 {
+        match intersect(
+            &Ray::new(origin, direction),
+            &self.vertices,
+            0.001,
+            f32::MAX,
+        ) {
+            None => 0.0,
+            Some(_) => 1.0 / area(&self.vertices),
+        }
+    }
+}
+
+#[test]
+fn test_triangle_hit() {
+    use crate::material::Lambertian;
+    use crate::texture::SolidColor;
+
+    let triangle = Triangle::new(
+        Vector3::new(0.0, 0.0, 0.0),
+        Vector3::new(1.0, 0.0, 0.0),
+        Vector3::new(0.0, 1.0, 0.0),
+        Lambertian::new(SolidColor::new(0.5, 0.5, 0.5)),
+    );
+
+    let r = Ray::new(Vector3::new(0.25, 0.5, 1.0), Vector3::new(0.0, 0.0, -1.0));
+    let hit = triangle.hit(&r, 0.001, f32::MAX).unwrap();
+    assert!((hit.t - 1.0).abs() < 1e-6);
+    assert!((hit.u - 0.25).abs() < 1e-6 && (hit.v - 0.5).abs() < 1e-6);
+    assert!(hit.front_face);
+    assert_eq!(hit.n, Vector3::new(0.0, 0.0, 1.0));
+
+    let r = Ray::new(Vector3::new(0.75, 0.5, 1.0), Vector3::new(0.0, 0.0, -1.0));
+    assert!(triangle.hit(&r, 0.001, f32::MAX).is_none());
+}
@@ -1,13 +1,14 @@
-use crate::{bounding_box::AABB, material::*, ray::Ray};
+use crate::{bounding_box::AABB, material::*, ray::Ray, sampler};
 use nalgebra::Vector3;
 use rand::{
     distributions::{Distribution, Uniform},
     seq::SliceRandom,
     Rng,
 };
+use std::sync::Arc;
 
-pub trait Hittable: Sync {
-    fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord>;
+pub trait Hittable: Send + Sync {
+    fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>>;
     fn bounding_box(&self) -> Option<AABB>;
     fn pdf_value(&self, _origin: Vector3<f32>, _direction: Vector3<f32>) -> f32 {
         0.0
@@ -15,6 +16,67 @@ pub trait Hittable: Sync {
     fn random(&self, _origin: Vector3<f32>) -> Vector3<f32> {
         Vector3::new(1.0, 0.0, 0.0)
     }
+    /// Random point on the surface, for paths starting on a light. `None` for objects that
+    /// cannot be sampled this way.
+    fn sample_surface(&self) -> Option<SurfaceSample> {
+        None
+    }
+    /// Density of [`Hittable::sample_surface`] at the point hit along the ray, 0 without a hit.
+    fn surface_pdf(&self, _origin: Vector3<f32>, _direction: Vector3<f32>) -> f32 {
+        0.0
+    }
+}
+
+/// Point sampled on the surface of an object.
+#[derive(Debug, Copy, Clone)]
+pub struct SurfaceSample {
+    pub p: Vector3<f32>,
+    /// Outward unit normal.
+    pub n: Vector3<f32>,
+    /// Density per unit area.
+    pub pdf: f32,
+}
+
+impl<H: Hittable + ?Sized> Hittable for Box<H> {
+    fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        (**self).hit(r, t_min, t_max)
+    }
+    fn bounding_box(&self) -> Option<AABB> {
+        (**self).bounding_box()
+    }
+    fn pdf_value(&self, origin: Vector3<f32>, direction: Vector3<f32>) -> f32 {
+        (**self).pdf_value(origin, direction)
+    }
+    fn random(&self, origin: Vector3<f32>) -> Vector3<f32> {
+        (**self).random(origin)
+    }
+    fn sample_surface(&self) -> Option<SurfaceSample> {
+        (**self).sample_surface()
+    }
+    fn surface_pdf(&self, origin: Vector3<f32>, direction: Vector3<f32>) -> f32 {
+        (**self).surface_pdf(origin, direction)
+    }
+}
+
+impl<H: Hittable + ?Sized> Hittable for Arc<H> {
+    fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        (**self).hit(r, t_min, t_max)
+    }
+    fn bounding_box(&self) -> Option<AABB> {
+        (**self).bounding_box()
+    }
+    fn pdf_value(&self, origin: Vector3<f32>, direction: Vector3<f32>) -> f32 {
+        (**self).pdf_value(origin, direction)
+    }
+    fn random(&self, origin: Vector3<f32>) -> Vector3<f32> {
+        (**self).random(origin)
+    }
+    fn sample_surface(&self) -> Option<SurfaceSample> {
+        (**self).sample_surface()
+    }
+    fn surface_pdf(&self, origin: Vector3<f32>, direction: Vector3<f32>) -> f32 {
+        (**self).surface_pdf(origin, direction)
+    }
 }
 
 #[derive(Copy, Clone)]
@@ -26,6 +88,10 @@ pub struct HitRecord<'a> {
     pub v: f32,
     pub m: &'a dyn Material,
     pub front_face: bool,
+    /// Interpolated vertex color, for meshes with per-vertex colors.
+    pub color: Option<Vector3<f32>>,
+    /// Identifier of the object for the object ID pass, 0 for objects without one.
+    pub object_id: u32,
 }
 
 impl HitRecord<'_> {
@@ -74,8 +140,14 @@ impl World {
     }
 }
 
+impl Default for World {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Hittable for World {
-    fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
+    fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
         let mut hit_record = None;
         let mut closest_hit = t_max;
 
@@ -90,7 +162,7 @@ impl Hittable for World {
     }
 
     fn bounding_box(&self) -> Option<AABB> {
-        return self.aabb;
+        self.aabb
     }
 
     fn pdf_value(&self, origin: Vector3<f32>, direction: Vector3<f32>) -> f32 {
@@ -102,24 +174,41 @@ impl Hittable for World {
     }
 
     fn random(&self, origin: Vector3<f32>) -> Vector3<f32> {
-        if self.objects.len() == 0 {
+        if self.objects.is_empty() {
             Vector3::new(1.0, 0.0, 0.0)
         } else {
             self.objects
-                .choose(&mut rand::thread_rng())
+                .choose(&mut sampler::rng())
                 .unwrap()
                 .random(origin)
         }
     }
+
+    fn sample_surface(&self) -> Option<SurfaceSample> {
+        let obj = self.objects.choose(&mut sampler::rng())?;
+        let sample = obj.sample_surface()?;
+        Some(SurfaceSample {
+            pdf: sample.pdf / self.objects.len() as f32,
+            ..sample
+        })
+    }
+
+    fn surface_pdf(&self, origin: Vector3<f32>, direction: Vector3<f32>) -> f32 {
+        self.objects
+            .iter()
+            .map(|h| h.surface_pdf(origin, direction))
+            .sum::<f32>()
+            / self.objects.len().max(1) as f32
+    }
 }
 
 pub fn random_double(min: f32, max: f32) -> f32 {
-    let mut rng = rand::thread_rng();
-    rng.gen_range(min..=max) as f32
+    let mut rng = sampler::rng();
+    rng.gen_range(min..=max)
 }
 
 pub fn random_int(min: u32, max: u32) -> u32 {
-    let mut rng = rand::thread_rng();
+    let mut rng = sampler::rng();
     rng.gen_range(min..=max)
 }
 
@@ -127,7 +216,7 @@ pub fn random_color_vector() -> Vector3<f32> {
     const MIN: f32 = 0.0;
     const MAX: f32 = 1.0;
 
-    let mut rng = rand::thread_rng();
+    let mut rng = sampler::rng();
 
     let uni = Uniform::from(MIN..=MAX);
 
@@ -1,19 +1,28 @@
 use crate::{
     bounding_box::AABB,
-    hit::{HitRecord, Hittable},
+    hit::{HitRecord, Hittable, SurfaceSample},
     ray::Ray,
 };
-use nalgebra::Vector3;
+use nalgebra::{Isometry3, Matrix3, Matrix4, Point3, Similarity3, Translation3, Unit, Vector3};
+use std::sync::Arc;
 
 pub struct FlipFace<H: Hittable> {
     obj: H,
 }
 
+/// Assigns an identifier to the hits of an object, for the object ID pass. Ids assigned by
+/// nested objects take precedence.
+pub struct ObjectId<H: Hittable> {
+    obj: H,
+    id: u32,
+}
+
 pub struct Translate<H: Hittable> {
     obj: H,
     offset: Vector3<f32>,
 }
 
+/// Rotation about the Y axis, see [`Transform`] for rotations about any axis.
 pub struct Rotate<H: Hittable> {
     obj: H,
     sin_theta: f32,
@@ -21,12 +30,257 @@ pub struct Rotate<H: Hittable> {
     aabb: Option<AABB>,
 }
 
+/// A placement of shared geometry, with a rotation, uniform scale and translation.
+///
+/// The geometry is referenced through an `Arc`, so repeated assets are stored once no matter
+/// how many instances of them are placed. Instances grouped in a bvh form the top level of a
+/// two-level acceleration structure, with the bvh of the geometry itself as the bottom level.
+pub struct Instance {
+    obj: Arc<dyn Hittable>,
+    transform: Similarity3<f32>,
+    inverse: Similarity3<f32>,
+    aabb: Option<AABB>,
+}
+
+impl Instance {
+    pub fn new(obj: Arc<dyn Hittable>, transform: Similarity3<f32>) -> Self {
+        assert!(transform.scaling() > 0.0, "instance scale must be positive");
+
+        let aabb = obj
+            .bounding_box()
+            .map(|bbox| bbox.transform(|p| transform.transform_point(&Point3::from(p)).coords));
+
+        Self {
+            obj,
+            transform,
+            inverse: transform.inverse(),
+            aabb,
+        }
+    }
+
+    pub fn object(&self) -> &Arc<dyn Hittable> {
+        &self.obj
+    }
+
+    pub fn transform(&self) -> &Similarity3<f32> {
+        &self.transform
+    }
+}
+
+/// Transform of a [`MovingInstance`] at a point in time.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Keyframe {
+    pub time: f32,
+    pub transform: Similarity3<f32>,
+}
+
+/// Number of steps per keyframe interval at which the bounds of a moving instance are evaluated.
+const MOTION_BOUND_STEPS: usize = 16;
+
+/// An instance of shared geometry moving through keyframed transforms.
+///
+/// Between two keyframes the translation and scale are interpolated linearly and the rotation
+/// spherically along the shortest path, so consecutive keyframes should be rotated by less than
+/// half a turn. Before the first and after the last keyframe the object does not move. Two
+/// keyframes give a linear motion. The bounds cover the object at all times.
+pub struct MovingInstance {
+    obj: Arc<dyn Hittable>,
+    keyframes: Vec<Keyframe>,
+    aabb: Option<AABB>,
+}
+
+impl MovingInstance {
+    pub fn new(obj: Arc<dyn Hittable>, mut keyframes: Vec<Keyframe>) -> Self {
+        assert!(!keyframes.is_empty(), "moving instance without keyframes");
+        assert!(
+            keyframes.iter().all(|k| k.transform.scaling() > 0.0),
+            "instance scale must be positive"
+        );
+        keyframes.sort_by(|a, b| a.time.total_cmp(&b.time));
+
+        let aabb = obj.bounding_box().map(|bbox| {
+            let transformed = |transform: &Similarity3<f32>| {
+                bbox.transform(|p| transform.transform_point(&Point3::from(p)).coords)
+            };
+            // distance of the farthest corner from the center of rotation
+            let radius = (0..8)
+                .map(|i| Vector3::new(bbox[i & 1].x, bbox[(i >> 1) & 1].y, bbox[i >> 2].z).norm())
+                .fold(0.0, f32::max);
+
+            let mut aabb = transformed(&keyframes[0].transform);
+            for pair in keyframes.windows(2) {
+                let (a, b) = (&pair[0].transform, &pair[1].transform);
+
+                // points move on arcs between the evaluated steps, pad the bounds by the
+                // largest distance between an arc and its chord
+                let angle = a.isometry.rotation.angle_to(&b.isometry.rotation);
+                let scale = a.scaling().max(b.scaling());
+                let step = angle / MOTION_BOUND_STEPS as f32;
+                let pad = Vector3::repeat(scale * radius * (1.0 - (step / 2.0).cos()));
+
+                for i in 1..=MOTION_BOUND_STEPS {
+                    let s = i as f32 / MOTION_BOUND_STEPS as f32;
+                    let step_aabb = transformed(&interpolate(a, b, s));
+                    let step_aabb = AABB::new(step_aabb.min() - pad, step_aabb.max() + pad);
+                    aabb = aabb.extend_box(&step_aabb);
+                }
+            }
+            aabb
+        });
+
+        Self {
+            obj,
+            keyframes,
+            aabb,
+        }
+    }
+
+    /// Linear motion from the first to the second transform.
+    pub fn linear(obj: Arc<dyn Hittable>, from: Keyframe, to: Keyframe) -> Self {
+        Self::new(obj, vec![from, to])
+    }
+
+    pub fn keyframes(&self) -> &[Keyframe] {
+        &self.keyframes
+    }
+
+    /// Transform at the given time.
+    pub fn transform(&self, time: f32) -> Similarity3<f32> {
+        let i = self.keyframes.partition_point(|k| k.time <= time);
+        if i == 0 {
+            return self.keyframes[0].transform;
+        }
+        if i == self.keyframes.len() {
+            return self.keyframes[i - 1].transform;
+        }
+
+        let (a, b) = (&self.keyframes[i - 1], &self.keyframes[i]);
+        interpolate(
+            &a.transform,
+            &b.transform,
+            (time - a.time) / (b.time - a.time),
+        )
+    }
+}
+
+fn interpolate(a: &Similarity3<f32>, b: &Similarity3<f32>, s: f32) -> Similarity3<f32> {
+    let translation = a
+        .isometry
+        .translation
+        .vector
+        .lerp(&b.isometry.translation.vector, s);
+    // antipodal quaternions are the same rotation
+    let rotation = a
+        .isometry
+        .rotation
+        .try_slerp(&b.isometry.rotation, s, f32::EPSILON)
+        .unwrap_or(a.isometry.rotation);
+    let scaling = a.scaling() + (b.scaling() - a.scaling()) * s;
+    Similarity3::from_parts(Translation3::from(translation), rotation, scaling)
+}
+
+/// A general affine transform of an object, given by a 4x4 matrix.
+///
+/// Transforms are composed by chaining the helpers, each one is applied after the previous ones:
+/// `Transform::new(obj).scale(..).rotate(..).translate(..)`.
+pub struct Transform<H: Hittable> {
+    obj: H,
+    matrix: Matrix4<f32>,
+    inverse: Matrix4<f32>,
+    /// Inverse transpose of the linear part, transforms the normals.
+    normal_matrix: Matrix3<f32>,
+    aabb: Option<AABB>,
+}
+
+impl<H: Hittable> Transform<H> {
+    pub fn new(obj: H) -> Self {
+        let aabb = obj.bounding_box();
+        Self {
+            obj,
+            matrix: Matrix4::identity(),
+            inverse: Matrix4::identity(),
+            normal_matrix: Matrix3::identity(),
+            aabb,
+        }
+    }
+
+    /// Transform the object by a matrix. Returns `None` if the matrix is not invertible.
+    pub fn with_matrix(obj: H, matrix: Matrix4<f32>) -> Option<Self> {
+        Self::new(obj).then(matrix)
+    }
+
+    pub fn translate(self, offset: Vector3<f32>) -> Self {
+        self.then(Matrix4::new_translation(&offset)).unwrap()
+    }
+
+    /// Rotation about an axis through the origin, angle in degrees.
+    pub fn rotate(self, axis: Vector3<f32>, angle: f32) -> Self {
+        let rotation = Matrix4::from_axis_angle(&Unit::new_normalize(axis), angle.to_radians());
+        self.then(rotation).unwrap()
+    }
+
+    /// Scale along the axes, panics if a factor is zero.
+    pub fn scale(self, factors: Vector3<f32>) -> Self {
+        self.then(Matrix4::new_nonuniform_scaling(&factors))
+            .expect("zero scale factor")
+    }
+
+    /// Move the object from the origin to `eye`, turning its +Z axis towards `target`.
+    pub fn look_at(self, eye: Vector3<f32>, target: Vector3<f32>, up: Vector3<f32>) -> Self {
+        let isometry = Isometry3::face_towards(&Point3::from(eye), &Point3::from(target), &up);
+        self.then(isometry.to_homogeneous()).unwrap()
+    }
+
+    /// Apply `matrix` after the current transform.
+    pub fn then(self, matrix: Matrix4<f32>) -> Option<Self> {
+        let matrix = matrix * self.matrix;
+        let inverse = matrix.try_inverse()?;
+        let normal_matrix = inverse.fixed_slice::<3, 3>(0, 0).transpose();
+        let aabb = self
+            .obj
+            .bounding_box()
+            .map(|bbox| bbox.transform(|p| matrix.transform_point(&Point3::from(p)).coords));
+
+        Some(Self {
+            obj: self.obj,
+            matrix,
+            inverse,
+            normal_matrix,
+            aabb,
+        })
+    }
+
+    pub fn matrix(&self) -> &Matrix4<f32> {
+        &self.matrix
+    }
+
+    fn to_local(&self, p: Vector3<f32>) -> Vector3<f32> {
+        self.inverse.transform_point(&Point3::from(p)).coords
+    }
+
+    /// Ratio of the transformed and local area of a surface element, given its transformed
+    /// normal before normalization.
+    fn area_scale(&self, n: &Vector3<f32>) -> f32 {
+        self.matrix.fixed_slice::<3, 3>(0, 0).determinant().abs() * n.norm()
+    }
+}
+
 impl<H: Hittable> FlipFace<H> {
     pub fn new(obj: H) -> Self {
         Self { obj }
     }
 }
 
+impl<H: Hittable> ObjectId<H> {
+    pub fn new(obj: H, id: u32) -> Self {
+        Self { obj, id }
+    }
+
+    pub fn id(&self) -> u32 {
+        self.id
+    }
+}
+
 impl<H: Hittable> Translate<H> {
     pub fn new(obj: H, offset: Vector3<f32>) -> Self {
         Self { obj, offset }
@@ -89,8 +343,8 @@ impl<H: Hittable> Hittable for FlipFace<H> {
         self.obj.bounding_box()
     }
 
-    fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
-        match self.obj.hit(&r, t_min, t_max) {
+    fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        match self.obj.hit(r, t_min, t_max) {
             None => None,
             Some(mut hit) => {
                 hit.front_face = !hit.front_face;
@@ -98,21 +352,63 @@ impl<H: Hittable> Hittable for FlipFace<H> {
             }
         }
     }
+
+    fn pdf_value(&self, origin: Vector3<f32>, direction: Vector3<f32>) -> f32 {
+        self.obj.pdf_value(origin, direction)
+    }
+
+    fn random(&self, origin: Vector3<f32>) -> Vector3<f32> {
+        self.obj.random(origin)
+    }
+
+    fn sample_surface(&self) -> Option<SurfaceSample> {
+        self.obj.sample_surface()
+    }
+
+    fn surface_pdf(&self, origin: Vector3<f32>, direction: Vector3<f32>) -> f32 {
+        self.obj.surface_pdf(origin, direction)
+    }
 }
 
-impl<H: Hittable> Hittable for Translate<H> {
+impl<H: Hittable> Hittable for ObjectId<H> {
     fn bounding_box(&self) -> Option<AABB> {
-        match self.obj.bounding_box() {
-            Some(aabb) => Some(AABB::new(
-                aabb.min() + self.offset,
-                aabb.max() + self.offset,
-            )),
-            None => None,
+        self.obj.bounding_box()
+    }
+
+    fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        let mut hit = self.obj.hit(r, t_min, t_max)?;
+        if hit.object_id == 0 {
+            hit.object_id = self.id;
         }
+        Some(hit)
+    }
+
+    fn pdf_value(&self, origin: Vector3<f32>, direction: Vector3<f32>) -> f32 {
+        self.obj.pdf_value(origin, direction)
+    }
+
+    fn random(&self, origin: Vector3<f32>) -> Vector3<f32> {
+        self.obj.random(origin)
     }
 
-    fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
-        let moved_r = Ray::new(r.ori - self.offset, r.dir);
+    fn sample_surface(&self) -> Option<SurfaceSample> {
+        self.obj.sample_surface()
+    }
+
+    fn surface_pdf(&self, origin: Vector3<f32>, direction: Vector3<f32>) -> f32 {
+        self.obj.surface_pdf(origin, direction)
+    }
+}
+
+impl<H: Hittable> Hittable for Translate<H> {
+    fn bounding_box(&self) -> Option<AABB> {
+        self.obj
+            .bounding_box()
+            .map(|aabb| AABB::new(aabb.min() + self.offset, aabb.max() + self.offset))
+    }
+
+    fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        let moved_r = Ray::with_time(r.ori - self.offset, r.dir, r.time);
 
         match self.obj.hit(&moved_r, t_min, t_max) {
             Some(mut hit) => {
@@ -124,14 +420,26 @@ impl<H: Hittable> Hittable for Translate<H> {
             None => None,
         }
     }
+
+    fn sample_surface(&self) -> Option<SurfaceSample> {
+        let sample = self.obj.sample_surface()?;
+        Some(SurfaceSample {
+            p: sample.p + self.offset,
+            ..sample
+        })
+    }
+
+    fn surface_pdf(&self, origin: Vector3<f32>, direction: Vector3<f32>) -> f32 {
+        self.obj.surface_pdf(origin - self.offset, direction)
+    }
 }
 
 impl<H: Hittable> Hittable for Rotate<H> {
     fn bounding_box(&self) -> Option<AABB> {
-        return self.aabb;
+        self.aabb
     }
 
-    fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
+    fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
         let mut origin: Vector3<f32> = r.ori;
         let mut direction: Vector3<f32> = r.dir;
 
@@ -141,7 +449,7 @@ impl<H: Hittable> Hittable for Rotate<H> {
         direction.x = self.cos_theta * r.dir.x - self.sin_theta * r.dir.z;
         direction.z = self.sin_theta * r.dir.x + self.cos_theta * r.dir.z;
 
-        let rotated_r = Ray::new(origin, direction);
+        let rotated_r = Ray::with_time(origin, direction, r.time);
 
         match self.obj.hit(&rotated_r, t_min, t_max) {
             Some(mut hit) => {
@@ -154,11 +462,360 @@ impl<H: Hittable> Hittable for Rotate<H> {
                 n[0] = self.cos_theta * hit.n.x + self.sin_theta * hit.n.z;
                 n[2] = -self.sin_theta * hit.n.x + self.cos_theta * hit.n.z;
 
+                // the rotation keeps the angle between the ray and the normal, so the face
+                // side does not change
                 hit.p = p;
-                hit.set_face_normal(&rotated_r, &n);
+                hit.n = n;
                 Some(hit)
             }
             None => None,
         }
     }
 }
+
+impl Hittable for Instance {
+    fn bounding_box(&self) -> Option<AABB> {
+        self.aabb
+    }
+
+    fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        similarity_hit(&*self.obj, &self.transform, &self.inverse, r, t_min, t_max)
+    }
+
+    fn pdf_value(&self, origin: Vector3<f32>, direction: Vector3<f32>) -> f32 {
+        similarity_pdf_value(&*self.obj, &self.inverse, origin, direction)
+    }
+
+    fn random(&self, origin: Vector3<f32>) -> Vector3<f32> {
+        similarity_random(&*self.obj, &self.transform, &self.inverse, origin)
+    }
+
+    fn sample_surface(&self) -> Option<SurfaceSample> {
+        similarity_sample_surface(&*self.obj, &self.transform)
+    }
+
+    fn surface_pdf(&self, origin: Vector3<f32>, direction: Vector3<f32>) -> f32 {
+        similarity_surface_pdf(&*self.obj, &self.inverse, origin, direction)
+    }
+}
+
+impl Hittable for MovingInstance {
+    fn bounding_box(&self) -> Option<AABB> {
+        self.aabb
+    }
+
+    fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        let transform = self.transform(r.time);
+        let inverse = transform.inverse();
+        similarity_hit(&*self.obj, &transform, &inverse, r, t_min, t_max)
+    }
+
+    // light sampling has no time, it uses the transform of the first keyframe
+
+    fn pdf_value(&self, origin: Vector3<f32>, direction: Vector3<f32>) -> f32 {
+        let inverse = self.keyframes[0].transform.inverse();
+        similarity_pdf_value(&*self.obj, &inverse, origin, direction)
+    }
+
+    fn random(&self, origin: Vector3<f32>) -> Vector3<f32> {
+        let transform = &self.keyframes[0].transform;
+        similarity_random(&*self.obj, transform, &transform.inverse(), origin)
+    }
+
+    fn sample_surface(&self) -> Option<SurfaceSample> {
+        similarity_sample_surface(&*self.obj, &self.keyframes[0].transform)
+    }
+
+    fn surface_pdf(&self, origin: Vector3<f32>, direction: Vector3<f32>) -> f32 {
+        let inverse = self.keyframes[0].transform.inverse();
+        similarity_surface_pdf(&*self.obj, &inverse, origin, direction)
+    }
+}
+
+fn similarity_hit<'a>(
+    obj: &'a dyn Hittable,
+    transform: &Similarity3<f32>,
+    inverse: &Similarity3<f32>,
+    r: &Ray,
+    t_min: f32,
+    t_max: f32,
+) -> Option<HitRecord<'a>> {
+    // the direction is scaled along with the origin, so the ray distance stays the same
+    let local_r = Ray::with_time(
+        inverse.transform_point(&Point3::from(r.ori)).coords,
+        inverse.transform_vector(&r.dir),
+        r.time,
+    );
+
+    obj.hit(&local_r, t_min, t_max).map(|mut hit| {
+        // rotations and uniform scales keep the angle between the ray and the normal, so the
+        // face side does not change
+        hit.p = transform.transform_point(&Point3::from(hit.p)).coords;
+        hit.n = transform.isometry.rotation * hit.n;
+        hit
+    })
+}
+
+fn similarity_pdf_value(
+    obj: &dyn Hittable,
+    inverse: &Similarity3<f32>,
+    origin: Vector3<f32>,
+    direction: Vector3<f32>,
+) -> f32 {
+    // solid angles are invariant under similarity transforms
+    obj.pdf_value(
+        inverse.transform_point(&Point3::from(origin)).coords,
+        inverse.transform_vector(&direction),
+    )
+}
+
+fn similarity_random(
+    obj: &dyn Hittable,
+    transform: &Similarity3<f32>,
+    inverse: &Similarity3<f32>,
+    origin: Vector3<f32>,
+) -> Vector3<f32> {
+    let local_origin = inverse.transform_point(&Point3::from(origin)).coords;
+    transform.transform_vector(&obj.random(local_origin))
+}
+
+fn similarity_sample_surface(
+    obj: &dyn Hittable,
+    transform: &Similarity3<f32>,
+) -> Option<SurfaceSample> {
+    let sample = obj.sample_surface()?;
+    let scale = transform.scaling();
+    Some(SurfaceSample {
+        p: transform.transform_point(&Point3::from(sample.p)).coords,
+        n: transform.isometry.rotation * sample.n,
+        pdf: sample.pdf / (scale * scale),
+    })
+}
+
+fn similarity_surface_pdf(
+    obj: &dyn Hittable,
+    inverse: &Similarity3<f32>,
+    origin: Vector3<f32>,
+    direction: Vector3<f32>,
+) -> f32 {
+    // areas scale by the square of the inverse scale
+    let scale = inverse.scaling();
+    obj.surface_pdf(
+        inverse.transform_point(&Point3::from(origin)).coords,
+        inverse.transform_vector(&direction),
+    ) * scale
+        * scale
+}
+
+impl<H: Hittable> Hittable for Transform<H> {
+    fn bounding_box(&self) -> Option<AABB> {
+        self.aabb
+    }
+
+    fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        // the direction is not normalized, so the ray distance is the same in both spaces
+        let local_r = Ray::with_time(
+            self.to_local(r.ori),
+            self.inverse.transform_vector(&r.dir),
+            r.time,
+        );
+
+        self.obj.hit(&local_r, t_min, t_max).map(|mut hit| {
+            // the inverse transpose keeps the dot product of the normal and the ray direction,
+            // so the face side does not change
+            hit.p = self.matrix.transform_point(&Point3::from(hit.p)).coords;
+            hit.n = (self.normal_matrix * hit.n).normalize();
+            hit
+        })
+    }
+
+    fn pdf_value(&self, origin: Vector3<f32>, direction: Vector3<f32>) -> f32 {
+        // a unit direction w maps onto the local direction Bw / |Bw|, which changes the solid
+        // angle measure by |det B| / |Bw|^3
+        let linear = self.inverse.fixed_slice::<3, 3>(0, 0);
+        let local_direction = linear * direction.normalize();
+        let length = local_direction.norm();
+
+        self.obj.pdf_value(self.to_local(origin), local_direction) * linear.determinant().abs()
+            / (length * length * length)
+    }
+
+    fn random(&self, origin: Vector3<f32>) -> Vector3<f32> {
+        self.matrix
+            .transform_vector(&self.obj.random(self.to_local(origin)))
+    }
+
+    fn sample_surface(&self) -> Option<SurfaceSample> {
+        let sample = self.obj.sample_surface()?;
+        let n = self.normal_matrix * sample.n;
+        Some(SurfaceSample {
+            p: self.matrix.transform_point(&Point3::from(sample.p)).coords,
+            n: n.normalize(),
+            pdf: sample.pdf / self.area_scale(&n),
+        })
+    }
+
+    fn surface_pdf(&self, origin: Vector3<f32>, direction: Vector3<f32>) -> f32 {
+        let local_r = Ray::new(
+            self.to_local(origin),
+            self.inverse.transform_vector(&direction),
+        );
+        match self.obj.hit(&local_r, 0.001, f32::MAX) {
+            None => 0.0,
+            Some(hit) => {
+                self.obj.surface_pdf(local_r.ori, local_r.dir)
+                    / self.area_scale(&(self.normal_matrix * hit.n))
+            }
+        }
+    }
+}
+
+#[test]
+fn test_instance_hit() {
+    use crate::geometry::sphere::Sphere;
+    use crate::material::Lambertian;
+    use crate::texture::SolidColor;
+    use nalgebra::{Translation3, UnitQuaternion};
+
+    let material = Lambertian::new(SolidColor::new(0.5, 0.5, 0.5));
+    let sphere: Arc<dyn Hittable> =
+        Arc::new(Sphere::new(Vector3::new(1.0, 0.0, 0.0), 1.0, material));
+
+    // rotate the sphere center onto the z axis, scale it by 2 and move it up
+    let transform = Similarity3::from_parts(
+        Translation3::new(0.0, 3.0, 0.0),
+        UnitQuaternion::from_axis_angle(&Vector3::y_axis(), -std::f32::consts::FRAC_PI_2),
+        2.0,
+    );
+    let instances: Vec<Instance> = (0..4)
+        .map(|_| Instance::new(sphere.clone(), transform))
+        .collect();
+    assert_eq!(Arc::strong_count(&sphere), 5);
+
+    let instance = &instances[0];
+    let aabb = instance.bounding_box().unwrap();
+    assert!((aabb.min() - Vector3::new(-2.0, 1.0, 0.0)).norm() < 1e-4);
+    assert!((aabb.max() - Vector3::new(2.0, 5.0, 4.0)).norm() < 1e-4);
+
+    let r = Ray::new(Vector3::new(0.0, 3.0, 10.0), Vector3::new(0.0, 0.0, -2.0));
+    let hit = instance.hit(&r, 0.001, f32::MAX).unwrap();
+    assert!((hit.t - 3.0).abs() < 1e-4);
+    assert!((hit.p - Vector3::new(0.0, 3.0, 4.0)).norm() < 1e-4);
+    assert!((hit.n - Vector3::new(0.0, 0.0, 1.0)).norm() < 1e-4);
+    assert!(hit.front_face);
+}
+
+#[test]
+fn test_transform() {
+    use crate::geometry::sphere::Sphere;
+    use crate::material::DiffuseLight;
+    use crate::texture::SolidColor;
+
+    let light = || DiffuseLight::new(SolidColor::new(1.0, 1.0, 1.0));
+    let origin = Vector3::new(0.5, 0.0, 0.0);
+
+    // a unit sphere stretched along x into an ellipsoid, rotated onto the z axis and moved
+    let ellipsoid = Transform::new(Sphere::new(Vector3::zeros(), 1.0, light()))
+        .scale(Vector3::new(3.0, 1.0, 1.0))
+        .rotate(Vector3::y(), 90.0)
+        .translate(Vector3::new(0.0, 5.0, 0.0));
+    let aabb = ellipsoid.bounding_box().unwrap();
+    assert!((aabb.min() - Vector3::new(-1.0, 4.0, -3.0)).norm() < 1e-4);
+    assert!((aabb.max() - Vector3::new(1.0, 6.0, 3.0)).norm() < 1e-4);
+
+    // hit the side of the ellipsoid at 45 degrees, the normal is perpendicular to the surface
+    let p = Vector3::new(1.0, 0.0, 3.0) / 2.0f32.sqrt() + Vector3::new(0.0, 5.0, 0.0);
+    let r = Ray::new(
+        p + Vector3::new(0.0, 0.0, 1.0),
+        Vector3::new(0.0, 0.0, -1.0),
+    );
+    let hit = ellipsoid.hit(&r, 0.001, f32::MAX).unwrap();
+    assert!((hit.p - p).norm() < 1e-4);
+    assert!((hit.n - Vector3::new(3.0, 0.0, 1.0).normalize()).norm() < 1e-4);
+
+    // a uniformly scaled light sphere has the same pdf as a larger sphere
+    let scaled = Transform::new(Sphere::new(Vector3::new(0.0, 1.0, 0.0), 1.0, light()))
+        .scale(Vector3::repeat(2.0))
+        .translate(Vector3::new(0.0, 4.0, 0.0));
+    let sphere = Sphere::new(Vector3::new(0.0, 6.0, 0.0), 2.0, light());
+    for direction in [Vector3::new(0.0, 1.0, 0.0), Vector3::new(0.2, 1.0, 0.1)] {
+        let expected = sphere.pdf_value(origin, direction);
+        assert!((scaled.pdf_value(origin, direction) - expected).abs() < 1e-3 * expected);
+    }
+    for _ in 0..16 {
+        let direction = scaled.random(origin);
+        assert!(sphere
+            .hit(&Ray::new(origin, direction), 0.001, f32::MAX)
+            .is_some());
+    }
+
+    // the pdf of a stretched light still integrates to one over all directions, evaluated on a
+    // fibonacci sphere
+    let n = 100_000;
+    let integral = (0..n)
+        .map(|i| {
+            let z = 1.0 - (2 * i + 1) as f32 / n as f32;
+            let phi = i as f32 * std::f32::consts::PI * (3.0 - 5.0f32.sqrt());
+            let r = (1.0 - z * z).sqrt();
+            ellipsoid.pdf_value(origin, Vector3::new(r * phi.cos(), r * phi.sin(), z))
+        })
+        .sum::<f32>()
+        * 4.0
+        * std::f32::consts::PI
+        / n as f32;
+    assert!((integral - 1.0).abs() < 0.02);
+
+    assert!(Transform::with_matrix(sphere, Matrix4::zeros()).is_none());
+}
+
+#[test]
+fn test_moving_instance() {
+    use crate::geometry::sphere::Sphere;
+    use crate::material::Lambertian;
+    use crate::texture::SolidColor;
+    use nalgebra::UnitQuaternion;
+
+    let material = Lambertian::new(SolidColor::new(0.5, 0.5, 0.5));
+    let sphere: Arc<dyn Hittable> =
+        Arc::new(Sphere::new(Vector3::new(4.0, 0.0, 0.0), 1.0, material));
+
+    // a quarter turn about the y axis while growing and moving up
+    let keyframe = |time: f32, angle: f32, scale: f32| Keyframe {
+        time,
+        transform: Similarity3::from_parts(
+            Translation3::new(0.0, 2.0 * time, 0.0),
+            UnitQuaternion::from_axis_angle(&Vector3::y_axis(), angle.to_radians()),
+            scale,
+        ),
+    };
+    let instance =
+        MovingInstance::linear(sphere, keyframe(0.0, 0.0, 1.0), keyframe(1.0, 90.0, 2.0));
+
+    // the bounds hold the sphere at every time of the interval
+    let aabb = instance.bounding_box().unwrap();
+    for i in 0..=100 {
+        let time = i as f32 / 100.0;
+        let transform = instance.transform(time);
+        let center = transform
+            .transform_point(&Point3::new(4.0, 0.0, 0.0))
+            .coords;
+        let radius = Vector3::repeat(transform.scaling());
+        assert!((center - radius)
+            .iter()
+            .zip(aabb.min().iter())
+            .all(|(p, min)| p >= min));
+        assert!((center + radius)
+            .iter()
+            .zip(aabb.max().iter())
+            .all(|(p, max)| p <= max));
+    }
+
+    // at the middle of the interval the center is at 45 degrees, at a distance of 6
+    let x = 6.0 * std::f32::consts::FRAC_1_SQRT_2;
+    let r = Ray::with_time(Vector3::new(x, 1.0, 0.0), Vector3::new(0.0, 0.0, -1.0), 0.5);
+    let hit = instance.hit(&r, 0.001, f32::MAX).unwrap();
+    assert!((hit.t - (x - 1.5)).abs() < 1e-3);
+    assert!(instance
+        .hit(&Ray::with_time(r.ori, r.dir, 0.0), 0.001, f32::MAX)
+        .is_none());
+}
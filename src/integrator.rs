@@ -0,0 +1,479 @@
+//! Integrators computing the light arriving along camera rays.
+
+use crate::{
+    aov::AovSample,
+    bdpt,
+    camera::Camera,
+    hit::{HitRecord, Hittable},
+    material::ScatterRecord,
+    mlt::Metropolis,
+    photon::{PhotonMapping, Photons},
+    ray::{Bounce, Ray},
+    sampler,
+};
+use nalgebra::Vector3;
+use rand::Rng;
+use std::f32::consts::PI;
+
+/// Light transport algorithm of a render.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Integrator {
+    /// Recursive path tracing, see [`Ray::color`]. Every path runs to the maximum depth unless
+    /// it leaves the scene or hits a surface that does not scatter.
+    Recursive,
+    /// Iterative path tracing, see [`PathTracer`].
+    Path(PathTracer),
+    /// Bidirectional path tracing, see [`bdpt::color`].
+    Bidirectional,
+    /// Photon mapping, see [`PhotonMapping`]. The renderer traces the photon maps of the
+    /// scene.
+    PhotonMap(PhotonMapping),
+    /// Metropolis light transport, see [`Metropolis`]. The renderer runs the chains over the
+    /// whole image, single camera rays and the passes are traced by its path tracer.
+    Metropolis(Metropolis),
+}
+
+impl Default for Integrator {
+    fn default() -> Self {
+        Integrator::Path(PathTracer::default())
+    }
+}
+
+/// The scene seen by an integrator.
+#[derive(Copy, Clone)]
+pub struct Scene<'a> {
+    pub camera: &'a Camera,
+    pub world: &'a dyn Hittable,
+    pub lights: &'a dyn Hittable,
+    pub background: &'a Vector3<f32>,
+    /// Maximum number of hits along a path.
+    pub max_depth: u8,
+    /// Area of the whole image in image plane coordinates, see [`Camera::ray`].
+    pub image_area: f32,
+    /// Photon maps of the render pass, for photon mapping.
+    pub photons: Option<&'a Photons>,
+}
+
+/// Light found by a path that reached the camera from a light, for the pixel at the image
+/// plane position (u, v). It adds to the pixel on top of the color of the camera samples,
+/// divided by their number.
+#[derive(Debug, Copy, Clone)]
+pub struct Splat {
+    pub u: f32,
+    pub v: f32,
+    pub color: Vector3<f32>,
+}
+
+impl Integrator {
+    /// Color along a camera ray, with at most `max_depth` hits. Light found for other pixels is
+    /// added to the splats. The passes of the sample are recorded when given.
+    pub fn color(
+        &self,
+        scene: &Scene,
+        ray: &Ray,
+        splats: &mut Vec<Splat>,
+        aovs: Option<&mut AovSample>,
+    ) -> Vector3<f32> {
+        let Scene {
+            world,
+            lights,
+            background,
+            max_depth,
+            ..
+        } = *scene;
+        match (self, aovs) {
+            (Integrator::Recursive, None) => ray.color(background, world, lights, max_depth),
+            (Integrator::Recursive, Some(aovs)) => {
+                ray.color_with_aovs(background, world, lights, max_depth, aovs)
+            }
+            (Integrator::Path(tracer), aovs) => {
+                tracer.color(ray, background, world, lights, max_depth, aovs)
+            }
+            (Integrator::Bidirectional, aovs) => bdpt::color(scene, ray, splats, aovs),
+            (Integrator::PhotonMap(mapping), aovs) => mapping.color(scene, ray, aovs),
+            (Integrator::Metropolis(metropolis), aovs) => metropolis
+                .tracer
+                .color(ray, background, world, lights, max_depth, aovs),
+        }
+    }
+}
+
+/// Weighting of the light and material samples of next event estimation.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum Heuristic {
+    /// Weights proportional to the pdfs of the two strategies.
+    Balance,
+    /// Weights proportional to the squared pdfs, favoring the better strategy more strongly.
+    #[default]
+    Power,
+}
+
+impl Heuristic {
+    /// Weight of a sample taken with the given pdf, when the other strategy would have taken
+    /// it with the pdf `other`.
+    pub fn weight(&self, pdf: f32, other: f32) -> f32 {
+        let (a, b) = match self {
+            Heuristic::Balance => (pdf, other),
+            Heuristic::Power => (pdf * pdf, other * other),
+        };
+        if a + b > 0.0 {
+            a / (a + b)
+        } else {
+            0.0
+        }
+    }
+}
+
+/// Iterative path tracing, tracking the throughput of the path.
+///
+/// After `roulette_depth` bounces a path survives each further bounce with a probability equal
+/// to its largest throughput component, and the surviving paths are weighted up by the inverse
+/// of that probability. The estimate stays unbiased, while paths that carry little light are
+/// stopped early.
+///
+/// With next event estimation every diffuse or volume scattering event also casts a shadow ray
+/// to a point sampled on the lights, while the path continues in a direction sampled from the
+/// material alone. Emission found by either strategy is weighted by the heuristic, so it is
+/// counted once. Without it the direction is sampled from the mix of the light and material
+/// pdfs like [`Ray::color`], and both compute the same color when the roulette is off.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct PathTracer {
+    /// Number of bounces before paths are terminated by Russian roulette.
+    pub roulette_depth: u8,
+    /// Heuristic weighting next event estimation and material sampling, `None` to sample the
+    /// mixture of the light and material pdfs instead.
+    pub next_event: Option<Heuristic>,
+}
+
+impl Default for PathTracer {
+    fn default() -> Self {
+        Self {
+            roulette_depth: 3,
+            next_event: Some(Heuristic::Power),
+        }
+    }
+}
+
+impl PathTracer {
+    /// Color along a camera ray like [`Integrator::color`].
+    pub fn color(
+        &self,
+        ray: &Ray,
+        background: &Vector3<f32>,
+        world: &dyn Hittable,
+        lights: &dyn Hittable,
+        max_depth: u8,
+        mut aovs: Option<&mut AovSample>,
+    ) -> Vector3<f32> {
+        let mut rng = sampler::rng();
+        let mut ray = *ray;
+        let mut throughput = Vector3::repeat(1.0);
+        let mut color = Vector3::zeros();
+
+        // light emitted at the first hit and reaching it straight from an emitter, for the
+        // passes
+        let mut emission = Vector3::zeros();
+        let mut direct = Vector3::zeros();
+        let mut add = |bounces: u8, light: Vector3<f32>| {
+            color += light;
+            match bounces {
+                0 => emission += light,
+                1 => direct += light,
+                _ => (),
+            }
+        };
+
+        // pdf of the material sample the ray was scattered with, when next event estimation
+        // could have found the same light
+        let mut material_pdf = None;
+
+        for depth in 0..max_depth {
+            let Some(hit) = world.hit(&ray, 0.001, f32::MAX) else {
+                let weight = self.emission_weight(lights, &ray, material_pdf);
+                add(depth, throughput.component_mul(background) * weight);
+                break;
+            };
+
+            let record = hit.m.scatter(&ray, &hit);
+            if let (0, Some(aovs)) = (depth, aovs.as_deref_mut()) {
+                aovs.record_hit(&hit, &ray.dir);
+                aovs.albedo = match &record {
+                    None => Vector3::zeros(),
+                    Some(
+                        ScatterRecord::Scatter { attenuation, .. }
+                        | ScatterRecord::Specular { attenuation, .. }
+                        | ScatterRecord::Isotropic { attenuation, .. },
+                    ) => *attenuation,
+                };
+            }
+
+            // specular surfaces only pass on the light they reflect
+            if !matches!(record, Some(ScatterRecord::Specular { .. })) {
+                let weight = self.emission_weight(lights, &ray, material_pdf);
+                add(
+                    depth,
+                    throughput.component_mul(&hit.m.emitted(&hit)) * weight,
+                );
+            }
+
+            let Some(record) = record else {
+                break;
+            };
+
+            let shadow_rays = depth + 1 < max_depth;
+            let bounce = match (self.next_event, record) {
+                (Some(heuristic), ScatterRecord::Scatter { attenuation, pdf }) => {
+                    let f = |scattered: &Ray| {
+                        let value = attenuation * hit.m.scattering_pdf(&hit, scattered);
+                        (value, pdf.value(scattered.dir))
+                    };
+                    if shadow_rays {
+                        let light =
+                            sample_light(heuristic, &hit, &ray, background, world, lights, f);
+                        add(depth + 1, throughput.component_mul(&light));
+                    }
+
+                    let scattered = Ray::with_time(hit.p, pdf.generate(), ray.time);
+                    let (value, pdf) = f(&scattered);
+                    material_pdf = Some(pdf);
+                    Bounce {
+                        attenuation,
+                        weight: value / pdf,
+                        ray: scattered,
+                        specular: false,
+                    }
+                }
+                (
+                    Some(heuristic),
+                    ScatterRecord::Isotropic {
+                        attenuation,
+                        scattered_ray,
+                    },
+                ) => {
+                    // uniform phase function
+                    let f = |_: &Ray| (attenuation / (4.0 * PI), 1.0 / (4.0 * PI));
+                    if shadow_rays {
+                        let light =
+                            sample_light(heuristic, &hit, &ray, background, world, lights, f);
+                        add(depth + 1, throughput.component_mul(&light));
+                    }
+
+                    material_pdf = Some(1.0 / (4.0 * PI));
+                    Bounce {
+                        attenuation,
+                        weight: attenuation,
+                        ray: scattered_ray,
+                        specular: false,
+                    }
+                }
+                (_, record) => {
+                    material_pdf = None;
+                    ray.scatter(&hit, lights, record)
+                }
+            };
+
+            throughput.component_mul_assign(&bounce.weight);
+            ray = bounce.ray;
+
+            if depth + 1 >= self.roulette_depth {
+                let survival = throughput.max().min(1.0);
+                if survival <= 0.0 || rng.gen::<f32>() >= survival {
+                    break;
+                }
+                throughput /= survival;
+            }
+        }
+
+        if let Some(aovs) = aovs {
+            aovs.emission = emission;
+            aovs.direct = direct;
+            aovs.indirect = color - emission - direct;
+        }
+
+        color
+    }
+
+    /// Weight of the emission found along a ray scattered with the given material pdf.
+    fn emission_weight(&self, lights: &dyn Hittable, ray: &Ray, material_pdf: Option<f32>) -> f32 {
+        match (self.next_event, material_pdf) {
+            (Some(heuristic), Some(pdf)) => {
+                // lights without any objects give a nan pdf
+                let light_pdf = lights.pdf_value(ray.ori, ray.dir).max(0.0);
+                heuristic.weight(pdf, light_pdf)
+            }
+            _ => 1.0,
+        }
+    }
+}
+
+/// Light arriving at a hit along a shadow ray to a point sampled on the lights, weighted by
+/// the heuristic. `f` gives the scattered fraction of the light and the material pdf of a
+/// direction.
+pub(crate) fn sample_light(
+    heuristic: Heuristic,
+    hit: &HitRecord,
+    ray: &Ray,
+    background: &Vector3<f32>,
+    world: &dyn Hittable,
+    lights: &dyn Hittable,
+    f: impl Fn(&Ray) -> (Vector3<f32>, f32),
+) -> Vector3<f32> {
+    // lights without any objects give a nan pdf
+    let direction = lights.random(hit.p);
+    let light_pdf = lights.pdf_value(hit.p, direction);
+    if light_pdf.is_nan() || light_pdf <= 0.0 {
+        return Vector3::zeros();
+    }
+
+    let shadow_ray = Ray::with_time(hit.p, direction, ray.time);
+    let (value, material_pdf) = f(&shadow_ray);
+    if value == Vector3::zeros() {
+        return Vector3::zeros();
+    }
+
+    // anything in between blocks the light, and carries its own emission if any
+    let radiance = match world.hit(&shadow_ray, 0.001, f32::MAX) {
+        None => *background,
+        Some(light) => light.m.emitted(&light),
+    };
+    value.component_mul(&radiance) * heuristic.weight(light_pdf, material_pdf) / light_pdf
+}
+
+/// Mean green value of a small seeded render of the smoke filled Cornell box, for comparing
+/// integrators.
+#[cfg(test)]
+pub(crate) fn test_render_mean(integrator: Integrator, seed: u64, samples_per_pixel: u16) -> f32 {
+    use crate::renderer::{render_to_float_buffer, RenderSettings};
+    use crate::scene::scene_description;
+
+    let (camera, world, lights, background) = scene_description(7).build().unwrap();
+    let settings = RenderSettings {
+        samples_per_pixel,
+        max_depth: 8,
+        seed: Some(seed),
+        integrator,
+        ..RenderSettings::new(16, 16)
+    };
+    let image = render_to_float_buffer(&camera, &world, &lights, &background, &settings);
+    image.pixels().map(|p| p.0[1]).sum::<f32>() / 256.0
+}
+
+#[test]
+fn test_path_trace() {
+    use crate::geometry::rectangle::{Plane, RectAA};
+    use crate::geometry::sphere::Sphere;
+    use crate::hit::World;
+    use crate::instance::FlipFace;
+    use crate::material::{Dielectric, DiffuseLight, Lambertian};
+    use crate::medium::Constant;
+    use crate::texture::SolidColor;
+
+    // light panel above the scene, facing down
+    let light = || {
+        let emit = DiffuseLight::new(SolidColor::new(4.0, 4.0, 4.0));
+        FlipFace::new(RectAA::new(Plane::XZ, -1.0, 1.0, -2.0, 0.0, 2.0, emit))
+    };
+    let mut world = World::new();
+    world.push(Sphere::new(
+        Vector3::new(0.0, -100.5, -1.0),
+        100.0,
+        Lambertian::new(SolidColor::new(0.5, 0.5, 0.5)),
+    ));
+    world.push(Sphere::new(
+        Vector3::new(0.0, 0.0, -1.0),
+        0.5,
+        Dielectric::new(1.5),
+    ));
+    let smoke = Sphere::new(Vector3::new(-0.8, 0.0, -1.2), 0.3, Dielectric::new(1.0));
+    world.push(Constant::new(smoke, 2.0, SolidColor::new(0.8, 0.8, 0.8)));
+    world.push(light());
+    let mut lights = World::new();
+    lights.push(light());
+    let background = Vector3::new(0.1, 0.1, 0.1);
+
+    let tracer = |roulette_depth, next_event| PathTracer {
+        roulette_depth,
+        next_event,
+    };
+
+    // mean and variance of the mean, skipping invalid samples like the renderer
+    let estimate = |sample: &dyn Fn() -> Vector3<f32>, n: usize| {
+        let samples: Vec<f32> = (0..n).map(|_| sample().x).filter(|c| !c.is_nan()).collect();
+        let n = samples.len() as f32;
+        let mean = samples.iter().sum::<f32>() / n;
+        let variance = samples.iter().map(|c| (c - mean).powi(2)).sum::<f32>() / n;
+        (mean, variance / n)
+    };
+
+    let rays = [
+        // through the glass sphere, onto the floor next to it and into the smoke
+        Ray::new(Vector3::zeros(), Vector3::new(0.0, 0.0, -1.0)),
+        Ray::new(Vector3::zeros(), Vector3::new(0.3, -0.4, -1.0)),
+        Ray::new(Vector3::zeros(), Vector3::new(-0.2, -0.5, -1.0)),
+        Ray::new(Vector3::zeros(), Vector3::new(-0.8, 0.0, -1.2)),
+    ];
+
+    for (i, ray) in rays.iter().enumerate() {
+        // without roulette and shadow rays the same random numbers give the same color
+        for seed in 0..8 {
+            sampler::seed(seed);
+            let recursive = ray.color(&background, &world, &lights, 10);
+            sampler::seed(seed);
+            let iterative = tracer(10, None).color(ray, &background, &world, &lights, 10, None);
+            assert!((recursive - iterative).norm() < 1e-4 * recursive.norm().max(1.0));
+        }
+
+        sampler::seed(i as u64);
+        let (expected, expected_var) =
+            estimate(&|| ray.color(&background, &world, &lights, 10), 10000);
+
+        // roulette and next event estimation give the same mean within the noise
+        for (roulette_depth, next_event) in [
+            (1, None),
+            (10, Some(Heuristic::Balance)),
+            (1, Some(Heuristic::Power)),
+        ] {
+            let tracer = tracer(roulette_depth, next_event);
+            let (mean, var) = estimate(
+                &|| tracer.color(ray, &background, &world, &lights, 10, None),
+                10000,
+            );
+            assert!(
+                (expected - mean).abs() <= 4.0 * (expected_var + var).sqrt() + 1e-6,
+                "{:?}: {} != {}",
+                tracer,
+                mean,
+                expected
+            );
+        }
+    }
+
+    // a floor under a small light converges much faster with shadow rays
+    let small_light = || {
+        let emit = DiffuseLight::new(SolidColor::new(50.0, 50.0, 50.0));
+        Sphere::new(Vector3::new(0.0, 1.0, -1.0), 0.1, emit)
+    };
+    let mut world = World::new();
+    world.push(Sphere::new(
+        Vector3::new(0.0, -100.5, -1.0),
+        100.0,
+        Lambertian::new(SolidColor::new(0.5, 0.5, 0.5)),
+    ));
+    world.push(small_light());
+    let mut lights = World::new();
+    lights.push(small_light());
+
+    let ray = Ray::new(Vector3::zeros(), Vector3::new(0.0, -0.5, -1.0));
+    let (expected, expected_var) = estimate(&|| ray.color(&background, &world, &lights, 10), 10000);
+    let tracer = PathTracer::default();
+    let (mean, var) = estimate(
+        &|| tracer.color(&ray, &background, &world, &lights, 10, None),
+        10000,
+    );
+    assert!((expected - mean).abs() <= 4.0 * (expected_var + var).sqrt());
+    assert!(var < 0.25 * expected_var);
+
+    assert_eq!(Heuristic::Balance.weight(1.0, 3.0), 0.25);
+    assert_eq!(Heuristic::Power.weight(1.0, 3.0), 0.1);
+    assert_eq!(Heuristic::Power.weight(0.0, 0.0), 0.0);
+}
@@ -0,0 +1,86 @@
+//! A cpu-based raytracer following the _Ray Tracing in One Weekend_ book series.
+//!
+//! Scenes are built from [`Hittable`] objects with a [`Material`] and optionally a
+//! [`Texture`], grouped in a [`World`] or a [`Bvh`], and rendered through a [`Camera`]:
+//!
+//! ```
+//! use nalgebra::Vector3;
+//! use raytracer::{
+//!     geometry::sphere::Sphere, material::Lambertian, render_to_buffer, texture::SolidColor,
+//!     Camera, Hittable, RenderSettings, World,
+//! };
+//!
+//! let mut world = World::new();
+//! let ground = Lambertian::new(SolidColor::new(0.5, 0.5, 0.5));
+//! world.push(Sphere::new(Vector3::new(0.0, -100.5, -1.0), 100.0, ground));
+//!
+//! let cam = Camera::new(
+//!     Vector3::new(0.0, 0.0, 1.0),
+//!     Vector3::new(0.0, 0.0, -1.0),
+//!     Vector3::new(0.0, 1.0, 0.0),
+//!     90.0,
+//!     1.0,
+//!     0.0,
+//!     1.0,
+//! );
+//!
+//! let world: Box<dyn Hittable> = Box::new(world);
+//! let lights: Box<dyn Hittable> = Box::new(World::new());
+//! let mut settings = RenderSettings::new(16, 16);
+//! settings.samples_per_pixel = 4;
+//! settings.max_depth = 10;
+//! settings.seed = Some(1);
+//! let background = Vector3::new(0.7, 0.8, 1.0);
+//!
+//! let image = render_to_buffer(&cam, &world, &lights, &background, &settings);
+//! assert_eq!(image.dimensions(), (16, 16));
+//! ```
+//!
+//! Scenes can also be loaded from JSON files through [`SceneDescription`].
+
+pub mod animation;
+pub mod aov;
+pub mod bdpt;
+pub mod bhv;
+pub mod bounding_box;
+pub mod camera;
+pub mod description;
+pub mod hit;
+pub mod instance;
+pub mod integrator;
+pub mod material;
+pub mod medium;
+pub mod mlt;
+pub mod noise;
+pub mod output;
+pub mod pdf;
+pub mod photon;
+pub mod ray;
+pub mod renderer;
+pub mod sampler;
+pub mod scene;
+pub mod texture;
+
+pub mod geometry {
+    pub mod cube;
+    pub mod mesh;
+    pub mod rectangle;
+    pub mod sphere;
+    pub mod triangle;
+}
+
+pub mod loader {
+    pub mod obj;
+    pub mod ply;
+    pub mod stl;
+}
+
+pub use bhv::{Bvh, BvhSettings, SplitMethod};
+pub use camera::Camera;
+pub use description::{SceneDescription, SceneError};
+pub use hit::{HitRecord, Hittable, World};
+pub use material::Material;
+pub use renderer::{
+    render, render_passes, render_to_buffer, render_to_float_buffer, RenderSettings,
+};
+pub use texture::Texture;
@@ -0,0 +1,514 @@
+//! Wavefront OBJ model loading, with the materials from the referenced MTL libraries.
+//!
+//! Faces are grouped by material into one [`TriangleMesh`] each. MTL materials are mapped
+//! onto the materials of the raytracer:
+//!
+//! - a non-zero emission `Ke` gives a [`DiffuseLight`],
+//! - a dissolve `d` below one (or `Tr` above zero) or a refraction `illum` model gives a
+//!   [`Dielectric`] with refraction index `Ni`,
+//! - a reflection `illum` model with a non-zero `Ks` gives a [`Metal`], with the fuzz derived
+//!   from the specular exponent `Ns`,
+//! - anything else gives a [`Lambertian`] with the `map_Kd` image or the `Kd` color.
+
+use crate::{
+    geometry::mesh::{MeshData, TriangleMesh},
+    hit::World,
+    material::{Dielectric, DiffuseLight, Lambertian, Material, Metal},
+    texture::{Image, SolidColor},
+};
+use nalgebra::{Vector2, Vector3};
+use std::{
+    collections::HashMap,
+    error::Error,
+    fmt, fs, io,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+#[derive(Debug)]
+pub enum ObjErrorKind {
+    /// A referenced file could not be read.
+    Io(PathBuf, io::Error),
+    /// A referenced texture could not be loaded.
+    Image(PathBuf, Box<image::ImageError>),
+    Syntax(String),
+    /// A face references a vertex, texture coordinate or normal that does not exist.
+    BadIndex(String),
+    Unsupported(String),
+    UnknownMaterial(String),
+    NoFaces,
+}
+
+/// Error while loading an OBJ or MTL file, with the line of the offending statement.
+#[derive(Debug)]
+pub struct ObjError {
+    pub path: PathBuf,
+    pub line: Option<usize>,
+    pub kind: ObjErrorKind,
+}
+
+impl fmt::Display for ObjErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ObjErrorKind::Io(path, err) => {
+                write!(f, "failed to read '{}': {}", path.display(), err)
+            }
+            ObjErrorKind::Image(path, err) => {
+                write!(f, "failed to load texture '{}': {}", path.display(), err)
+            }
+            ObjErrorKind::Syntax(msg) => write!(f, "{}", msg),
+            ObjErrorKind::BadIndex(index) => write!(f, "index '{}' out of range", index),
+            ObjErrorKind::Unsupported(statement) => {
+                write!(f, "unsupported statement '{}'", statement)
+            }
+            ObjErrorKind::UnknownMaterial(name) => write!(f, "unknown material '{}'", name),
+            ObjErrorKind::NoFaces => write!(f, "model has no faces"),
+        }
+    }
+}
+
+impl fmt::Display for ObjError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.line {
+            Some(line) => write!(f, "{}:{}: {}", self.path.display(), line, self.kind),
+            None => write!(f, "{}: {}", self.path.display(), self.kind),
+        }
+    }
+}
+
+impl Error for ObjError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match &self.kind {
+            ObjErrorKind::Io(_, err) => Some(err),
+            ObjErrorKind::Image(_, err) => Some(err.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+/// Load an OBJ model, faces without a material use `default_material`.
+pub fn load<P: AsRef<Path>>(
+    path: P,
+    default_material: Arc<dyn Material>,
+) -> Result<World, ObjError> {
+    let path = path.as_ref();
+    let source = fs::read_to_string(path).map_err(|err| ObjError {
+        path: path.to_path_buf(),
+        line: None,
+        kind: ObjErrorKind::Io(path.to_path_buf(), err),
+    })?;
+
+    parse(&source, path, default_material)
+}
+
+/// Position, texture coordinate and normal index of a face corner.
+type Corner = (usize, Option<usize>, Option<usize>);
+
+struct Group {
+    material: Arc<dyn Material>,
+    faces: Vec<[Corner; 3]>,
+}
+
+/// Current file and line, to attach to errors.
+struct Location<'a> {
+    path: &'a Path,
+    line: usize,
+}
+
+impl Location<'_> {
+    fn error(&self, kind: ObjErrorKind) -> ObjError {
+        ObjError {
+            path: self.path.to_path_buf(),
+            line: Some(self.line),
+            kind,
+        }
+    }
+
+    fn syntax(&self, msg: impl Into<String>) -> ObjError {
+        self.error(ObjErrorKind::Syntax(msg.into()))
+    }
+
+    /// Parse between `min` and `max` numbers, only the first `min` are returned.
+    fn floats<const N: usize>(&self, args: &[&str], max: usize) -> Result<[f32; N], ObjError> {
+        if args.len() < N || args.len() > max {
+            return Err(self.syntax(format!("expected {} to {} numbers", N, max)));
+        }
+
+        let mut values = [0.0; N];
+        for (value, arg) in values.iter_mut().zip(args) {
+            *value = arg
+                .parse()
+                .map_err(|_| self.syntax(format!("invalid number '{}'", arg)))?;
+        }
+        Ok(values)
+    }
+
+    /// An RGB color, a single value is used for all channels.
+    fn color(&self, args: &[&str]) -> Result<Vector3<f32>, ObjError> {
+        if args.len() == 1 {
+            let [c] = self.floats(args, 1)?;
+            Ok(Vector3::new(c, c, c))
+        } else {
+            Ok(Vector3::from(self.floats::<3>(args, 3)?))
+        }
+    }
+
+    /// Resolve a one-based (or negative, relative) OBJ index into a buffer of `count` elements.
+    fn index(&self, token: &str, count: usize) -> Result<usize, ObjError> {
+        let index: i64 = token
+            .parse()
+            .map_err(|_| self.syntax(format!("invalid index '{}'", token)))?;
+
+        let resolved = if index > 0 {
+            index - 1
+        } else {
+            count as i64 + index
+        };
+
+        if index == 0 || resolved < 0 || resolved >= count as i64 {
+            Err(self.error(ObjErrorKind::BadIndex(token.to_string())))
+        } else {
+            Ok(resolved as usize)
+        }
+    }
+}
+
+/// Split a line into the statement keyword and its arguments, skipping comments.
+fn tokenize(line: &str) -> Option<(&str, Vec<&str>)> {
+    let line = line.split('#').next().unwrap_or_default();
+    let mut tokens = line.split_whitespace();
+    let keyword = tokens.next()?;
+    Some((keyword, tokens.collect()))
+}
+
+fn parse(
+    source: &str,
+    path: &Path,
+    default_material: Arc<dyn Material>,
+) -> Result<World, ObjError> {
+    let dir = path.parent().unwrap_or_else(|| Path::new(""));
+
+    let mut positions: Vec<Vector3<f32>> = vec![];
+    let mut uvs: Vec<Vector2<f32>> = vec![];
+    let mut normals: Vec<Vector3<f32>> = vec![];
+
+    let mut materials: HashMap<String, Arc<dyn Material>> = HashMap::new();
+    let mut textures: HashMap<PathBuf, Arc<Image>> = HashMap::new();
+
+    let mut groups = vec![Group {
+        material: default_material,
+        faces: vec![],
+    }];
+    let mut group_index: HashMap<String, usize> = HashMap::new();
+    let mut current = 0;
+
+    for (i, line) in source.lines().enumerate() {
+        let loc = Location { path, line: i + 1 };
+        let (keyword, args) = match tokenize(line) {
+            Some(statement) => statement,
+            None => continue,
+        };
+
+        match keyword {
+            "v" => positions.push(Vector3::from(loc.floats::<3>(&args, 7)?)),
+            "vt" => {
+                let [u] = loc.floats(&args, 3)?;
+                let v = match args.get(1) {
+                    Some(_) => loc.floats::<2>(&args, 3)?[1],
+                    None => 0.0,
+                };
+                uvs.push(Vector2::new(u, v));
+            }
+            "vn" => normals.push(Vector3::from(loc.floats::<3>(&args, 3)?).normalize()),
+            "f" => {
+                if args.len() < 3 {
+                    return Err(loc.syntax("face with less than three vertices"));
+                }
+
+                let corners = args
+                    .iter()
+                    .map(|arg| {
+                        let mut parts = arg.split('/');
+                        let v = loc.index(parts.next().unwrap_or_default(), positions.len())?;
+                        let vt = match parts.next() {
+                            Some("") | None => None,
+                            Some(vt) => Some(loc.index(vt, uvs.len())?),
+                        };
+                        let vn = match parts.next() {
+                            Some("") | None => None,
+                            Some(vn) => Some(loc.index(vn, normals.len())?),
+                        };
+                        if parts.next().is_some() {
+                            return Err(loc.syntax(format!("invalid face vertex '{}'", arg)));
+                        }
+                        Ok((v, vt, vn))
+                    })
+                    .collect::<Result<Vec<Corner>, ObjError>>()?;
+
+                // triangulate polygons as a fan
+                for k in 1..corners.len() - 1 {
+                    groups[current]
+                        .faces
+                        .push([corners[0], corners[k], corners[k + 1]]);
+                }
+            }
+            "mtllib" => {
+                if args.is_empty() {
+                    return Err(loc.syntax("missing material library"));
+                }
+                for lib in args {
+                    let lib_path = dir.join(lib);
+                    let source = fs::read_to_string(&lib_path)
+                        .map_err(|err| loc.error(ObjErrorKind::Io(lib_path.clone(), err)))?;
+                    parse_mtl(&source, &lib_path, &mut materials, &mut textures)?;
+                }
+            }
+            "usemtl" => {
+                let name = match args.as_slice() {
+                    [name] => *name,
+                    _ => return Err(loc.syntax("expected a single material name")),
+                };
+                current = match group_index.get(name) {
+                    Some(&index) => index,
+                    None => {
+                        let material = materials.get(name).ok_or_else(|| {
+                            loc.error(ObjErrorKind::UnknownMaterial(name.to_string()))
+                        })?;
+                        groups.push(Group {
+                            material: material.clone(),
+                            faces: vec![],
+                        });
+                        group_index.insert(name.to_string(), groups.len() - 1);
+                        groups.len() - 1
+                    }
+                };
+            }
+            // object names, groups and smoothing groups do not affect the geometry
+            "o" | "g" | "s" => {}
+            _ => return Err(loc.error(ObjErrorKind::Unsupported(keyword.to_string()))),
+        }
+    }
+
+    let mut world = World::new();
+    for mesh in groups
+        .iter()
+        .filter_map(|group| build_mesh(group, &positions, &uvs, &normals))
+    {
+        world.push(mesh);
+    }
+
+    if world.objects.is_empty() {
+        return Err(ObjError {
+            path: path.to_path_buf(),
+            line: None,
+            kind: ObjErrorKind::NoFaces,
+        });
+    }
+
+    Ok(world)
+}
+
+/// Build a mesh with a unified index per distinct corner of the group.
+///
+/// Normals and texture coordinates are only used when every corner has one. `None` for a group
+/// without faces.
+fn build_mesh(
+    group: &Group,
+    positions: &[Vector3<f32>],
+    uvs: &[Vector2<f32>],
+    normals: &[Vector3<f32>],
+) -> Option<TriangleMesh> {
+    let corners = || group.faces.iter().flatten();
+    let has_uvs = corners().all(|c| c.1.is_some());
+    let has_normals = corners().all(|c| c.2.is_some());
+
+    let mut mesh = MeshData::default();
+    let mut unified: HashMap<Corner, usize> = HashMap::new();
+
+    for face in group.faces.iter() {
+        let indices = face.map(|(v, vt, vn)| {
+            let key = (
+                v,
+                if has_uvs { vt } else { None },
+                if has_normals { vn } else { None },
+            );
+            *unified.entry(key).or_insert_with(|| {
+                mesh.vertices.push(positions[v]);
+                if let Some(vt) = key.1 {
+                    mesh.uvs.push(uvs[vt]);
+                }
+                if let Some(vn) = key.2 {
+                    mesh.normals.push(normals[vn]);
+                }
+                mesh.vertices.len() - 1
+            })
+        });
+        mesh.indices.push(indices);
+    }
+
+    TriangleMesh::new(mesh, group.material.clone())
+}
+
+/// Material parameters as given in a MTL file.
+struct MtlMaterial {
+    kd: Vector3<f32>,
+    ks: Vector3<f32>,
+    ke: Vector3<f32>,
+    ns: f32,
+    ni: Option<f32>,
+    dissolve: f32,
+    illum: Option<u32>,
+    map_kd: Option<Arc<Image>>,
+}
+
+impl Default for MtlMaterial {
+    fn default() -> Self {
+        Self {
+            kd: Vector3::new(0.8, 0.8, 0.8),
+            ks: Vector3::zeros(),
+            ke: Vector3::zeros(),
+            ns: 0.0,
+            ni: None,
+            dissolve: 1.0,
+            illum: None,
+            map_kd: None,
+        }
+    }
+}
+
+impl MtlMaterial {
+    fn build(self) -> Arc<dyn Material> {
+        let solid = |c: Vector3<f32>| SolidColor::new(c.x, c.y, c.z);
+
+        if self.ke != Vector3::zeros() {
+            Arc::new(DiffuseLight::new(solid(self.ke)))
+        } else if self.dissolve < 1.0 || matches!(self.illum, Some(4 | 6 | 7 | 9)) {
+            Arc::new(Dielectric::new(self.ni.unwrap_or(1.5)))
+        } else if matches!(self.illum, Some(3 | 5 | 8)) && self.ks != Vector3::zeros() {
+            // map the specular exponent onto a roughness
+            let fuzz = (2.0 / (self.ns.max(0.0) + 2.0)).sqrt();
+            Arc::new(Metal::new(solid(self.ks), fuzz))
+        } else {
+            match self.map_kd {
+                Some(image) => Arc::new(Lambertian::new(image)),
+                None => Arc::new(Lambertian::new(solid(self.kd))),
+            }
+        }
+    }
+}
+
+fn parse_mtl(
+    source: &str,
+    path: &Path,
+    materials: &mut HashMap<String, Arc<dyn Material>>,
+    textures: &mut HashMap<PathBuf, Arc<Image>>,
+) -> Result<(), ObjError> {
+    let dir = path.parent().unwrap_or_else(|| Path::new(""));
+
+    let mut current: Option<(String, MtlMaterial)> = None;
+
+    for (i, line) in source.lines().enumerate() {
+        let loc = Location { path, line: i + 1 };
+        let (keyword, args) = match tokenize(line) {
+            Some(statement) => statement,
+            None => continue,
+        };
+
+        if keyword == "newmtl" {
+            let name = match args.as_slice() {
+                [name] => name.to_string(),
+                _ => return Err(loc.syntax("expected a single material name")),
+            };
+            if let Some((name, mtl)) = current.replace((name, MtlMaterial::default())) {
+                materials.insert(name, mtl.build());
+            }
+            continue;
+        }
+
+        let mtl = match current.as_mut() {
+            Some((_, mtl)) => mtl,
+            None => return Err(loc.syntax(format!("'{}' before newmtl", keyword))),
+        };
+
+        match keyword {
+            "Kd" => mtl.kd = loc.color(&args)?,
+            "Ks" => mtl.ks = loc.color(&args)?,
+            "Ke" => mtl.ke = loc.color(&args)?,
+            "Ns" => mtl.ns = loc.floats::<1>(&args, 1)?[0],
+            "Ni" => mtl.ni = Some(loc.floats::<1>(&args, 1)?[0]),
+            "d" => mtl.dissolve = loc.floats::<1>(&args, 1)?[0],
+            "Tr" => mtl.dissolve = 1.0 - loc.floats::<1>(&args, 1)?[0],
+            "illum" => {
+                let [illum] = loc.floats::<1>(&args, 1)?;
+                mtl.illum = Some(illum as u32);
+            }
+            "map_Kd" => {
+                // texture options precede the file name
+                let file = args
+                    .last()
+                    .ok_or_else(|| loc.syntax("missing texture file"))?;
+                let texture_path = dir.join(file);
+                let image = match textures.get(&texture_path) {
+                    Some(image) => image.clone(),
+                    None => {
+                        let image = Image::load(&texture_path).map_err(|err| {
+                            loc.error(ObjErrorKind::Image(texture_path.clone(), Box::new(err)))
+                        })?;
+                        let image = Arc::new(image);
+                        textures.insert(texture_path, image.clone());
+                        image
+                    }
+                };
+                mtl.map_kd = Some(image);
+            }
+            // statements without an equivalent in the materials of the raytracer
+            "Ka" | "Tf" | "sharpness" | "map_Ka" | "map_Ks" | "map_Ke" | "map_Ns" | "map_d"
+            | "map_Bump" | "map_bump" | "bump" | "disp" | "decal" | "refl" | "norm" | "Pr"
+            | "Pm" | "Ps" | "Pc" | "Pcr" | "aniso" | "anisor" | "map_Pr" | "map_Pm" => {}
+            _ => return Err(loc.error(ObjErrorKind::Unsupported(keyword.to_string()))),
+        }
+    }
+
+    if let Some((name, mtl)) = current {
+        materials.insert(name, mtl.build());
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_parse_obj() {
+    use crate::hit::Hittable;
+    use crate::ray::Ray;
+
+    let default: Arc<dyn Material> = Arc::new(Lambertian::new(SolidColor::new(0.5, 0.5, 0.5)));
+
+    // a unit quad in the xy plane, given as a single polygon with relative indices
+    let source = "
+        # quad
+        v 0 0 0
+        v 1 0 0
+        v 1 1 0
+        v 0 1 0
+        vn 0 0 1
+        f -4//1 -3//1 -2//1 -1//1
+    ";
+    let world = parse(source, Path::new("quad.obj"), default.clone()).unwrap();
+    let r = Ray::new(Vector3::new(0.75, 0.75, 1.0), Vector3::new(0.0, 0.0, -1.0));
+    assert!((world.hit(&r, 0.001, f32::MAX).unwrap().t - 1.0).abs() < 1e-6);
+
+    let err = parse(
+        "v 0 0 0\nv 1 0 0\nf 1 2 3\n",
+        Path::new("bad.obj"),
+        default.clone(),
+    )
+    .err()
+    .unwrap();
+    assert_eq!(err.line, Some(3));
+    assert!(matches!(err.kind, ObjErrorKind::BadIndex(_)));
+
+    let err = parse("v 0 0 0\nl 1 1\n", Path::new("line.obj"), default)
+        .err()
+        .unwrap();
+    assert_eq!(err.to_string(), "line.obj:2: unsupported statement 'l'");
+}
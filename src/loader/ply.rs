@@ -0,0 +1,454 @@
+//! Stanford PLY mesh loading, in ascii and binary (little and big endian) format.
+//!
+//! The `vertex` element provides the positions (`x`, `y`, `z`) and optionally the normals
+//! (`nx`, `ny`, `nz`), colors (`red`, `green`, `blue`) and texture coordinates (`u`, `v` or
+//! `s`, `t`). The `face` element lists the vertex indices of each polygon in its
+//! `vertex_indices` property. Other elements and properties are skipped.
+//!
+//! Vertex colors are available to materials through the
+//! [`VertexColor`](crate::texture::VertexColor) texture.
+
+use crate::{
+    geometry::mesh::{MeshData, TriangleMesh},
+    material::Material,
+};
+use nalgebra::{Vector2, Vector3};
+use std::{
+    error::Error,
+    fmt, fs, io,
+    path::{Path, PathBuf},
+};
+
+#[derive(Debug)]
+pub enum PlyErrorKind {
+    Io(io::Error),
+    /// Invalid header, with the header line.
+    Header(usize, String),
+    /// Invalid or truncated element data.
+    Data(String),
+    /// A face references a vertex that does not exist.
+    BadIndex {
+        face: usize,
+        index: i64,
+    },
+    NoFaces,
+}
+
+#[derive(Debug)]
+pub struct PlyError {
+    pub path: PathBuf,
+    pub kind: PlyErrorKind,
+}
+
+impl fmt::Display for PlyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let path = self.path.display();
+        match &self.kind {
+            PlyErrorKind::Io(err) => write!(f, "{}: {}", path, err),
+            PlyErrorKind::Header(line, msg) => write!(f, "{}:{}: {}", path, line, msg),
+            PlyErrorKind::Data(msg) => write!(f, "{}: {}", path, msg),
+            PlyErrorKind::BadIndex { face, index } => write!(
+                f,
+                "{}: face {} references vertex {} out of range",
+                path, face, index
+            ),
+            PlyErrorKind::NoFaces => write!(f, "{}: mesh has no faces", path),
+        }
+    }
+}
+
+impl Error for PlyError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match &self.kind {
+            PlyErrorKind::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+/// Load a PLY mesh with a single material.
+pub fn load<P: AsRef<Path>, M: Material + 'static>(
+    path: P,
+    material: M,
+) -> Result<TriangleMesh, PlyError> {
+    let path = path.as_ref();
+    let error = |kind| PlyError {
+        path: path.to_path_buf(),
+        kind,
+    };
+
+    let data = fs::read(path).map_err(|err| error(PlyErrorKind::Io(err)))?;
+    let mesh = parse(&data).map_err(error)?;
+
+    TriangleMesh::new(mesh, material).ok_or_else(|| error(PlyErrorKind::NoFaces))
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Scalar {
+    I8,
+    U8,
+    I16,
+    U16,
+    I32,
+    U32,
+    F32,
+    F64,
+}
+
+impl Scalar {
+    fn from_name(name: &str) -> Option<Self> {
+        Some(match name {
+            "char" | "int8" => Scalar::I8,
+            "uchar" | "uint8" => Scalar::U8,
+            "short" | "int16" => Scalar::I16,
+            "ushort" | "uint16" => Scalar::U16,
+            "int" | "int32" => Scalar::I32,
+            "uint" | "uint32" => Scalar::U32,
+            "float" | "float32" => Scalar::F32,
+            "double" | "float64" => Scalar::F64,
+            _ => return None,
+        })
+    }
+
+    /// Full scale of a color channel stored in this type.
+    fn color_scale(self) -> f32 {
+        match self {
+            Scalar::I8 | Scalar::U8 => 255.0,
+            Scalar::I16 | Scalar::U16 => 65535.0,
+            Scalar::I32 | Scalar::U32 => u32::MAX as f32,
+            Scalar::F32 | Scalar::F64 => 1.0,
+        }
+    }
+}
+
+enum Property {
+    Scalar(String, Scalar),
+    List(String, Scalar, Scalar),
+}
+
+impl Property {
+    fn name(&self) -> &str {
+        match self {
+            Property::Scalar(name, _) | Property::List(name, _, _) => name,
+        }
+    }
+}
+
+struct Element {
+    name: String,
+    count: usize,
+    properties: Vec<Property>,
+}
+
+impl Element {
+    fn find(&self, names: &[&str]) -> Option<usize> {
+        self.properties
+            .iter()
+            .position(|p| matches!(p, Property::Scalar(..)) && names.contains(&p.name()))
+    }
+}
+
+enum Format {
+    Ascii,
+    BinaryLittleEndian,
+    BinaryBigEndian,
+}
+
+/// Split the header from the element data.
+fn parse_header(data: &[u8]) -> Result<(Format, Vec<Element>, &[u8]), PlyErrorKind> {
+    let mut format = None;
+    let mut elements: Vec<Element> = vec![];
+
+    let mut rest = data;
+    let mut line_number = 0;
+    loop {
+        line_number += 1;
+        let end = rest
+            .iter()
+            .position(|&b| b == b'\n')
+            .ok_or_else(|| PlyErrorKind::Header(line_number, "missing end_header".into()))?;
+        let line = std::str::from_utf8(&rest[..end])
+            .map_err(|_| PlyErrorKind::Header(line_number, "invalid header".into()))?;
+        rest = &rest[end + 1..];
+
+        let error = |msg: &str| PlyErrorKind::Header(line_number, msg.to_string());
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+
+        if line_number == 1 {
+            if tokens != ["ply"] {
+                return Err(error("not a ply file"));
+            }
+            continue;
+        }
+
+        match tokens.as_slice() {
+            ["format", name, _version] => {
+                format = Some(match *name {
+                    "ascii" => Format::Ascii,
+                    "binary_little_endian" => Format::BinaryLittleEndian,
+                    "binary_big_endian" => Format::BinaryBigEndian,
+                    _ => return Err(error("unsupported format")),
+                })
+            }
+            ["element", name, count] => elements.push(Element {
+                name: name.to_string(),
+                count: count.parse().map_err(|_| error("invalid element count"))?,
+                properties: vec![],
+            }),
+            ["property", "list", count, item, name] => {
+                let count = Scalar::from_name(count).ok_or_else(|| error("unknown type"))?;
+                let item = Scalar::from_name(item).ok_or_else(|| error("unknown type"))?;
+                elements
+                    .last_mut()
+                    .ok_or_else(|| error("property before element"))?
+                    .properties
+                    .push(Property::List(name.to_string(), count, item));
+            }
+            ["property", ty, name] => {
+                let ty = Scalar::from_name(ty).ok_or_else(|| error("unknown type"))?;
+                elements
+                    .last_mut()
+                    .ok_or_else(|| error("property before element"))?
+                    .properties
+                    .push(Property::Scalar(name.to_string(), ty));
+            }
+            ["end_header"] => {
+                let format = format.ok_or_else(|| error("missing format"))?;
+                return Ok((format, elements, rest));
+            }
+            ["comment", ..] | ["obj_info", ..] | [] => {}
+            _ => return Err(error("invalid header statement")),
+        }
+    }
+}
+
+/// Element data reader for the ascii and binary formats.
+enum Body<'a> {
+    Ascii(std::str::SplitAsciiWhitespace<'a>),
+    Binary { data: &'a [u8], big_endian: bool },
+}
+
+impl Body<'_> {
+    fn take<const N: usize>(data: &mut &[u8]) -> Result<[u8; N], PlyErrorKind> {
+        if data.len() < N {
+            return Err(PlyErrorKind::Data("unexpected end of file".into()));
+        }
+        let (bytes, rest) = data.split_at(N);
+        *data = rest;
+        Ok(bytes.try_into().unwrap())
+    }
+
+    fn read(&mut self, ty: Scalar) -> Result<f64, PlyErrorKind> {
+        match self {
+            Body::Ascii(tokens) => {
+                let token = tokens
+                    .next()
+                    .ok_or_else(|| PlyErrorKind::Data("unexpected end of file".into()))?;
+                token
+                    .parse()
+                    .map_err(|_| PlyErrorKind::Data(format!("invalid number '{}'", token)))
+            }
+            Body::Binary { data, big_endian } => {
+                macro_rules! read {
+                    ($t:ty) => {{
+                        let bytes = Self::take(data)?;
+                        if *big_endian {
+                            <$t>::from_be_bytes(bytes) as f64
+                        } else {
+                            <$t>::from_le_bytes(bytes) as f64
+                        }
+                    }};
+                }
+                Ok(match ty {
+                    Scalar::I8 => read!(i8),
+                    Scalar::U8 => read!(u8),
+                    Scalar::I16 => read!(i16),
+                    Scalar::U16 => read!(u16),
+                    Scalar::I32 => read!(i32),
+                    Scalar::U32 => read!(u32),
+                    Scalar::F32 => read!(f32),
+                    Scalar::F64 => read!(f64),
+                })
+            }
+        }
+    }
+}
+
+fn parse(data: &[u8]) -> Result<MeshData, PlyErrorKind> {
+    let (format, elements, rest) = parse_header(data)?;
+
+    let mut body = match format {
+        Format::Ascii => Body::Ascii(
+            std::str::from_utf8(rest)
+                .map_err(|_| PlyErrorKind::Data("invalid ascii data".into()))?
+                .split_ascii_whitespace(),
+        ),
+        Format::BinaryLittleEndian => Body::Binary {
+            data: rest,
+            big_endian: false,
+        },
+        Format::BinaryBigEndian => Body::Binary {
+            data: rest,
+            big_endian: true,
+        },
+    };
+
+    let mut mesh = MeshData::default();
+    let mut polygons: Vec<Vec<i64>> = vec![];
+
+    for element in elements.iter() {
+        let position = [
+            element.find(&["x"]),
+            element.find(&["y"]),
+            element.find(&["z"]),
+        ];
+        let normal = [
+            element.find(&["nx"]),
+            element.find(&["ny"]),
+            element.find(&["nz"]),
+        ];
+        let color = [
+            element.find(&["red", "diffuse_red"]),
+            element.find(&["green", "diffuse_green"]),
+            element.find(&["blue", "diffuse_blue"]),
+        ];
+        let uv = [
+            element.find(&["u", "s", "texture_u"]),
+            element.find(&["v", "t", "texture_v"]),
+        ];
+        let indices = element.properties.iter().position(|p| {
+            matches!(p, Property::List(..))
+                && ["vertex_indices", "vertex_index"].contains(&p.name())
+        });
+
+        let is_vertex = element.name == "vertex";
+        let is_face = element.name == "face";
+        if is_vertex && position.contains(&None) {
+            return Err(PlyErrorKind::Data("vertex without x, y and z".into()));
+        }
+        if is_face && indices.is_none() {
+            return Err(PlyErrorKind::Data("face without vertex_indices".into()));
+        }
+
+        let mut values = vec![0.0; element.properties.len()];
+        for _ in 0..element.count {
+            for (i, property) in element.properties.iter().enumerate() {
+                match property {
+                    Property::Scalar(_, ty) => values[i] = body.read(*ty)?,
+                    Property::List(_, count, item) => {
+                        let count = body.read(*count)? as usize;
+                        let list = (0..count)
+                            .map(|_| body.read(*item).map(|index| index as i64))
+                            .collect::<Result<Vec<i64>, _>>()?;
+                        if is_face && Some(i) == indices {
+                            polygons.push(list);
+                        }
+                    }
+                }
+            }
+
+            if !is_vertex {
+                continue;
+            }
+
+            let vector = |slots: [Option<usize>; 3]| {
+                Vector3::from(slots.map(|slot| values[slot.unwrap()] as f32))
+            };
+            mesh.vertices.push(vector(position));
+            if !normal.contains(&None) {
+                mesh.normals.push(vector(normal).normalize());
+            }
+            if !color.contains(&None) {
+                let scale = match &element.properties[color[0].unwrap()] {
+                    Property::Scalar(_, ty) => ty.color_scale(),
+                    Property::List(..) => unreachable!(),
+                };
+                mesh.colors.push(vector(color) / scale);
+            }
+            if !uv.contains(&None) {
+                mesh.uvs.push(Vector2::new(
+                    values[uv[0].unwrap()] as f32,
+                    values[uv[1].unwrap()] as f32,
+                ));
+            }
+        }
+    }
+
+    for (face, polygon) in polygons.iter().enumerate() {
+        if polygon.len() < 3 {
+            return Err(PlyErrorKind::Data(format!(
+                "face {} has less than three vertices",
+                face
+            )));
+        }
+        if let Some(&index) = polygon
+            .iter()
+            .find(|&&i| i < 0 || i as usize >= mesh.vertices.len())
+        {
+            return Err(PlyErrorKind::BadIndex { face, index });
+        }
+
+        // triangulate polygons as a fan
+        for k in 1..polygon.len() - 1 {
+            mesh.indices.push([
+                polygon[0] as usize,
+                polygon[k] as usize,
+                polygon[k + 1] as usize,
+            ]);
+        }
+    }
+
+    if mesh.indices.is_empty() {
+        return Err(PlyErrorKind::NoFaces);
+    }
+
+    Ok(mesh)
+}
+
+#[test]
+fn test_parse_ply() {
+    let header = |format: &str| {
+        format!(
+            "ply\nformat {} 1.0\ncomment test\nelement vertex 4\n\
+             property float x\nproperty float y\nproperty float z\n\
+             property uchar red\nproperty uchar green\nproperty uchar blue\n\
+             element face 1\nproperty list uchar int vertex_indices\nend_header\n",
+            format
+        )
+    };
+
+    let vertices = [
+        ([0.0f32, 0.0, 0.0], [255u8, 0, 0]),
+        ([1.0, 0.0, 0.0], [0, 255, 0]),
+        ([1.0, 1.0, 0.0], [0, 0, 255]),
+        ([0.0, 1.0, 0.0], [255, 255, 255]),
+    ];
+
+    let mut ascii = header("ascii");
+    for (p, c) in vertices {
+        ascii += &format!("{} {} {} {} {} {}\n", p[0], p[1], p[2], c[0], c[1], c[2]);
+    }
+    ascii += "4 0 1 2 3\n";
+
+    let mut binary = header("binary_big_endian").into_bytes();
+    for (p, c) in vertices {
+        p.iter().for_each(|x| binary.extend(x.to_be_bytes()));
+        binary.extend(c);
+    }
+    binary.push(4);
+    [0i32, 1, 2, 3]
+        .iter()
+        .for_each(|i| binary.extend(i.to_be_bytes()));
+
+    for data in [ascii.as_bytes(), &binary] {
+        let mesh = parse(data).unwrap();
+        assert_eq!(mesh.vertices[2], Vector3::new(1.0, 1.0, 0.0));
+        assert_eq!(mesh.colors[1], Vector3::new(0.0, 1.0, 0.0));
+        assert_eq!(mesh.indices, vec![[0, 1, 2], [0, 2, 3]]);
+    }
+
+    assert!(matches!(
+        parse(ascii.replace("4 0 1 2 3", "3 0 1 4").as_bytes()),
+        Err(PlyErrorKind::BadIndex { face: 0, index: 4 })
+    ));
+}
@@ -0,0 +1,207 @@
+//! STL mesh loading, in ascii and binary format.
+//!
+//! STL files store every triangle with its own vertices, identical positions are merged into
+//! shared vertices so the mesh is indexed like the other loaders. The facet normals are
+//! ignored, the faces are shaded flat with their winding defining the front side.
+
+use crate::{
+    geometry::mesh::{MeshData, TriangleMesh},
+    material::Material,
+};
+use nalgebra::Vector3;
+use std::{
+    collections::HashMap,
+    error::Error,
+    fmt, fs, io,
+    path::{Path, PathBuf},
+};
+
+#[derive(Debug)]
+pub enum StlErrorKind {
+    Io(io::Error),
+    /// Invalid ascii statement, with the line number.
+    Syntax(usize, String),
+    /// Truncated or inconsistent binary data.
+    Data(String),
+    NoFaces,
+}
+
+#[derive(Debug)]
+pub struct StlError {
+    pub path: PathBuf,
+    pub kind: StlErrorKind,
+}
+
+impl fmt::Display for StlError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let path = self.path.display();
+        match &self.kind {
+            StlErrorKind::Io(err) => write!(f, "{}: {}", path, err),
+            StlErrorKind::Syntax(line, msg) => write!(f, "{}:{}: {}", path, line, msg),
+            StlErrorKind::Data(msg) => write!(f, "{}: {}", path, msg),
+            StlErrorKind::NoFaces => write!(f, "{}: mesh has no faces", path),
+        }
+    }
+}
+
+impl Error for StlError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match &self.kind {
+            StlErrorKind::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+/// Load a STL mesh with a single material.
+pub fn load<P: AsRef<Path>, M: Material + 'static>(
+    path: P,
+    material: M,
+) -> Result<TriangleMesh, StlError> {
+    let path = path.as_ref();
+    let error = |kind| StlError {
+        path: path.to_path_buf(),
+        kind,
+    };
+
+    let data = fs::read(path).map_err(|err| error(StlErrorKind::Io(err)))?;
+    let mesh = parse(&data).map_err(error)?;
+
+    TriangleMesh::new(mesh, material).ok_or_else(|| error(StlErrorKind::NoFaces))
+}
+
+/// Builds an indexed mesh from separate triangles, merging identical vertices.
+#[derive(Default)]
+struct MeshBuilder {
+    mesh: MeshData,
+    vertices: HashMap<[u32; 3], usize>,
+}
+
+impl MeshBuilder {
+    fn vertex(&mut self, p: [f32; 3]) -> usize {
+        // adding zero turns -0.0 into 0.0, so both map onto the same vertex
+        let key = p.map(|x| (x + 0.0).to_bits());
+        let mesh = &mut self.mesh;
+        *self.vertices.entry(key).or_insert_with(|| {
+            mesh.vertices.push(Vector3::from(p));
+            mesh.vertices.len() - 1
+        })
+    }
+
+    fn face(&mut self, p: [[f32; 3]; 3]) {
+        let indices = p.map(|p| self.vertex(p));
+        self.mesh.indices.push(indices);
+    }
+}
+
+fn parse(data: &[u8]) -> Result<MeshData, StlErrorKind> {
+    // binary files may also start with "solid", so check whether the size matches first
+    let is_binary = data.len() >= 84 && {
+        let count = u32::from_le_bytes(data[80..84].try_into().unwrap()) as usize;
+        data.len() == 84 + 50 * count
+    };
+
+    let mesh = if is_binary {
+        parse_binary(data)
+    } else if data.starts_with(b"solid") {
+        let source = std::str::from_utf8(data)
+            .map_err(|_| StlErrorKind::Data("invalid ascii data".into()))?;
+        parse_ascii(source)?
+    } else {
+        return Err(StlErrorKind::Data(
+            "file size does not match the triangle count".into(),
+        ));
+    };
+
+    if mesh.indices.is_empty() {
+        return Err(StlErrorKind::NoFaces);
+    }
+
+    Ok(mesh)
+}
+
+fn parse_binary(data: &[u8]) -> MeshData {
+    let mut builder = MeshBuilder::default();
+
+    // 80 byte header and the triangle count, followed by 50 bytes per triangle: the normal,
+    // the three vertices and an attribute byte count
+    for record in data[84..].chunks_exact(50) {
+        let float = |i: usize| f32::from_le_bytes(record[4 * i..4 * i + 4].try_into().unwrap());
+        let vertex = |v: usize| [float(3 + 3 * v), float(4 + 3 * v), float(5 + 3 * v)];
+        builder.face([vertex(0), vertex(1), vertex(2)]);
+    }
+
+    builder.mesh
+}
+
+fn parse_ascii(source: &str) -> Result<MeshData, StlErrorKind> {
+    let mut builder = MeshBuilder::default();
+    let mut facet: Vec<[f32; 3]> = vec![];
+
+    for (i, line) in source.lines().enumerate() {
+        let error = |msg: String| StlErrorKind::Syntax(i + 1, msg);
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+
+        match tokens.as_slice() {
+            ["vertex", x, y, z] => {
+                let mut p = [0.0; 3];
+                for (p, token) in p.iter_mut().zip([x, y, z]) {
+                    *p = token
+                        .parse()
+                        .map_err(|_| error(format!("invalid number '{}'", token)))?;
+                }
+                facet.push(p);
+            }
+            ["endloop"] => {
+                if facet.len() != 3 {
+                    return Err(error(format!("facet with {} vertices", facet.len())));
+                }
+                builder.face([facet[0], facet[1], facet[2]]);
+                facet.clear();
+            }
+            ["solid", ..]
+            | ["endsolid", ..]
+            | ["facet", ..]
+            | ["outer", "loop"]
+            | ["endfacet"]
+            | [] => {}
+            _ => return Err(error(format!("invalid statement '{}'", line.trim()))),
+        }
+    }
+
+    Ok(builder.mesh)
+}
+
+#[test]
+fn test_parse_stl() {
+    let quad = [
+        [[0.0f32, 0.0, 0.0], [1.0, 0.0, 0.0], [1.0, 1.0, 0.0]],
+        [[0.0, 0.0, 0.0], [1.0, 1.0, 0.0], [0.0, 1.0, 0.0]],
+    ];
+
+    let mut ascii = "solid quad\n".to_string();
+    let mut binary = vec![0u8; 80];
+    binary.extend(2u32.to_le_bytes());
+    for face in quad {
+        ascii += "facet normal 0 0 1\nouter loop\n";
+        binary.extend([0.0f32, 0.0, 1.0].iter().flat_map(|x| x.to_le_bytes()));
+        for p in face {
+            ascii += &format!("vertex {} {} {}\n", p[0], p[1], p[2]);
+            binary.extend(p.iter().flat_map(|x| x.to_le_bytes()));
+        }
+        ascii += "endloop\nendfacet\n";
+        binary.extend([0, 0]);
+    }
+    ascii += "endsolid quad\n";
+
+    for data in [ascii.as_bytes(), &binary] {
+        let mesh = parse(data).unwrap();
+        assert_eq!(mesh.vertices.len(), 4);
+        assert_eq!(mesh.indices, vec![[0, 1, 2], [0, 2, 3]]);
+    }
+
+    assert!(matches!(
+        parse(ascii.replacen("vertex 1 0 0", "vertex 1 0", 1).as_bytes()),
+        Err(StlErrorKind::Syntax(5, _))
+    ));
+}
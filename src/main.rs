@@ -1,36 +1,423 @@
-use renderer::render;
-use scene::select_scene;
-mod bhv;
-mod bounding_box;
-mod camera;
-mod hit;
-mod instance;
-mod material;
-mod medium;
-mod noise;
-mod pdf;
-mod ray;
-mod renderer;
-mod scene;
-mod texture;
-
-mod geometry {
-    pub mod cube;
-    pub mod rectangle;
-    pub mod sphere;
-}
-
-fn main() {
-    let samples_per_pixel = 10000;
-    for i in 0..=7 {
-        let (cam, world, lights, background) = select_scene(i);
-        let path = format!("./tests/result_{}.png", i);
-        render(&cam, &world, &lights, &background, &path, samples_per_pixel);
-    }
-
-    // let samples_per_pixel = 10000;
-    let i = 8;
-    let (cam, world, lights, background) = select_scene(i);
-    let path = format!("./tests/result_{}.png", i);
-    render(&cam, &world, &lights, &background, &path, samples_per_pixel);
+use clap::{value_parser, Parser, ValueEnum};
+use raytracer::{
+    aov::Aov,
+    integrator::{Heuristic, Integrator, PathTracer},
+    mlt::Metropolis,
+    output::{self, OutputSettings, Precision, ToneMap},
+    photon::PhotonMapping,
+    render_passes, sampler,
+    scene::{scene_description, SCENE_NAMES},
+    BvhSettings, RenderSettings, SceneDescription,
+};
+use std::{
+    error::Error,
+    path::{Path, PathBuf},
+    process::ExitCode,
+};
+
+/// Render a scene to an image.
+#[derive(Parser, Debug)]
+#[command(version, about)]
+struct Args {
+    /// Built-in scene name or path to a scene file
+    scene: String,
+
+    /// Output image, the format is derived from the file extension. OpenEXR (.exr) and Radiance
+    /// (.hdr) files keep the linear color values, other formats get an 8-bit preview. A run of
+    /// '#' is replaced by the zero-padded frame number, e.g. frame_###.png
+    #[arg(short, long, default_value = "result.png")]
+    output: PathBuf,
+
+    /// Also write an 8-bit preview image, numbered like the output
+    #[arg(long, value_name = "FILE")]
+    preview: Option<PathBuf>,
+
+    /// Passes to render alongside the image, comma separated. OpenEXR output gets them as
+    /// layers, other formats a file per pass named like the output, e.g. result_depth.png
+    #[arg(long, value_enum, value_delimiter = ',', value_name = "PASSES")]
+    aov: Vec<Pass>,
+
+    /// Store the channels of OpenEXR output as 16-bit floats
+    #[arg(long)]
+    half: bool,
+
+    /// Tone mapping of 8-bit images
+    #[arg(long, value_enum, default_value_t = ToneMapping::Clamp)]
+    tone_map: ToneMapping,
+
+    /// Luminance mapped to white by the extended Reinhard tone mapping
+    #[arg(long, default_value_t = 4.0)]
+    white_point: f32,
+
+    /// Exposure adjustment of 8-bit images in stops
+    #[arg(long, default_value_t = 0.0, allow_negative_numbers = true)]
+    exposure: f32,
+
+    /// Image width in pixels
+    #[arg(long, default_value_t = 800, value_parser = value_parser!(u32).range(2..))]
+    width: u32,
+
+    /// Image height in pixels, overrides the aspect ratio of the camera [default: width / aspect ratio]
+    #[arg(long, value_parser = value_parser!(u32).range(2..))]
+    height: Option<u32>,
+
+    /// Frames of an animated scene to render, a single frame N or a range FIRST-LAST [default: all frames with a numbered output, else 0]
+    #[arg(long, value_parser = parse_frames)]
+    frames: Option<(u32, u32)>,
+
+    /// Number of samples per pixel
+    #[arg(short, long, default_value_t = 100, value_parser = value_parser!(u16).range(1..))]
+    samples: u16,
+
+    /// Maximum number of bounces per ray
+    #[arg(long, default_value_t = 50, value_parser = value_parser!(u8).range(1..))]
+    max_depth: u8,
+
+    /// Light transport algorithm
+    #[arg(long, value_enum, default_value_t = IntegratorKind::Path)]
+    integrator: IntegratorKind,
+
+    /// Number of bounces before paths are terminated by Russian roulette, used with the path and metropolis integrators
+    #[arg(long, default_value_t = 3)]
+    roulette_depth: u8,
+
+    /// Light sampling of the path and metropolis integrators: shadow rays weighted against material samples by the balance or power heuristic, or a mixture of the light and material pdfs
+    #[arg(long, value_enum, default_value_t = LightSampling::Power)]
+    light_sampling: LightSampling,
+
+    /// Number of photons traced from the lights for every photon map, used with the photon integrator
+    #[arg(long, default_value_t = PhotonMapping::default().photons, value_parser = value_parser!(u32).range(1..))]
+    photons: u32,
+
+    /// Number of nearest photons of the photon map estimates, which sets their radius
+    #[arg(long, default_value_t = PhotonMapping::default().nearest, value_parser = value_parser!(u16).range(1..))]
+    nearest_photons: u16,
+
+    /// Number of final gather rays at the first diffuse hit, 0 to use the photon map right there
+    #[arg(long, default_value_t = PhotonMapping::default().gather_rays)]
+    gather_rays: u16,
+
+    /// Progressive photon mapping: trace new photon maps for every sample and shrink the radius of the estimates
+    #[arg(long)]
+    progressive: bool,
+
+    /// Probability of a metropolis mutation drawing a new independent path instead of a nearby one
+    #[arg(long, default_value_t = Metropolis::default().large_step_probability)]
+    large_step_probability: f32,
+
+    /// Number of independent paths estimating the brightness of the image, used with the metropolis integrator
+    #[arg(long, default_value_t = Metropolis::default().bootstrap, value_parser = value_parser!(u32).range(1..))]
+    bootstrap: u32,
+
+    /// Number of Markov chains of the metropolis integrator, which share the samples of the image
+    #[arg(long, default_value_t = Metropolis::default().chains, value_parser = value_parser!(u32).range(1..))]
+    chains: u32,
+
+    /// Number of render threads [default: number of cores]
+    #[arg(short = 'j', long, value_parser = value_parser!(u16).range(1..))]
+    threads: Option<u16>,
+
+    /// Seed for the random number generators, makes renders and generated scenes reproducible
+    #[arg(long)]
+    seed: Option<u64>,
+
+    /// Write the scene description to a file instead of rendering it
+    #[arg(long, value_name = "FILE")]
+    export: Option<PathBuf>,
+
+    /// Split method for all bvh objects in the scene [default: as given in the scene]
+    #[arg(long, value_enum)]
+    bvh: Option<BvhSplit>,
+
+    /// Maximum number of objects per bvh leaf, used with --bvh
+    #[arg(long, requires = "bvh", value_parser = value_parser!(u16).range(1..))]
+    bvh_leaf_size: Option<u16>,
+
+    /// Print the statistics of the bvh objects in the scene
+    #[arg(long)]
+    bvh_stats: bool,
+}
+
+#[derive(ValueEnum, Copy, Clone, Debug)]
+enum ToneMapping {
+    Clamp,
+    Reinhard,
+    ExtendedReinhard,
+    Aces,
+    Agx,
+}
+
+#[derive(ValueEnum, Copy, Clone, Debug)]
+enum Pass {
+    Depth,
+    Normal,
+    Albedo,
+    ObjectId,
+    MaterialId,
+    Uv,
+    Direct,
+    Indirect,
+    Emission,
+    SampleCount,
+    /// All of the passes
+    All,
+}
+
+#[derive(ValueEnum, Copy, Clone, Debug)]
+enum IntegratorKind {
+    /// Recursive path tracing to the maximum depth
+    Recursive,
+    /// Iterative path tracing with Russian roulette
+    Path,
+    /// Bidirectional path tracing, connecting paths from the camera and from the lights
+    Bidirectional,
+    /// Photon mapping, with caustics and indirect light from photons traced from the lights
+    Photon,
+    /// Metropolis light transport, mutating the random numbers of the path integrator to explore paths that carry much light
+    Metropolis,
+}
+
+#[derive(ValueEnum, Copy, Clone, Debug)]
+enum LightSampling {
+    Mixture,
+    Balance,
+    Power,
+}
+
+#[derive(ValueEnum, Copy, Clone, Debug)]
+enum BvhSplit {
+    Median,
+    Sah,
+}
+
+fn parse_frames(s: &str) -> Result<(u32, u32), String> {
+    let parse = |s: &str| {
+        s.trim()
+            .parse::<u32>()
+            .map_err(|_| format!("invalid frame '{}'", s))
+    };
+    let (first, last) = match s.split_once('-') {
+        Some((first, last)) => (parse(first)?, parse(last)?),
+        None => (parse(s)?, parse(s)?),
+    };
+    if first > last {
+        return Err(format!("first frame {} after last frame {}", first, last));
+    }
+    Ok((first, last))
+}
+
+/// Output path of a frame, with the last run of '#' in the file name replaced by the frame
+/// number.
+fn frame_path(pattern: &Path, frame: u32) -> PathBuf {
+    let pattern = pattern.to_string_lossy();
+    let Some(end) = pattern.rfind('#') else {
+        return PathBuf::from(pattern.as_ref());
+    };
+    let start = pattern[..end].trim_end_matches('#').len();
+    let width = end + 1 - start;
+    PathBuf::from(format!(
+        "{}{:0width$}{}",
+        &pattern[..start],
+        frame,
+        &pattern[end + 1..],
+        width = width
+    ))
+}
+
+fn load_scene(scene: &str) -> Result<SceneDescription, Box<dyn Error>> {
+    if let Some(i) = SCENE_NAMES.iter().position(|&name| name == scene) {
+        return Ok(scene_description(i));
+    }
+
+    let path = PathBuf::from(scene);
+    if !path.exists() {
+        return Err(format!(
+            "unknown scene '{}', expected a scene file or one of: {}",
+            scene,
+            SCENE_NAMES.join(", ")
+        )
+        .into());
+    }
+
+    SceneDescription::load(&path).map_err(|err| format!("{}: {}", path.display(), err).into())
+}
+
+fn run(args: Args) -> Result<(), Box<dyn Error>> {
+    if let Some(seed) = args.seed {
+        sampler::seed(seed);
+    }
+
+    let mut description = load_scene(&args.scene)?;
+
+    if let Some(path) = args.export {
+        description.save(&path)?;
+        return Ok(());
+    }
+
+    // fail before rendering if the output format is not supported
+    let previews = std::iter::once(&args.output).filter(|path| !output::is_hdr(path));
+    for path in previews.chain(&args.preview) {
+        let format = image::ImageFormat::from_path(path)?;
+        if !format.can_write() {
+            return Err(format!("cannot write {:?} images", format).into());
+        }
+    }
+    if args.white_point <= 0.0 {
+        return Err(format!("white point {} is not positive", args.white_point).into());
+    }
+    if !(0.0..=1.0).contains(&args.large_step_probability) {
+        return Err(format!(
+            "large step probability {} is not between 0 and 1",
+            args.large_step_probability
+        )
+        .into());
+    }
+    let output_settings = OutputSettings {
+        precision: if args.half {
+            Precision::Half
+        } else {
+            Precision::Float
+        },
+        tone_map: match args.tone_map {
+            ToneMapping::Clamp => ToneMap::Clamp,
+            ToneMapping::Reinhard => ToneMap::Reinhard,
+            ToneMapping::ExtendedReinhard => ToneMap::ExtendedReinhard {
+                white: args.white_point,
+            },
+            ToneMapping::Aces => ToneMap::Aces,
+            ToneMapping::Agx => ToneMap::Agx,
+        },
+        exposure: args.exposure,
+    };
+
+    let mut aovs = Vec::new();
+    for pass in &args.aov {
+        let pass: &[Aov] = match pass {
+            Pass::Depth => &[Aov::Depth],
+            Pass::Normal => &[Aov::Normal],
+            Pass::Albedo => &[Aov::Albedo],
+            Pass::ObjectId => &[Aov::ObjectId],
+            Pass::MaterialId => &[Aov::MaterialId],
+            Pass::Uv => &[Aov::Uv],
+            Pass::Direct => &[Aov::Direct],
+            Pass::Indirect => &[Aov::Indirect],
+            Pass::Emission => &[Aov::Emission],
+            Pass::SampleCount => &[Aov::SampleCount],
+            Pass::All => &Aov::ALL,
+        };
+        for &aov in pass {
+            if !aovs.contains(&aov) {
+                aovs.push(aov);
+            }
+        }
+    }
+
+    if let Some(threads) = args.threads {
+        rayon::ThreadPoolBuilder::new()
+            .num_threads(threads as usize)
+            .build_global()?;
+    }
+
+    if let Some(height) = args.height {
+        description.camera.aspect_ratio = args.width as f32 / height as f32;
+    }
+
+    if let Some(split) = args.bvh {
+        let mut settings = match split {
+            BvhSplit::Median => BvhSettings::default(),
+            BvhSplit::Sah => BvhSettings::sah(),
+        };
+        if let Some(leaf_size) = args.bvh_leaf_size {
+            settings.max_leaf_size = leaf_size as usize;
+        }
+        description.set_bvh_settings(settings);
+    }
+
+    let tracer = PathTracer {
+        roulette_depth: args.roulette_depth,
+        next_event: match args.light_sampling {
+            LightSampling::Mixture => None,
+            LightSampling::Balance => Some(Heuristic::Balance),
+            LightSampling::Power => Some(Heuristic::Power),
+        },
+    };
+
+    // static objects are built with the first frame and shared by the following ones, the
+    // sequence also rejects an animation without frames
+    let mut sequence = description.sequence()?;
+    let numbered = args.output.to_string_lossy().contains('#');
+    let frame_count = sequence.frame_count();
+    let (first, last) = args.frames.unwrap_or(if numbered {
+        (0, frame_count - 1)
+    } else {
+        (0, 0)
+    });
+    if last >= frame_count {
+        return Err(format!("frame {} out of range for {} frames", last, frame_count).into());
+    }
+    if first != last && !numbered {
+        return Err("rendering several frames needs a numbered output, e.g. frame_###.png".into());
+    }
+
+    for frame in first..=last {
+        let ((cam, world, lights, background), bvh_stats) = sequence.frame(frame)?;
+
+        if args.bvh_stats {
+            for (i, stats) in bvh_stats.iter().enumerate() {
+                println!("bvh {}: {}", i, stats);
+            }
+        }
+
+        let height = args
+            .height
+            .unwrap_or(((args.width as f32 / cam.aspect_ratio) as u32).max(2));
+
+        let mut settings = RenderSettings::new(args.width, height);
+        settings.samples_per_pixel = args.samples;
+        settings.max_depth = args.max_depth;
+        // a different noise pattern in every frame
+        settings.seed = args.seed.map(|seed| seed.wrapping_add(frame as u64));
+        settings.integrator = match args.integrator {
+            IntegratorKind::Recursive => Integrator::Recursive,
+            IntegratorKind::Path => Integrator::Path(tracer),
+            IntegratorKind::Bidirectional => Integrator::Bidirectional,
+            IntegratorKind::Photon => Integrator::PhotonMap(PhotonMapping {
+                photons: args.photons,
+                nearest: args.nearest_photons,
+                gather_rays: args.gather_rays,
+                progressive: args.progressive,
+            }),
+            IntegratorKind::Metropolis => Integrator::Metropolis(Metropolis {
+                tracer,
+                large_step_probability: args.large_step_probability,
+                bootstrap: args.bootstrap,
+                chains: args.chains,
+            }),
+        };
+        settings.progress = true;
+
+        let path = frame_path(&args.output, frame);
+        if numbered {
+            println!("Frame {}: {}", frame, path.display());
+        }
+
+        let (image, passes) = render_passes(&cam, &world, &lights, &background, &settings, &aovs);
+        let passes: Vec<_> = aovs.iter().map(Aov::name).zip(&passes).collect();
+        output::save_passes(&image, &passes, &path, &output_settings)?;
+        if let Some(preview) = &args.preview {
+            output::preview(&image, &output_settings).save(frame_path(preview, frame))?;
+        }
+    }
+
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    match run(Args::parse()) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("error: {}", err);
+            ExitCode::FAILURE
+        }
+    }
 }
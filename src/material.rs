@@ -1,6 +1,7 @@
-use crate::{hit::HitRecord, pdf::Pdf, ray::Ray, texture::Texture};
+use crate::{hit::HitRecord, pdf::Pdf, ray::Ray, sampler, texture::Texture};
 use nalgebra::Vector3;
 use rand::{distributions::Uniform, prelude::Distribution, Rng};
+use std::sync::Arc;
 
 pub enum ScatterRecord<'a> {
     Scatter {
@@ -18,7 +19,7 @@ pub enum ScatterRecord<'a> {
 }
 
 pub fn random_cosine_direction() -> Vector3<f32> {
-    let mut rng = rand::thread_rng();
+    let mut rng = sampler::rng();
     let (r1, r2) = rng.gen::<(f32, f32)>();
 
     let phi = 2.0 * std::f32::consts::PI * r1;
@@ -30,7 +31,7 @@ pub fn random_cosine_direction() -> Vector3<f32> {
 }
 
 pub fn random_unit_vector() -> Vector3<f32> {
-    let mut rng = rand::thread_rng();
+    let mut rng = sampler::rng();
     let (r1, r2) = rng.gen::<(f32, f32)>();
 
     let x = f32::cos(2.0 * std::f32::consts::PI * r1) * 2.0 * f32::sqrt(r2 * (1.0 - r2));
@@ -41,7 +42,7 @@ pub fn random_unit_vector() -> Vector3<f32> {
 }
 
 pub fn random_in_unit_sphere() -> Vector3<f32> {
-    let mut rng = rand::thread_rng();
+    let mut rng = sampler::rng();
 
     const MIN: f32 = -1.0;
     const MAX: f32 = 1.0;
@@ -62,7 +63,7 @@ pub fn random_in_unit_sphere() -> Vector3<f32> {
 }
 
 fn reflect(v: &Vector3<f32>, n: &Vector3<f32>) -> Vector3<f32> {
-    return v - 2.0 * v.dot(n) * n;
+    v - 2.0 * v.dot(n) * n
 }
 
 fn refract(
@@ -83,8 +84,8 @@ fn reflectance(cosine: f32, refraction_ratio: f32) -> f32 {
     r0 + (1.0 - r0) * ((1.0 - cosine).powi(5))
 }
 
-pub trait Material: Sync {
-    fn scatter(&self, _r_in: &Ray, _rec: &HitRecord) -> Option<ScatterRecord> {
+pub trait Material: Send + Sync {
+    fn scatter(&self, _r_in: &Ray, _rec: &HitRecord) -> Option<ScatterRecord<'_>> {
         None
     }
 
@@ -95,6 +96,59 @@ pub trait Material: Sync {
     fn emitted(&self, _rec: &HitRecord) -> Vector3<f32> {
         Vector3::new(0.0, 0.0, 0.0)
     }
+
+    /// Identifier for the material ID pass, 0 for materials without one.
+    fn id(&self) -> u32 {
+        0
+    }
+}
+
+impl<M: Material + ?Sized> Material for Arc<M> {
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<ScatterRecord<'_>> {
+        (**self).scatter(r_in, rec)
+    }
+
+    fn scattering_pdf(&self, rec: &HitRecord, scattered: &Ray) -> f32 {
+        (**self).scattering_pdf(rec, scattered)
+    }
+
+    fn emitted(&self, rec: &HitRecord) -> Vector3<f32> {
+        (**self).emitted(rec)
+    }
+
+    fn id(&self) -> u32 {
+        (**self).id()
+    }
+}
+
+/// Assigns an identifier to a material, for the material ID pass.
+pub struct MaterialId<M: Material> {
+    material: M,
+    id: u32,
+}
+
+impl<M: Material> MaterialId<M> {
+    pub fn new(material: M, id: u32) -> Self {
+        Self { material, id }
+    }
+}
+
+impl<M: Material> Material for MaterialId<M> {
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<ScatterRecord<'_>> {
+        self.material.scatter(r_in, rec)
+    }
+
+    fn scattering_pdf(&self, rec: &HitRecord, scattered: &Ray) -> f32 {
+        self.material.scattering_pdf(rec, scattered)
+    }
+
+    fn emitted(&self, rec: &HitRecord) -> Vector3<f32> {
+        self.material.emitted(rec)
+    }
+
+    fn id(&self) -> u32 {
+        self.id
+    }
 }
 
 #[test]
@@ -118,11 +172,11 @@ impl<T: Texture> Lambertian<T> {
 }
 
 impl<T: Texture> Material for Lambertian<T> {
-    fn scatter(&self, _r_in: &Ray, rec: &HitRecord) -> Option<ScatterRecord> {
+    fn scatter(&self, _r_in: &Ray, rec: &HitRecord) -> Option<ScatterRecord<'_>> {
         // let uvw = Onb::build_from_w(rec.n);
         // let direction: Vector3<f32> = uvw.local(&random_cosine_direction());
         // let scattered = Ray::new(rec.p, direction.normalize());
-        let attenuation: Vector3<f32> = self.albedo.value(rec.u, rec.v, &rec.p);
+        let attenuation: Vector3<f32> = self.albedo.sample(rec);
         let pdf = Pdf::cosine_pdf(rec.n);
 
         Some(ScatterRecord::Scatter { attenuation, pdf })
@@ -147,11 +201,11 @@ impl<T: Texture> Metal<T> {
 }
 
 impl<T: Texture> Material for Metal<T> {
-    fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<ScatterRecord> {
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<ScatterRecord<'_>> {
         let reflected: Vector3<f32> = reflect(&r_in.nrm_dir, &rec.n);
         let direction = reflected + self.fuzz * random_in_unit_sphere();
-        let specular_ray = Ray::new(rec.p, direction);
-        let attenuation: Vector3<f32> = self.albedo.value(rec.u, rec.v, &rec.p);
+        let specular_ray = Ray::with_time(rec.p, direction, r_in.time);
+        let attenuation: Vector3<f32> = self.albedo.sample(rec);
 
         // if direction.dot(&rec.n) > 0.0 {
         Some(ScatterRecord::Specular {
@@ -176,7 +230,7 @@ impl Dielectric {
 }
 
 impl Material for Dielectric {
-    fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<ScatterRecord> {
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<ScatterRecord<'_>> {
         let attenuation = Vector3::new(1.0, 1.0, 1.0);
 
         let refraction_ratio = if rec.front_face {
@@ -191,7 +245,7 @@ impl Material for Dielectric {
 
         let cannot_refract = refraction_ratio * sin_theta > 1.0;
 
-        let mut rng = rand::thread_rng();
+        let mut rng = sampler::rng();
         let direction =
             if cannot_refract || reflectance(cos_theta, refraction_ratio) > rng.gen::<f32>() {
                 reflect(&unit_dir, &rec.n)
@@ -199,7 +253,7 @@ impl Material for Dielectric {
                 refract(&unit_dir, &rec.n, cos_theta, refraction_ratio)
             };
 
-        let specular_ray = Ray::new(rec.p, direction);
+        let specular_ray = Ray::with_time(rec.p, direction, r_in.time);
         Some(ScatterRecord::Specular {
             specular_ray,
             attenuation,
@@ -221,7 +275,7 @@ impl<T: Texture> DiffuseLight<T> {
 impl<T: Texture> Material for DiffuseLight<T> {
     fn emitted(&self, rec: &HitRecord) -> Vector3<f32> {
         if rec.front_face {
-            self.emit.value(rec.u, rec.v, &rec.p)
+            self.emit.sample(rec)
         } else {
             Vector3::new(0.0, 0.0, 0.0)
         }
@@ -240,9 +294,9 @@ impl<T: Texture> Isotropic<T> {
 }
 
 impl<T: Texture> Material for Isotropic<T> {
-    fn scatter(&self, _r_in: &Ray, rec: &HitRecord) -> Option<ScatterRecord> {
-        let scattered_ray = Ray::new(rec.p, random_in_unit_sphere());
-        let attenuation = self.albedo.value(rec.u, rec.v, &rec.p);
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<ScatterRecord<'_>> {
+        let scattered_ray = Ray::with_time(rec.p, random_in_unit_sphere(), r_in.time);
+        let attenuation = self.albedo.sample(rec);
 
         Some(ScatterRecord::Isotropic {
             attenuation,
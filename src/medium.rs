@@ -3,6 +3,7 @@ use crate::{
     hit::{HitRecord, Hittable},
     material::Isotropic,
     ray::Ray,
+    sampler,
     texture::Texture,
 };
 use nalgebra::Vector3;
@@ -17,7 +18,7 @@ pub struct Constant<H: Hittable, T: Texture> {
 impl<H: Hittable, T: Texture> Constant<H, T> {
     pub fn new(boundary: H, density: f32, texture: T) -> Self {
         Constant {
-            boundary: boundary,
+            boundary,
             phase_function: Isotropic::new(texture),
             neg_inv_density: -1.0 / density,
         }
@@ -29,13 +30,13 @@ impl<H: Hittable, T: Texture> Hittable for Constant<H, T> {
         self.boundary.bounding_box()
     }
 
-    fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
-        let mut rng = rand::thread_rng();
+    fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        let mut rng = sampler::rng();
 
-        match self.boundary.hit(&r, f32::MIN, f32::MAX) {
+        match self.boundary.hit(r, f32::MIN, f32::MAX) {
             None => None,
             Some(hit1) => {
-                match self.boundary.hit(&r, hit1.t + 0.0001, f32::MAX) {
+                match self.boundary.hit(r, hit1.t + 0.0001, f32::MAX) {
                     None => None,
                     Some(hit2) => {
                         let t_min = hit1.t.max(t_min);
@@ -66,6 +67,8 @@ impl<H: Hittable, T: Texture> Hittable for Constant<H, T> {
                             v: 0.0,
                             m: &self.phase_function,
                             front_face: true, // arbitrary
+                            color: None,
+                            object_id: 0,
                         })
                     }
                 }
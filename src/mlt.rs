@@ -0,0 +1,250 @@
+//! Metropolis light transport in primary sample space.
+//!
+//! Every sample of the path tracer is a point in the unit hypercube of the random numbers it
+//! draws, its primary samples. Instead of drawing the points independently, Markov chains
+//! mutate them, see [`PrimarySamples`], and accept the mutations in proportion to the
+//! brightness of the paths they turn into. The chains then stay in the parts of the image that
+//! carry the most light and explore the neighborhood of a path once they found it, like the
+//! light falling through a small opening or the paths behind the smoke boxes.
+//!
+//! Every sample of a chain lands on the pixel of its camera ray with its color divided by its
+//! brightness, so the chains only give the distribution of the light over the image. Its total
+//! brightness comes from the independent samples of a bootstrap phase, which also pick the
+//! starting points of the chains.
+
+use crate::{
+    integrator::{PathTracer, Scene},
+    output,
+    renderer::RenderSettings,
+    sampler::{self, PrimarySamples},
+};
+use nalgebra::Vector3;
+use rand::{rngs::SmallRng, Rng, SeedableRng};
+use rayon::prelude::{IntoParallelIterator, ParallelIterator};
+use std::sync::atomic::{self, AtomicU32};
+use std::time::SystemTime;
+
+/// Settings of Metropolis light transport, see the [module](self) documentation.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Metropolis {
+    /// Path tracer turning the primary samples into colors.
+    pub tracer: PathTracer,
+    /// Probability of a mutation drawing a new independent path instead of a nearby one.
+    pub large_step_probability: f32,
+    /// Number of independent samples estimating the brightness of the image.
+    pub bootstrap: u32,
+    /// Number of Markov chains, which share the samples of the render.
+    pub chains: u32,
+}
+
+impl Default for Metropolis {
+    fn default() -> Self {
+        Self {
+            tracer: PathTracer::default(),
+            large_step_probability: 0.3,
+            bootstrap: 100_000,
+            chains: 1000,
+        }
+    }
+}
+
+/// Color of the primary samples and the pixel it lands on.
+#[derive(Debug, Copy, Clone)]
+struct Sample {
+    u: f32,
+    v: f32,
+    color: Vector3<f32>,
+    /// Luminance of the color, the target density of the chains.
+    contribution: f32,
+}
+
+impl Metropolis {
+    /// Render the light of the scene with the samples per pixel of the settings, as the colors
+    /// of the pixels row by row from the top. The chains and the bootstrap samples are seeded
+    /// when a seed is given.
+    pub fn render(&self, scene: &Scene, settings: &RenderSettings) -> Vec<Vector3<f32>> {
+        let (width, height) = (settings.width, settings.height);
+        let pixel_count = (width * height) as usize;
+        let seed = settings.seed.unwrap_or_else(|| sampler::rng().gen());
+        let large_step = self.large_step_probability;
+
+        // the brightness of the image is the mean contribution of independent samples, which
+        // are the starting points of the chains in proportion to their contribution
+        let contributions: Vec<f32> = (0..self.bootstrap)
+            .into_par_iter()
+            .map(|i| {
+                let samples = PrimarySamples::new(seed ^ i as u64, large_step);
+                sampler::with_primary_samples(samples, || self.sample(scene, width, height))
+                    .1
+                    .contribution
+            })
+            .collect();
+        let cdf: Vec<f64> = contributions
+            .iter()
+            .scan(0.0, |sum, &contribution| {
+                *sum += contribution as f64;
+                Some(*sum)
+            })
+            .collect();
+        let total = cdf.last().copied().unwrap_or_default();
+        if settings.progress {
+            println!(
+                "Metropolis bootstrap: {} samples, mean luminance {:.5}",
+                self.bootstrap,
+                total / self.bootstrap.max(1) as f64
+            );
+        }
+        if total <= 0.0 {
+            return vec![Vector3::zeros(); pixel_count];
+        }
+
+        let chains = self.chains.max(1);
+        let mutations = (settings.samples_per_pixel as u64 * pixel_count as u64)
+            .div_ceil(chains as u64)
+            .max(1);
+
+        let total_time = SystemTime::now();
+        let done = AtomicU32::new(0);
+        let image = (0..chains)
+            .into_par_iter()
+            .fold(
+                || vec![Vector3::zeros(); pixel_count],
+                |mut image, chain| {
+                    let chain_seed = seed ^ ((chain as u64 + 1) << 32);
+                    let mut rng = SmallRng::seed_from_u64(chain_seed);
+                    let x = rng.gen::<f64>() * total;
+                    let start = cdf.partition_point(|&sum| sum <= x).min(cdf.len() - 1);
+
+                    let mut splat = |sample: &Sample, weight: f32| {
+                        let i = ((sample.u * (width - 1) as f32) as u32).min(width - 1);
+                        let j = ((sample.v * (height - 1) as f32) as u32).min(height - 1);
+                        let index = (height - 1 - j) * width + i;
+                        image[index as usize] += sample.color * weight;
+                    };
+
+                    // replay the bootstrap sample, with mutations of the chain's own
+                    let samples = PrimarySamples::new(seed ^ start as u64, large_step);
+                    let (mut samples, mut current) = sampler::with_primary_samples(samples, || {
+                        self.sample(scene, width, height)
+                    });
+                    samples.reseed(chain_seed);
+
+                    for _ in 0..mutations {
+                        samples.start_iteration();
+                        let (mutated, proposal) = sampler::with_primary_samples(samples, || {
+                            self.sample(scene, width, height)
+                        });
+                        samples = mutated;
+
+                        // both samples splat their expected share of the iteration
+                        let accept = (proposal.contribution / current.contribution).min(1.0);
+                        if accept > 0.0 {
+                            splat(&proposal, accept / proposal.contribution);
+                        }
+                        splat(&current, (1.0 - accept) / current.contribution);
+
+                        if rng.gen::<f32>() < accept {
+                            current = proposal;
+                            samples.accept();
+                        } else {
+                            samples.reject();
+                        }
+                    }
+
+                    if settings.progress {
+                        let done = done.fetch_add(1, atomic::Ordering::Relaxed) + 1;
+                        println!(
+                            "Chain {} of {}\t| ELA: {:>9.2} [s]",
+                            done,
+                            chains,
+                            total_time.elapsed().unwrap().as_millis() as f32 * 1.0e-3
+                        );
+                    }
+                    image
+                },
+            )
+            .reduce_with(|mut a, b| {
+                a.iter_mut().zip(b).for_each(|(a, b)| *a += b);
+                a
+            })
+            .unwrap_or_default();
+
+        // every sample splats a weight of one over the whole image
+        let scale =
+            total / self.bootstrap as f64 * pixel_count as f64 / (mutations * chains as u64) as f64;
+        image.into_iter().map(|c| c * scale as f32).collect()
+    }
+
+    /// Trace the camera path of the installed primary samples, through a random position on
+    /// the image.
+    fn sample(&self, scene: &Scene, width: u32, height: u32) -> Sample {
+        let mut rng = sampler::rng();
+        // positions up to (width / (width - 1)) x (height / (height - 1)) cover the image
+        let u = rng.gen::<f32>() * width as f32 / (width - 1) as f32;
+        let v = rng.gen::<f32>() * height as f32 / (height - 1) as f32;
+        let ray = scene.camera.ray(u, v);
+        let color = self.tracer.color(
+            &ray,
+            scene.background,
+            scene.world,
+            scene.lights,
+            scene.max_depth,
+            None,
+        );
+
+        let contribution = output::luminance(&color);
+        if contribution > 0.0 && contribution.is_finite() {
+            Sample {
+                u,
+                v,
+                color,
+                contribution,
+            }
+        } else {
+            Sample {
+                u,
+                v,
+                color: Vector3::zeros(),
+                contribution: 0.0,
+            }
+        }
+    }
+}
+
+#[test]
+fn test_metropolis() {
+    use crate::aov::Aov;
+    use crate::integrator::{test_render_mean, Integrator};
+    use crate::renderer::render_passes;
+    use crate::scene::scene_description;
+
+    // same mean as the path tracer, the bootstrap sets the brightness
+    let path = test_render_mean(Integrator::Path(Default::default()), 1 << 20, 16);
+    let metropolis = Metropolis {
+        bootstrap: 4096,
+        chains: 16,
+        ..Default::default()
+    };
+
+    // the passes come from the camera samples of the path tracer
+    let (camera, world, lights, background) = scene_description(7).build().unwrap();
+    let settings = RenderSettings {
+        samples_per_pixel: 2,
+        max_depth: 8,
+        seed: Some(1 << 20),
+        integrator: Integrator::Metropolis(metropolis),
+        ..RenderSettings::new(8, 8)
+    };
+    let aovs = [Aov::Depth, Aov::SampleCount];
+    let (_, passes) = render_passes(&camera, &world, &lights, &background, &settings, &aovs);
+    assert!(passes[0].pixels().any(|p| p.0[0] > 0.0));
+    assert!(passes[1].pixels().all(|p| p.0[0] == 2.0));
+
+    let metropolis = test_render_mean(Integrator::Metropolis(metropolis), 2 << 20, 16);
+    assert!(
+        (metropolis - path).abs() < 0.05 * path,
+        "{} != {}",
+        metropolis,
+        path
+    );
+}
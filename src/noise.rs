@@ -10,6 +10,12 @@ pub struct Perlin {
     perm_z: [i16; Perlin::POINT_COUNT],
 }
 
+impl Default for Perlin {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Perlin {
     const POINT_COUNT: usize = 256;
 
@@ -24,7 +30,7 @@ impl Perlin {
 
     pub fn turb(&self, p: &Vector3<f32>, depth: u8) -> f32 {
         let mut accum = 0.0;
-        let mut temp_p = p.clone();
+        let mut temp_p = *p;
         let mut weight = 1.0;
 
         for _ in 0..depth {
@@ -0,0 +1,437 @@
+//! Writing rendered images.
+//!
+//! Renders are kept as linear floating point images. OpenEXR (`.exr`) and Radiance (`.hdr`)
+//! files store these values as they are, every other format gets an 8-bit preview: exposed,
+//! tone mapped into the displayable range and encoded with the sRGB transfer function.
+
+use exr::prelude::{
+    AnyChannel, AnyChannels, Encoding, FlatSamples, Image, Layer, LayerAttributes, SmallVec,
+    WritableImage,
+};
+use image::{codecs::hdr::HdrEncoder, Rgb, Rgb32FImage, RgbImage};
+use nalgebra::{Matrix3, Vector3};
+use std::{
+    error::Error,
+    fmt,
+    fs::File,
+    io::BufWriter,
+    path::{Path, PathBuf},
+};
+
+/// Precision of the channels of an OpenEXR file.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum Precision {
+    /// 16-bit floats, half the size and enough for display referred values.
+    Half,
+    #[default]
+    Float,
+}
+
+/// Operator compressing linear color values into the displayable range of a preview.
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+pub enum ToneMap {
+    /// Clip values above one.
+    #[default]
+    Clamp,
+    /// Reinhard curve `L / (1 + L)` on the luminance, never reaching white.
+    Reinhard,
+    /// Reinhard curve reaching white at the given luminance.
+    ExtendedReinhard { white: f32 },
+    /// Filmic curve fitted to the ACES reference rendering transform.
+    Aces,
+    /// AgX-style log sigmoid in a widened gamut, desaturating bright colors towards white.
+    Agx,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+pub struct OutputSettings {
+    pub precision: Precision,
+    pub tone_map: ToneMap,
+    /// Exposure adjustment of the preview in stops.
+    pub exposure: f32,
+}
+
+#[derive(Debug)]
+pub enum OutputError {
+    Image(image::ImageError),
+    Exr(exr::error::Error),
+    /// An OpenEXR file was to be written without layers.
+    NoLayers,
+    /// The named layer differs in size from the first layer of an OpenEXR file.
+    LayerSize {
+        name: String,
+        size: (u32, u32),
+        expected: (u32, u32),
+    },
+}
+
+impl fmt::Display for OutputError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OutputError::Image(err) => write!(f, "failed to write image: {}", err),
+            OutputError::Exr(err) => write!(f, "failed to write OpenEXR image: {}", err),
+            OutputError::NoLayers => write!(f, "no layers to write to the OpenEXR image"),
+            OutputError::LayerSize {
+                name,
+                size,
+                expected,
+            } => write!(
+                f,
+                "layer '{}' is {}x{}, expected {}x{} like the first layer",
+                name, size.0, size.1, expected.0, expected.1
+            ),
+        }
+    }
+}
+
+impl Error for OutputError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            OutputError::Image(err) => Some(err),
+            OutputError::Exr(err) => Some(err),
+            OutputError::NoLayers | OutputError::LayerSize { .. } => None,
+        }
+    }
+}
+
+impl From<image::ImageError> for OutputError {
+    fn from(err: image::ImageError) -> Self {
+        OutputError::Image(err)
+    }
+}
+
+impl From<exr::error::Error> for OutputError {
+    fn from(err: exr::error::Error) -> Self {
+        OutputError::Exr(err)
+    }
+}
+
+impl From<std::io::Error> for OutputError {
+    fn from(err: std::io::Error) -> Self {
+        OutputError::Image(image::ImageError::IoError(err))
+    }
+}
+
+/// Whether the file format of the path keeps the high dynamic range of the image.
+pub fn is_hdr(path: &Path) -> bool {
+    matches!(
+        path.extension()
+            .and_then(|e| e.to_str())
+            .map(str::to_ascii_lowercase)
+            .as_deref(),
+        Some("exr" | "hdr")
+    )
+}
+
+/// 8-bit sRGB preview, exposed and tone mapped following the settings.
+pub fn preview(image: &Rgb32FImage, settings: &OutputSettings) -> RgbImage {
+    let scale = settings.exposure.exp2();
+    RgbImage::from_fn(image.width(), image.height(), |x, y| {
+        let Rgb(pixel) = *image.get_pixel(x, y);
+        let color = tone_map(Vector3::from(pixel) * scale, settings.tone_map);
+        Rgb(color.data.0[0].map(|c| (srgb_oetf(c) * 255.0 + 0.5) as u8))
+    })
+}
+
+pub(crate) fn luminance(color: &Vector3<f32>) -> f32 {
+    color.dot(&Vector3::new(0.2126, 0.7152, 0.0722))
+}
+
+/// Map a linear color into [0, 1].
+pub fn tone_map(color: Vector3<f32>, operator: ToneMap) -> Vector3<f32> {
+    // invalid samples are already culled, negative values only come from reconstruction
+    let color = color.map(|c| c.max(0.0));
+
+    let mapped = match operator {
+        ToneMap::Clamp => color,
+        ToneMap::Reinhard | ToneMap::ExtendedReinhard { .. } => {
+            let l = luminance(&color);
+            if l <= 0.0 {
+                return Vector3::zeros();
+            }
+            let mapped = match operator {
+                ToneMap::ExtendedReinhard { white } => l * (1.0 + l / (white * white)) / (1.0 + l),
+                _ => l / (1.0 + l),
+            };
+            color * (mapped / l)
+        }
+        ToneMap::Aces => {
+            // curve by Krzysztof Narkowicz, with its exposure of 0.6
+            color.map(|c| {
+                let c = 0.6 * c;
+                (c * (2.51 * c + 0.03)) / (c * (2.43 * c + 0.59) + 0.14)
+            })
+        }
+        ToneMap::Agx => agx(color),
+    };
+
+    mapped.map(|c| c.clamp(0.0, 1.0))
+}
+
+fn agx(color: Vector3<f32>) -> Vector3<f32> {
+    // inset into the AgX working gamut, after the minimal implementation of Troy Sobotka's AgX
+    let inset = Matrix3::new(
+        0.84247905,
+        0.0784336,
+        0.079223745,
+        0.042328242,
+        0.87846863,
+        0.07916613,
+        0.042375654,
+        0.0784336,
+        0.879143,
+    );
+    let outset = Matrix3::new(
+        1.196879,
+        -0.09802088,
+        -0.09902974,
+        -0.052896854,
+        1.1519032,
+        -0.098961174,
+        -0.052971635,
+        -0.09804345,
+        1.1510737,
+    );
+    const MIN_EV: f32 = -12.47393;
+    const MAX_EV: f32 = 4.026069;
+
+    let encoded = (inset * color).map(|c| {
+        let x = (c.max(1e-10).log2().clamp(MIN_EV, MAX_EV) - MIN_EV) / (MAX_EV - MIN_EV);
+
+        // polynomial fit of the default contrast sigmoid
+        let (x2, x4) = (x * x, x * x * x * x);
+        15.5 * x4 * x2 - 40.14 * x4 * x + 31.96 * x4 - 6.868 * x2 * x + 0.4298 * x2 + 0.1191 * x
+            - 0.00232
+    });
+
+    // the sigmoid gives display encoded values with a gamma of 2.2
+    (outset * encoded).map(|c| c.max(0.0).powf(2.2))
+}
+
+/// sRGB transfer function, from linear to display encoded values.
+pub fn srgb_oetf(c: f32) -> f32 {
+    let c = c.clamp(0.0, 1.0);
+    if c <= 0.0031308 {
+        12.92 * c
+    } else {
+        1.055 * c.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+/// Save the image, in the format following from the file extension.
+pub fn save(
+    image: &Rgb32FImage,
+    path: &Path,
+    settings: &OutputSettings,
+) -> Result<(), OutputError> {
+    let extension = path.extension().and_then(|e| e.to_str());
+    match extension.map(str::to_ascii_lowercase).as_deref() {
+        Some("exr") => save_exr(&[("", image)], path, settings.precision),
+        Some("hdr") => save_hdr(image, path),
+        _ => Ok(preview(image, settings).save(path)?),
+    }
+}
+
+/// Save the image with its passes. OpenEXR files get the passes as named layers, every other
+/// format a file per pass, named after the image with the name of the pass appended.
+///
+/// Data passes like depth or ids are only preserved by the floating point formats, 8-bit
+/// files are tone mapped like the image.
+pub fn save_passes(
+    image: &Rgb32FImage,
+    passes: &[(&str, &Rgb32FImage)],
+    path: &Path,
+    settings: &OutputSettings,
+) -> Result<(), OutputError> {
+    let extension = path.extension().and_then(|e| e.to_str());
+    if extension.map(str::to_ascii_lowercase).as_deref() == Some("exr") {
+        let mut layers = vec![("", image)];
+        layers.extend_from_slice(passes);
+        return save_exr(&layers, path, settings.precision);
+    }
+
+    save(image, path, settings)?;
+    for &(name, pass) in passes {
+        save(pass, &pass_path(path, name), settings)?;
+    }
+    Ok(())
+}
+
+/// Path of a pass saved next to the image, e.g. `out_depth.png` for `out.png`.
+pub fn pass_path(path: &Path, name: &str) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let mut file_name = format!("{}_{}", stem, name);
+    if let Some(extension) = path.extension() {
+        file_name = format!("{}.{}", file_name, extension.to_string_lossy());
+    }
+    path.with_file_name(file_name)
+}
+
+/// Save named images as the layers of an OpenEXR file, all of the same size. The channels of
+/// a layer are prefixed with its name, e.g. `depth.R`, a layer with an empty name is written
+/// with plain channel names as the main image of the file.
+pub fn save_exr(
+    layers: &[(&str, &Rgb32FImage)],
+    path: &Path,
+    precision: Precision,
+) -> Result<(), OutputError> {
+    let (width, height) = layers.first().ok_or(OutputError::NoLayers)?.1.dimensions();
+    let size = (width as usize, height as usize);
+    if let Some((name, image)) = layers
+        .iter()
+        .find(|(_, image)| image.dimensions() != (width, height))
+    {
+        return Err(OutputError::LayerSize {
+            name: name.to_string(),
+            size: image.dimensions(),
+            expected: (width, height),
+        });
+    }
+
+    let channels: Vec<_> = layers
+        .iter()
+        .flat_map(|&(name, image)| {
+            ["R", "G", "B"]
+                .into_iter()
+                .enumerate()
+                .map(move |(c, channel)| {
+                    let values = image.pixels().map(|p| p[c]);
+                    let samples = match precision {
+                        Precision::Half => {
+                            FlatSamples::F16(values.map(exr::prelude::f16::from_f32).collect())
+                        }
+                        Precision::Float => FlatSamples::F32(values.collect()),
+                    };
+                    let channel = if name.is_empty() {
+                        channel.to_string()
+                    } else {
+                        format!("{}.{}", name, channel)
+                    };
+                    AnyChannel::new(channel.as_str(), samples)
+                })
+        })
+        .collect();
+
+    let layer = Layer::new(
+        size,
+        LayerAttributes::default(),
+        Encoding::SMALL_LOSSLESS,
+        AnyChannels::sort(SmallVec::from_vec(channels)),
+    );
+    Image::from_layer(layer).write().to_file(path)?;
+
+    Ok(())
+}
+
+/// Save the image as a Radiance RGBE file.
+pub fn save_hdr(image: &Rgb32FImage, path: &Path) -> Result<(), OutputError> {
+    let pixels: Vec<Rgb<f32>> = image.pixels().copied().collect();
+    let file = BufWriter::new(File::create(path)?);
+    HdrEncoder::new(file).encode(&pixels, image.width() as usize, image.height() as usize)?;
+    Ok(())
+}
+
+#[test]
+fn test_hdr_output() {
+    let image = Rgb32FImage::from_fn(4, 2, |x, y| Rgb([x as f32 * 5.0, y as f32, 0.25]));
+    let dir = std::env::temp_dir();
+
+    // values above one survive, within the precision of the format
+    for (name, precision, tolerance) in [
+        ("raytracer_test_float.exr", Precision::Float, 0.0),
+        ("raytracer_test_half.exr", Precision::Half, 1e-2),
+        ("raytracer_test.hdr", Precision::Float, 0.1),
+    ] {
+        let path = dir.join(name);
+        let settings = OutputSettings {
+            precision,
+            ..Default::default()
+        };
+        save(&image, &path, &settings).unwrap();
+        // the image decoder maps radiance files to 8 bits, so read those directly
+        let read: Vec<Rgb<f32>> = if name.ends_with(".hdr") {
+            let file = std::io::BufReader::new(File::open(&path).unwrap());
+            let decoder = image::codecs::hdr::HdrDecoder::new(file).unwrap();
+            decoder.read_image_hdr().unwrap()
+        } else {
+            image::open(&path)
+                .unwrap()
+                .into_rgb32f()
+                .pixels()
+                .copied()
+                .collect()
+        };
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(read.len(), image.len() / 3);
+        for (a, b) in read.iter().zip(image.pixels()) {
+            for c in 0..3 {
+                assert!((a[c] - b[c]).abs() <= tolerance * b[c].max(1.0), "{}", name);
+            }
+        }
+    }
+
+    // passes are stored as prefixed channels next to the main image
+    let path = dir.join("raytracer_test_layers.exr");
+    let depth = Rgb32FImage::from_pixel(4, 2, Rgb([7.0; 3]));
+    save_exr(&[("", &image), ("depth", &depth)], &path, Precision::Float).unwrap();
+    let read = exr::prelude::read_all_flat_layers_from_file(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    let channels = &read.layer_data[0].channel_data.list;
+    let names: Vec<String> = channels.iter().map(|c| c.name.to_string()).collect();
+    assert_eq!(names, ["B", "G", "R", "depth.B", "depth.G", "depth.R"]);
+    assert_eq!(channels[5].sample_data.value_by_flat_index(0).to_f32(), 7.0);
+    assert!(matches!(
+        save_exr(&[], &path, Precision::Float),
+        Err(OutputError::NoLayers)
+    ));
+    let small = Rgb32FImage::new(2, 2);
+    assert!(matches!(
+        save_exr(&[("", &image), ("small", &small)], &path, Precision::Float),
+        Err(OutputError::LayerSize { size: (2, 2), .. })
+    ));
+    assert!(!path.exists());
+
+    let settings = OutputSettings::default();
+    assert_eq!(
+        preview(&image, &settings).get_pixel(3, 1),
+        &Rgb([255, 255, 137])
+    );
+}
+
+#[test]
+fn test_tone_map() {
+    let gray = |v: f32| Vector3::repeat(v);
+
+    assert_eq!(srgb_oetf(0.0), 0.0);
+    assert!((srgb_oetf(1.0) - 1.0).abs() < 1e-6);
+    assert!((srgb_oetf(0.18) - 0.4614).abs() < 1e-3);
+    // the linear segment and the power curve meet
+    assert!((srgb_oetf(0.0031308) - srgb_oetf(0.0031309)).abs() < 1e-5);
+
+    assert!((tone_map(gray(1.0), ToneMap::Reinhard).x - 0.5).abs() < 1e-6);
+    let white = ToneMap::ExtendedReinhard { white: 4.0 };
+    assert!((tone_map(gray(4.0), white).x - 1.0).abs() < 1e-6);
+
+    for operator in [
+        ToneMap::Clamp,
+        ToneMap::Reinhard,
+        white,
+        ToneMap::Aces,
+        ToneMap::Agx,
+    ] {
+        // black stays black (up to the toe of the AgX curve), brighter input never maps darker,
+        // and the output stays displayable
+        assert!(tone_map(gray(0.0), operator).x < 1e-3);
+        let mut last = 0.0;
+        for i in 0..100 {
+            let mapped = tone_map(gray(0.01 * 1.1f32.powi(i)), operator);
+            assert!(mapped.x >= last - 1e-6 && mapped.x <= 1.0, "{:?}", operator);
+            last = mapped.x;
+        }
+
+        // hues are kept in the displayable range as well
+        let mapped = tone_map(Vector3::new(20.0, 2.0, 0.1), operator);
+        assert!(mapped.iter().all(|c| (0.0..=1.0).contains(c)));
+    }
+}
@@ -1,4 +1,4 @@
-use crate::{hit::Hittable, material::random_cosine_direction};
+use crate::{hit::Hittable, material::random_cosine_direction, sampler};
 use nalgebra::Vector3;
 use rand::Rng;
 
@@ -10,7 +10,7 @@ pub struct Onb {
 
 impl Onb {
     pub fn local(&self, a: &Vector3<f32>) -> Vector3<f32> {
-        return a.x * self.u + a.y * self.v + a.z * self.w;
+        a.x * self.u + a.y * self.v + a.z * self.w
     }
 
     pub fn build_from_w(n: Vector3<f32>) -> Self {
@@ -33,7 +33,7 @@ pub enum Pdf<'a> {
     },
     Hittable {
         origin: Vector3<f32>,
-        hittable: &'a Box<dyn Hittable>,
+        hittable: &'a dyn Hittable,
     },
     Mixture {
         p: &'a Pdf<'a>,
@@ -47,7 +47,7 @@ impl<'a> Pdf<'a> {
         Pdf::Cosine { uvw }
     }
 
-    pub fn hittable_pdf(hittable: &'a Box<dyn Hittable>, origin: &Vector3<f32>) -> Self {
+    pub fn hittable_pdf(hittable: &'a dyn Hittable, origin: &Vector3<f32>) -> Self {
         Pdf::Hittable {
             origin: *origin,
             hittable,
@@ -74,7 +74,7 @@ impl<'a> Pdf<'a> {
             Pdf::Cosine { uvw } => uvw.local(&random_cosine_direction()),
             Pdf::Hittable { origin, hittable } => hittable.random(*origin),
             Pdf::Mixture { p, q } => {
-                let mut rng = rand::thread_rng();
+                let mut rng = sampler::rng();
                 if rng.gen::<bool>() {
                     p.generate()
                 } else {
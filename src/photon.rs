@@ -0,0 +1,819 @@
+//! Photon mapping.
+//!
+//! Before rendering, photons are traced from points sampled on the lights and stored where they
+//! hit diffuse surfaces: all of them in a global map and those that only passed specular
+//! surfaces on the way in a caustic map. The maps are kd-trees, and the light reflected at a
+//! point is estimated from the power of the nearest photons, spread over the disc that holds
+//! them.
+//!
+//! Camera paths are traced like the path tracer up to their first diffuse hit. There, the direct
+//! light comes from shadow rays, the caustics from the caustic map and the remaining indirect
+//! light from final gather rays, which look up the global map at their own first diffuse hit.
+//! Focused light that is hard to find from the camera, like the caustics of glass spheres,
+//! comes out smooth instead of as fireflies. The estimates blur the light over their radius,
+//! progressive photon mapping shrinks it from render pass to render pass, so the blur vanishes
+//! as the passes grow.
+//!
+//! The photons only carry the light of the lights of the scene. The background still lights
+//! the scene along the paths, which continue past the photon map lookups for it alone.
+
+use crate::{
+    aov::AovSample,
+    bdpt,
+    hit::{HitRecord, Hittable},
+    integrator::{self, Heuristic, Scene},
+    material::ScatterRecord,
+    ray::{offset_origin, Ray},
+    sampler,
+};
+use nalgebra::Vector3;
+use rand::Rng;
+use rayon::prelude::{
+    IndexedParallelIterator, IntoParallelIterator, IntoParallelRefIterator, ParallelIterator,
+};
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+use std::f32::consts::PI;
+
+/// Bounces before the paths are terminated by Russian roulette, like the path tracer.
+const ROULETTE_DEPTH: u8 = 3;
+
+/// Radius reduction of progressive photon mapping, the fraction of the photons kept from one
+/// pass to the next.
+const ALPHA: f32 = 2.0 / 3.0;
+
+/// Fraction of the global photons with a precomputed irradiance for the final gather, one in
+/// this many.
+const IRRADIANCE_STEP: usize = 8;
+
+/// Photons traced per task, and per seed of seeded renders.
+const CHUNK_SIZE: u32 = 4096;
+
+/// Settings of photon mapping, see the [module](self) documentation.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct PhotonMapping {
+    /// Number of photons emitted from the lights for the photon maps of a render pass.
+    pub photons: u32,
+    /// Number of photons of an estimate, the distance to the farthest one is its radius.
+    pub nearest: u16,
+    /// Number of final gather rays at the first diffuse hit of the camera paths, 0 to look up
+    /// the global map right there instead.
+    pub gather_rays: u16,
+    /// Trace new photon maps for every sample per pixel and shrink the radius of the estimates
+    /// with each of them.
+    pub progressive: bool,
+}
+
+impl Default for PhotonMapping {
+    fn default() -> Self {
+        Self {
+            photons: 100_000,
+            nearest: 64,
+            gather_rays: 8,
+            progressive: false,
+        }
+    }
+}
+
+/// Where a path is relative to the photon map lookups, which decides the light it gathers.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Stage {
+    /// Before the first diffuse hit.
+    Camera,
+    /// Final gather ray, before its first diffuse hit.
+    Gather,
+    /// After the global map lookup, which accounts for all the light of the lights.
+    Background,
+}
+
+impl PhotonMapping {
+    /// Trace the photon maps of a render pass, the first pass is 0. The random numbers are
+    /// seeded per chunk of photons when a seed is given, like the pixels of a render.
+    pub fn trace(&self, scene: &Scene, seed: Option<u64>, pass: u16) -> Photons {
+        let chunks = self.photons.div_ceil(CHUNK_SIZE);
+        let traced: Vec<_> = (0..chunks)
+            .into_par_iter()
+            .map(|chunk| {
+                if let Some(seed) = seed {
+                    sampler::seed(seed ^ ((pass as u64) << 48) ^ !(chunk as u64));
+                }
+                let count = CHUNK_SIZE.min(self.photons - chunk * CHUNK_SIZE);
+                let mut global = Vec::new();
+                let mut caustic = Vec::new();
+                for _ in 0..count {
+                    self.trace_photon(scene, &mut global, &mut caustic);
+                }
+                (global, caustic)
+            })
+            .collect();
+        let (global, caustic): (Vec<_>, Vec<_>) = traced.into_iter().unzip();
+
+        // the radius of pass i is scaled by the product of (k + alpha) / (k + 1) for k < i
+        let radius_scale = if self.progressive {
+            (1..=pass as u32)
+                .map(|k| ((k as f32 + ALPHA) / (k as f32 + 1.0)).sqrt())
+                .product()
+        } else {
+            1.0
+        };
+
+        let nearest = self.nearest as usize;
+        let mut global = PhotonMap::new(global.concat(), nearest);
+        if self.gather_rays > 0 {
+            global.precompute_irradiance(IRRADIANCE_STEP, radius_scale);
+        }
+        Photons {
+            global,
+            caustic: PhotonMap::new(caustic.concat(), nearest),
+            radius_scale,
+        }
+    }
+
+    /// Trace a photon from the lights and store it at the diffuse surfaces it hits.
+    fn trace_photon(&self, scene: &Scene, global: &mut Vec<Photon>, caustic: &mut Vec<Photon>) {
+        let mut rng = sampler::rng();
+        let time = scene.camera.sample_time();
+        let Some((mut ray, power)) = bdpt::sample_emitted_ray(scene, time) else {
+            return;
+        };
+        let mut power = power / self.photons as f32;
+
+        // a caustic passed specular surfaces only, after leaving the light
+        let mut specular = false;
+        let mut diffuse = false;
+        for depth in 0..scene.max_depth {
+            let Some(hit) = scene.world.hit(&ray, 0.001, f32::MAX) else {
+                break;
+            };
+
+            let (weight, direction, n) = match hit.m.scatter(&ray, &hit) {
+                None => break,
+                Some(ScatterRecord::Scatter { attenuation, pdf }) => {
+                    let photon = Photon {
+                        p: hit.p,
+                        direction: ray.dir.normalize(),
+                        n: hit.n,
+                        power,
+                        direct: depth == 0,
+                        irradiance: None,
+                        axis: 0,
+                    };
+                    global.push(photon);
+                    if specular && !diffuse {
+                        caustic.push(photon);
+                    }
+                    diffuse = true;
+
+                    let direction = pdf.generate();
+                    let pdf = pdf.value(direction);
+                    if pdf <= 0.0 {
+                        break;
+                    }
+                    let value =
+                        attenuation * hit.m.scattering_pdf(&hit, &Ray::new(hit.p, direction));
+                    (value / pdf, direction, hit.n)
+                }
+                Some(ScatterRecord::Isotropic {
+                    attenuation,
+                    scattered_ray,
+                }) => {
+                    diffuse = true;
+                    (attenuation, scattered_ray.dir, Vector3::zeros())
+                }
+                Some(ScatterRecord::Specular {
+                    attenuation,
+                    specular_ray,
+                }) => {
+                    specular = true;
+                    (attenuation, specular_ray.dir, hit.n)
+                }
+            };
+
+            // continue with the fraction of the power kept, at the same power per photon
+            let survival = weight.max().min(1.0);
+            if survival <= 0.0 || rng.gen::<f32>() >= survival {
+                break;
+            }
+            power.component_mul_assign(&(weight / survival));
+            ray = Ray::with_time(offset_origin(hit.p, n, &direction), direction, ray.time);
+        }
+    }
+
+    /// Color along a camera ray like [`Integrator::color`](integrator::Integrator::color), with
+    /// the photon maps of the scene. Without photon maps only the light found by the paths
+    /// themselves remains.
+    pub fn color(
+        &self,
+        scene: &Scene,
+        ray: &Ray,
+        mut aovs: Option<&mut AovSample>,
+    ) -> Vector3<f32> {
+        let empty = Photons::default();
+        let photons = scene.photons.unwrap_or(&empty);
+
+        // light emitted at the first hit and reaching it straight from an emitter, for the
+        // passes
+        let mut color = Vector3::zeros();
+        let mut emission = Vector3::zeros();
+        let mut direct = Vector3::zeros();
+        let mut add = |bounces: u8, light: Vector3<f32>| {
+            color += light;
+            match bounces {
+                0 => emission += light,
+                1 => direct += light,
+                _ => (),
+            }
+        };
+
+        let path = Path {
+            ray: *ray,
+            throughput: Vector3::repeat(1.0),
+            stage: Stage::Camera,
+            depth: 0,
+            material_pdf: None,
+        };
+        self.trace_path(scene, photons, path, &mut add, aovs.as_deref_mut());
+
+        if let Some(aovs) = aovs {
+            aovs.emission = emission;
+            aovs.direct = direct;
+            aovs.indirect = color - emission - direct;
+        }
+
+        color
+    }
+
+    /// Follow a path, adding the light it gathers with the number of bounces it took.
+    fn trace_path(
+        &self,
+        scene: &Scene,
+        photons: &Photons,
+        path: Path,
+        add: &mut dyn FnMut(u8, Vector3<f32>),
+        mut aovs: Option<&mut AovSample>,
+    ) {
+        let Scene {
+            world,
+            lights,
+            background,
+            max_depth,
+            ..
+        } = *scene;
+        let Path {
+            mut ray,
+            mut throughput,
+            mut stage,
+            depth,
+            mut material_pdf,
+        } = path;
+        let mut rng = sampler::rng();
+
+        // the light of the lights reached through specular surfaces only, right after the
+        // first diffuse hit, is in the caustic map
+        let mut caustic = false;
+
+        for depth in depth..max_depth {
+            let Some(hit) = world.hit(&ray, 0.001, f32::MAX) else {
+                add(
+                    depth,
+                    throughput.component_mul(background) * mis(lights, &ray, material_pdf),
+                );
+                break;
+            };
+
+            let record = hit.m.scatter(&ray, &hit);
+            if let (0, Some(aovs)) = (depth, aovs.as_deref_mut()) {
+                aovs.record_hit(&hit, &ray.dir);
+                aovs.albedo = match &record {
+                    None => Vector3::zeros(),
+                    Some(
+                        ScatterRecord::Scatter { attenuation, .. }
+                        | ScatterRecord::Specular { attenuation, .. }
+                        | ScatterRecord::Isotropic { attenuation, .. },
+                    ) => *attenuation,
+                };
+            }
+
+            // specular surfaces only pass on the light they reflect
+            let specular = matches!(record, Some(ScatterRecord::Specular { .. }));
+            if !specular && !caustic && stage != Stage::Background {
+                let weight = mis(lights, &ray, material_pdf);
+                add(
+                    depth,
+                    throughput.component_mul(&hit.m.emitted(&hit)) * weight,
+                );
+            }
+
+            let shadow_rays = stage != Stage::Background && depth + 1 < max_depth;
+            let (weight, direction, n) = match record {
+                None => break,
+                Some(ScatterRecord::Specular {
+                    attenuation,
+                    specular_ray,
+                }) => {
+                    caustic |= stage == Stage::Gather;
+                    material_pdf = None;
+                    (attenuation, specular_ray.dir, hit.n)
+                }
+                Some(ScatterRecord::Isotropic {
+                    attenuation,
+                    scattered_ray,
+                }) => {
+                    // uniform phase function
+                    let f = |_: &Ray| (attenuation / (4.0 * PI), 1.0 / (4.0 * PI));
+                    if shadow_rays {
+                        let light = integrator::sample_light(
+                            Heuristic::Power,
+                            &hit,
+                            &ray,
+                            background,
+                            world,
+                            lights,
+                            f,
+                        );
+                        add(depth + 1, throughput.component_mul(&light));
+                    }
+
+                    caustic = false;
+                    material_pdf = (stage != Stage::Background).then_some(1.0 / (4.0 * PI));
+                    (attenuation, scattered_ray.dir, Vector3::zeros())
+                }
+                Some(ScatterRecord::Scatter { attenuation, pdf }) => {
+                    let f = |scattered: &Ray| {
+                        let value = attenuation * hit.m.scattering_pdf(&hit, scattered);
+                        (value, pdf.value(scattered.dir))
+                    };
+
+                    if stage == Stage::Camera && self.gather_rays > 0 {
+                        if shadow_rays {
+                            let light = integrator::sample_light(
+                                Heuristic::Power,
+                                &hit,
+                                &ray,
+                                background,
+                                world,
+                                lights,
+                                f,
+                            );
+                            add(depth + 1, throughput.component_mul(&light));
+                        }
+                        let (_, caustics) =
+                            photons
+                                .caustic
+                                .estimate(&hit, &attenuation, photons.radius_scale);
+                        add(depth + 2, throughput.component_mul(&caustics));
+
+                        for _ in 0..self.gather_rays {
+                            let direction = pdf.generate();
+                            let gather = Ray::with_time(
+                                offset_origin(hit.p, hit.n, &direction),
+                                direction,
+                                ray.time,
+                            );
+                            let (value, pdf) = f(&gather);
+                            if pdf <= 0.0 {
+                                continue;
+                            }
+                            let path = Path {
+                                ray: gather,
+                                throughput: throughput.component_mul(&value)
+                                    / (pdf * self.gather_rays as f32),
+                                stage: Stage::Gather,
+                                depth: depth + 1,
+                                material_pdf: Some(pdf),
+                            };
+                            self.trace_path(scene, photons, path, add, None);
+                        }
+                        break;
+                    }
+
+                    if stage == Stage::Gather {
+                        let light = photons.global.gather(&hit, &attenuation);
+                        add(depth + 1, throughput.component_mul(&light));
+                    } else if stage == Stage::Camera {
+                        let (direct, indirect) =
+                            photons
+                                .global
+                                .estimate(&hit, &attenuation, photons.radius_scale);
+                        add(depth + 1, throughput.component_mul(&direct));
+                        add(depth + 2, throughput.component_mul(&indirect));
+                    }
+                    if stage != Stage::Background {
+                        stage = Stage::Background;
+                        if *background == Vector3::zeros() {
+                            break;
+                        }
+                    }
+
+                    let direction = pdf.generate();
+                    let scattered = Ray::new(hit.p, direction);
+                    let (value, pdf) = f(&scattered);
+                    if pdf <= 0.0 {
+                        break;
+                    }
+                    material_pdf = None;
+                    (value / pdf, direction, hit.n)
+                }
+            };
+
+            throughput.component_mul_assign(&weight);
+            ray = Ray::with_time(offset_origin(hit.p, n, &direction), direction, ray.time);
+
+            if depth + 1 >= ROULETTE_DEPTH {
+                let survival = throughput.max().min(1.0);
+                if survival <= 0.0 || rng.gen::<f32>() >= survival {
+                    break;
+                }
+                throughput /= survival;
+            }
+        }
+    }
+}
+
+/// Start of a path traced by [`PhotonMapping::trace_path`].
+struct Path {
+    ray: Ray,
+    throughput: Vector3<f32>,
+    stage: Stage,
+    /// Number of hits before the ray.
+    depth: u8,
+    /// Pdf of the material sample the ray was scattered with, when a shadow ray could have
+    /// found the same light.
+    material_pdf: Option<f32>,
+}
+
+/// Power heuristic weight of the light found along a ray scattered with the material pdf,
+/// against the shadow rays.
+fn mis(lights: &dyn Hittable, ray: &Ray, material_pdf: Option<f32>) -> f32 {
+    match material_pdf {
+        Some(pdf) => {
+            // lights without any objects give a nan pdf
+            let light_pdf = lights.pdf_value(ray.ori, ray.dir).max(0.0);
+            Heuristic::Power.weight(pdf, light_pdf)
+        }
+        None => 1.0,
+    }
+}
+
+/// Photon maps of a render pass, see [`PhotonMapping::trace`].
+pub struct Photons {
+    global: PhotonMap,
+    caustic: PhotonMap,
+    /// Scale of the radius of the estimates, shrinking with the passes of progressive photon
+    /// mapping.
+    radius_scale: f32,
+}
+
+impl Default for Photons {
+    fn default() -> Self {
+        Self {
+            global: PhotonMap::default(),
+            caustic: PhotonMap::default(),
+            radius_scale: 1.0,
+        }
+    }
+}
+
+impl Photons {
+    /// Number of photons in the global and in the caustic map.
+    pub fn counts(&self) -> (usize, usize) {
+        (self.global.photons.len(), self.caustic.photons.len())
+    }
+}
+
+/// Light arriving at a diffuse surface.
+#[derive(Debug, Copy, Clone)]
+struct Photon {
+    p: Vector3<f32>,
+    /// Unit direction of travel.
+    direction: Vector3<f32>,
+    /// Unit normal of the surface, on the side the photon arrived from.
+    n: Vector3<f32>,
+    power: Vector3<f32>,
+    /// Arrived straight from the light.
+    direct: bool,
+    /// Light arriving at the photon position from all photons, per unit area, precomputed at
+    /// some of the photons for the final gather.
+    irradiance: Option<Vector3<f32>>,
+    /// Split axis of the kd-tree node.
+    axis: u8,
+}
+
+/// Photons in a balanced kd-tree, each node is the median of the photons of its subtree and
+/// the photons before and after it in the slice are its two children.
+#[derive(Default)]
+struct PhotonMap {
+    photons: Vec<Photon>,
+    nearest: usize,
+    /// Largest radius of an estimate, twice the radius holding the nearest photons if they were
+    /// spread evenly over the bounds of the map.
+    max_radius: f32,
+}
+
+impl PhotonMap {
+    fn new(mut photons: Vec<Photon>, nearest: usize) -> Self {
+        let (min, max) = photons.iter().fold(
+            (Vector3::repeat(f32::MAX), Vector3::repeat(f32::MIN)),
+            |(min, max), photon| (min.inf(&photon.p), max.sup(&photon.p)),
+        );
+        let max_radius = if photons.is_empty() {
+            0.0
+        } else {
+            2.0 * (max - min).norm() * (nearest as f32 / photons.len() as f32).sqrt()
+        };
+
+        build(&mut photons);
+        Self {
+            photons,
+            nearest: nearest.max(1),
+            max_radius,
+        }
+    }
+
+    /// Estimate the irradiance at every `step`th photon, so the final gather only needs the
+    /// nearest of them instead of a full estimate.
+    fn precompute_irradiance(&mut self, step: usize, radius_scale: f32) {
+        let irradiance: Vec<_> = self
+            .photons
+            .par_iter()
+            .step_by(step)
+            .map(|photon| {
+                let (neighbors, radius_squared) =
+                    self.neighbors(&photon.p, &photon.n, radius_scale);
+                let power: Vector3<f32> = neighbors
+                    .iter()
+                    .map(|neighbor| self.photons[neighbor.index].power)
+                    .sum();
+                power / (PI * radius_squared)
+            })
+            .collect();
+        for (photon, irradiance) in self.photons.iter_mut().step_by(step).zip(irradiance) {
+            photon.irradiance = Some(irradiance);
+        }
+    }
+
+    /// The nearest photons arriving from the side of the normal `n`, and the squared radius
+    /// of the disc holding them. The radius is the distance to the farthest of the nearest
+    /// photons, times the scale. That photon lies on the edge and is left out, which keeps
+    /// evenly spread photons from being overestimated.
+    fn neighbors(
+        &self,
+        p: &Vector3<f32>,
+        n: &Vector3<f32>,
+        radius_scale: f32,
+    ) -> (Vec<Neighbor>, f32) {
+        let mut neighbors = BinaryHeap::with_capacity(self.nearest + 1);
+        let mut max_distance = self.max_radius * self.max_radius;
+        let front = |photon: &Photon| n.dot(&photon.direction) < 0.0 && n.dot(&photon.n) > 0.5;
+        let search = Search {
+            p,
+            nearest: self.nearest,
+            accept: &front,
+        };
+        search.run(&self.photons, 0, &mut neighbors, &mut max_distance);
+
+        let radius_squared = max_distance * radius_scale * radius_scale;
+        let neighbors = neighbors
+            .into_iter()
+            .filter(|neighbor| neighbor.distance_squared < radius_squared)
+            .collect();
+        (neighbors, radius_squared)
+    }
+
+    /// Light reflected at a diffuse hit back along the ray, from the photons that arrived
+    /// straight from the lights and from the other ones.
+    fn estimate(
+        &self,
+        hit: &HitRecord,
+        attenuation: &Vector3<f32>,
+        radius_scale: f32,
+    ) -> (Vector3<f32>, Vector3<f32>) {
+        let (neighbors, radius_squared) = self.neighbors(&hit.p, &hit.n, radius_scale);
+        if radius_squared <= 0.0 {
+            return (Vector3::zeros(), Vector3::zeros());
+        }
+
+        let mut direct = Vector3::zeros();
+        let mut indirect = Vector3::zeros();
+        for neighbor in neighbors {
+            let photon = &self.photons[neighbor.index];
+            let incoming = Ray::new(hit.p, -photon.direction);
+            let cosine = -hit.n.dot(&photon.direction);
+            let f = attenuation * hit.m.scattering_pdf(hit, &incoming) / cosine;
+            let light = f.component_mul(&photon.power);
+            if photon.direct {
+                direct += light;
+            } else {
+                indirect += light;
+            }
+        }
+
+        let area = PI * radius_squared;
+        (direct / area, indirect / area)
+    }
+
+    /// Light reflected at the hit of a final gather ray back along the ray, from the irradiance
+    /// of the nearest photon on the same side of a surface that has it. Exact for materials
+    /// that scatter the same in all directions, like [`Lambertian`](crate::material::Lambertian).
+    fn gather(&self, hit: &HitRecord, attenuation: &Vector3<f32>) -> Vector3<f32> {
+        let mut neighbors = BinaryHeap::with_capacity(2);
+        let mut max_distance = self.max_radius * self.max_radius;
+        let accept = |photon: &Photon| photon.irradiance.is_some() && hit.n.dot(&photon.n) > 0.0;
+        let search = Search {
+            p: &hit.p,
+            nearest: 1,
+            accept: &accept,
+        };
+        search.run(&self.photons, 0, &mut neighbors, &mut max_distance);
+
+        let Some(neighbor) = neighbors.peek() else {
+            return Vector3::zeros();
+        };
+        let irradiance = self.photons[neighbor.index].irradiance.unwrap_or_default();
+        let f = attenuation * hit.m.scattering_pdf(hit, &Ray::new(hit.p, hit.n));
+        f.component_mul(&irradiance)
+    }
+}
+
+/// Sort the photons into a kd-tree, splitting along the axis of their largest extent.
+fn build(photons: &mut [Photon]) {
+    if photons.len() <= 1 {
+        return;
+    }
+
+    let (min, max) = photons.iter().fold(
+        (Vector3::repeat(f32::MAX), Vector3::repeat(f32::MIN)),
+        |(min, max), photon| (min.inf(&photon.p), max.sup(&photon.p)),
+    );
+    let axis = (max - min).imax();
+
+    let median = photons.len() / 2;
+    photons.select_nth_unstable_by(median, |a, b| a.p[axis].total_cmp(&b.p[axis]));
+    photons[median].axis = axis as u8;
+
+    let (left, right) = photons.split_at_mut(median);
+    build(left);
+    build(&mut right[1..]);
+}
+
+/// Photon found by a [`Search`], ordered by its distance.
+#[derive(Debug, Copy, Clone)]
+struct Neighbor {
+    distance_squared: f32,
+    index: usize,
+}
+
+impl PartialEq for Neighbor {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Neighbor {}
+
+impl PartialOrd for Neighbor {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Neighbor {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.distance_squared.total_cmp(&other.distance_squared)
+    }
+}
+
+/// Search for the `nearest` accepted photons of a kd-tree around `p`.
+struct Search<'a> {
+    p: &'a Vector3<f32>,
+    nearest: usize,
+    accept: &'a dyn Fn(&Photon) -> bool,
+}
+
+impl Search<'_> {
+    /// Collect the photons of the tree closer than the square root of `max_distance`, with
+    /// the farthest one on top of the heap. Once all are found the maximum distance shrinks
+    /// to the farthest of them. The indices of the tree start at `offset`.
+    fn run(
+        &self,
+        photons: &[Photon],
+        offset: usize,
+        neighbors: &mut BinaryHeap<Neighbor>,
+        max_distance: &mut f32,
+    ) {
+        if photons.is_empty() {
+            return;
+        }
+
+        let median = photons.len() / 2;
+        let node = &photons[median];
+        let axis = node.axis as usize;
+        let (left, right) = photons.split_at(median);
+        let right = &right[1..];
+        let right_offset = offset + median + 1;
+
+        // the side of the split plane holding the point first, the other one if it is close
+        // enough
+        let d = self.p[axis] - node.p[axis];
+        let (near, near_offset, far, far_offset) = if d < 0.0 {
+            (left, offset, right, right_offset)
+        } else {
+            (right, right_offset, left, offset)
+        };
+        self.run(near, near_offset, neighbors, max_distance);
+
+        let distance_squared = (node.p - self.p).norm_squared();
+        if distance_squared < *max_distance && (self.accept)(node) {
+            neighbors.push(Neighbor {
+                distance_squared,
+                index: offset + median,
+            });
+            if neighbors.len() > self.nearest {
+                neighbors.pop();
+            }
+            if neighbors.len() == self.nearest {
+                *max_distance = neighbors.peek().unwrap().distance_squared;
+            }
+        }
+
+        if d * d < *max_distance {
+            self.run(far, far_offset, neighbors, max_distance);
+        }
+    }
+}
+
+#[test]
+fn test_photon_mapping() {
+    use crate::integrator::{test_render_mean, Integrator};
+    use crate::scene::scene_description;
+
+    // the kd-tree finds the same nearest photons as a linear search
+    sampler::seed(1 << 20);
+    let photons: Vec<_> = (0..1000)
+        .map(|_| Photon {
+            p: Vector3::from_fn(|_, _| sampler::rng().gen::<f32>()),
+            direction: -Vector3::z(),
+            n: Vector3::z(),
+            power: Vector3::repeat(1.0),
+            direct: true,
+            irradiance: None,
+            axis: 0,
+        })
+        .collect();
+    let map = PhotonMap::new(photons.clone(), 16);
+    for _ in 0..16 {
+        let p = Vector3::from_fn(|_, _| sampler::rng().gen::<f32>());
+        let (neighbors, radius_squared) = map.neighbors(&p, &Vector3::z(), 1.0);
+        let mut distances: Vec<_> = photons.iter().map(|q| (q.p - p).norm_squared()).collect();
+        distances.sort_by(f32::total_cmp);
+        assert_eq!(radius_squared, distances[15]);
+        assert_eq!(neighbors.len(), 15);
+        assert!(neighbors.iter().all(|n| n.distance_squared < distances[15]));
+    }
+
+    // the radius shrinks from pass to pass of progressive photon mapping
+    let (camera, world, lights, background) = scene_description(7).build().unwrap();
+    let scene = Scene {
+        camera: &camera,
+        world: &world,
+        lights: &lights,
+        background: &background,
+        max_depth: 8,
+        image_area: 1.0,
+        photons: None,
+    };
+    let progressive = PhotonMapping {
+        photons: 1000,
+        gather_rays: 0,
+        progressive: true,
+        ..Default::default()
+    };
+    let mut radius_scale = 1.0;
+    for pass in 0..4 {
+        let photons = progressive.trace(&scene, Some(1 << 20), pass);
+        assert!((photons.radius_scale - radius_scale).abs() < 1e-6);
+        let k = pass as f32 + 1.0;
+        radius_scale *= ((k + ALPHA) / (k + 1.0)).sqrt();
+    }
+
+    // close to the mean of the path tracer, up to the blur of the estimates
+    let path = test_render_mean(Integrator::Path(Default::default()), 1 << 20, 16);
+    let mapping = PhotonMapping {
+        photons: 20_000,
+        gather_rays: 4,
+        ..Default::default()
+    };
+    let progressive = PhotonMapping {
+        photons: 5000,
+        ..progressive
+    };
+    for mapping in [mapping, progressive] {
+        let photon = test_render_mean(Integrator::PhotonMap(mapping), 2 << 20, 16);
+        assert!(
+            (photon - path).abs() < 0.05 * path,
+            "{} != {}",
+            photon,
+            path
+        );
+    }
+}
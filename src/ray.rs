@@ -1,6 +1,31 @@
-use crate::{hit::Hittable, material::ScatterRecord, pdf::Pdf};
+use crate::{
+    aov::AovSample,
+    hit::{HitRecord, Hittable},
+    material::ScatterRecord,
+    pdf::Pdf,
+};
 use nalgebra::Vector3;
 
+/// Origin of the rays leaving the surface point `p` with the normal `n` in a direction, moved
+/// off the surface on the side of the direction. The offset grows with the distance from the
+/// scene origin, so the rays do not hit the surface they leave again due to rounding.
+pub fn offset_origin(p: Vector3<f32>, n: Vector3<f32>, direction: &Vector3<f32>) -> Vector3<f32> {
+    let offset = 1.0e-4 * (1.0 + p.amax());
+    p + offset * n.dot(direction).signum() * n
+}
+
+/// A ray scattered at a hit, see [`Ray::bounce`].
+pub struct Bounce {
+    /// Attenuation of the material at the hit.
+    pub attenuation: Vector3<f32>,
+    /// Weight of the light arriving along the scattered ray, the attenuation times the ratio of
+    /// the scattering pdf and the pdf the direction was sampled from.
+    pub weight: Vector3<f32>,
+    pub ray: Ray,
+    /// Scattered by a specular material, which does not add its own emission.
+    pub specular: bool,
+}
+
 #[derive(Default, Copy, Clone)]
 pub struct Ray {
     pub ori: Vector3<f32>,
@@ -10,19 +35,26 @@ pub struct Ray {
     pub sign_x: usize,
     pub sign_y: usize,
     pub sign_z: usize,
+    /// Time at which the ray was cast, for moving objects.
+    pub time: f32,
 }
 
 impl Ray {
     pub fn new(origin: Vector3<f32>, direction: Vector3<f32>) -> Self {
+        Self::with_time(origin, direction, 0.0)
+    }
+
+    pub fn with_time(origin: Vector3<f32>, direction: Vector3<f32>, time: f32) -> Self {
         let nrm_dir = direction.normalize();
         Ray {
             ori: origin,
             dir: direction,
-            nrm_dir: nrm_dir,
+            nrm_dir,
             inv_dir: Vector3::new(1.0 / direction.x, 1.0 / direction.y, 1.0 / direction.z),
             sign_x: (direction.x < 0.0) as usize,
             sign_y: (direction.y < 0.0) as usize,
             sign_z: (direction.z < 0.0) as usize,
+            time,
         }
     }
 
@@ -34,6 +66,15 @@ impl Ray {
     //     self.dir
     // }
 
+    /// Whether the direction is negative along the axis, as an index into the `AABB` corners.
+    pub fn sign(&self, axis: usize) -> usize {
+        match axis {
+            0 => self.sign_x,
+            1 => self.sign_y,
+            _ => self.sign_z,
+        }
+    }
+
     pub fn point_at(&self, t: f32) -> Vector3<f32> {
         self.ori + t * self.dir
     }
@@ -41,98 +82,117 @@ impl Ray {
     pub fn color(
         &self,
         background: &Vector3<f32>,
-        world: &Box<dyn Hittable>,
-        lights: &Box<dyn Hittable>,
+        world: &dyn Hittable,
+        lights: &dyn Hittable,
+        depth: u8,
+    ) -> Vector3<f32> {
+        self.trace(background, world, lights, depth, None).0
+    }
+
+    /// Color along the ray like [`Ray::color`], recording the first hit and the split of the
+    /// light into emission, direct and indirect lighting for the output passes.
+    pub fn color_with_aovs(
+        &self,
+        background: &Vector3<f32>,
+        world: &dyn Hittable,
+        lights: &dyn Hittable,
         depth: u8,
+        aovs: &mut AovSample,
     ) -> Vector3<f32> {
-        if depth <= 0 {
+        self.trace(background, world, lights, depth, Some(aovs)).0
+    }
+
+    /// Returns the color along the ray and the part of it emitted at the first hit.
+    fn trace(
+        &self,
+        background: &Vector3<f32>,
+        world: &dyn Hittable,
+        lights: &dyn Hittable,
+        depth: u8,
+        aovs: Option<&mut AovSample>,
+    ) -> (Vector3<f32>, Vector3<f32>) {
+        if depth == 0 {
             // exceeded depth count, no light remaining
-            return Vector3::new(0.0, 0.0, 0.0);
+            return (Vector3::zeros(), Vector3::zeros());
         }
 
         // test object collision
-        match world.hit(self, 0.001, f32::MAX) {
-            None => *background,
-            Some(hit) => {
-                // get the emitted color
-                let emitted = hit.m.emitted(&hit);
-
-                // get scatter record
-                match hit.m.scatter(self, &hit) {
-                    None => emitted,
-                    Some(ScatterRecord::Scatter { attenuation, pdf }) => {
-                        let light_pdf = Pdf::hittable_pdf(lights, &hit.p);
-                        let mixed_pdf = Pdf::mixture_pdf(&light_pdf, &pdf);
-                        let direction = mixed_pdf.generate();
-                        let scattered = Ray::new(hit.p, direction);
-                        let pdf_val = mixed_pdf.value(scattered.dir);
-                        let scattering_pdf = hit.m.scattering_pdf(&hit, &scattered);
-
-                        let color: Vector3<f32> =
-                            scattered.color(background, &world, &lights, depth - 1);
-
-                        emitted + attenuation.component_mul(&color) * scattering_pdf / pdf_val
-                    }
-                    Some(ScatterRecord::Specular {
-                        attenuation,
-                        specular_ray,
-                    }) => {
-                        let color: Vector3<f32> =
-                            specular_ray.color(background, &world, &lights, depth - 1);
-
-                        attenuation.component_mul(&color)
-                    }
-                    Some(ScatterRecord::Isotropic {
-                        attenuation,
-                        scattered_ray,
-                    }) => {
-                        let color: Vector3<f32> =
-                            scattered_ray.color(background, &world, &lights, depth - 1);
-
-                        emitted + attenuation.component_mul(&color)
-                    }
-                }
+        let Some(hit) = world.hit(self, 0.001, f32::MAX) else {
+            if let Some(aovs) = aovs {
+                aovs.emission = *background;
+            }
+            return (*background, *background);
+        };
+
+        // get the emitted color, specular surfaces only pass on the light they reflect
+        let bounce = self.bounce(&hit, lights);
+        let emitted = match &bounce {
+            Some(bounce) if bounce.specular => Vector3::zeros(),
+            _ => hit.m.emitted(&hit),
+        };
+
+        let (attenuation, weight, color, direct) = match bounce {
+            None => Default::default(),
+            Some(bounce) => {
+                let (color, direct) = bounce.ray.trace(background, world, lights, depth - 1, None);
+                (bounce.attenuation, bounce.weight, color, direct)
             }
+        };
+
+        if let Some(aovs) = aovs {
+            aovs.record_hit(&hit, &self.dir);
+            aovs.albedo = attenuation;
+            aovs.emission = emitted;
+            aovs.direct = weight.component_mul(&direct);
+            aovs.indirect = weight.component_mul(&(color - direct));
         }
+
+        (emitted + weight.component_mul(&color), emitted)
     }
 
-    /*
-    pub fn color_iter(
-        &self,
-        background: &Vector3<f32>,
-        world: &World,
-        max_depth: u8,
-    ) -> Vector3<f32> {
-        let mut cur_ray = *self;
-
-        let mut color = Vector3::new(0.0, 0.0, 0.0);
-        let mut global_attenuation = Vector3::new(1.0, 1.0, 1.0);
-
-        for _depth in 0..max_depth {
-            match world.hit(&cur_ray, 0.001, f32::MAX) {
-                None => return color + background.component_mul(&global_attenuation),
-                Some(hit) => {
-                    // get the emitted color
-                    let emitted = hit.m.emitted(&hit);
-                    color = color + emitted.component_mul(&global_attenuation);
-
-                    // test for scattering
-                    match hit.m.scatter(&cur_ray, &hit) {
-                        None => return color,
-                        Some((attenuation, scattered, pdf)) => {
-                            // update global attenuation based on current scattered attenuation
-                            // global_attenuation.component_mul_assign(&attenuation);
-                            global_attenuation = global_attenuation.component_mul(&attenuation);
-
-                            // update current ray
-                            cur_ray = scattered;
-                        }
-                    }
+    /// Scatter the ray at a hit, sampling the direction from a mix of the material and the
+    /// light pdfs. Returns `None` when the material absorbs the ray.
+    pub fn bounce(&self, hit: &HitRecord, lights: &dyn Hittable) -> Option<Bounce> {
+        let record = hit.m.scatter(self, hit)?;
+        Some(self.scatter(hit, lights, record))
+    }
+
+    /// Scatter the ray like [`Ray::bounce`], with the scatter record of the material.
+    pub fn scatter(&self, hit: &HitRecord, lights: &dyn Hittable, record: ScatterRecord) -> Bounce {
+        match record {
+            ScatterRecord::Scatter { attenuation, pdf } => {
+                let light_pdf = Pdf::hittable_pdf(lights, &hit.p);
+                let mixed_pdf = Pdf::mixture_pdf(&light_pdf, &pdf);
+                let direction = mixed_pdf.generate();
+                let scattered = Ray::with_time(hit.p, direction, self.time);
+                let pdf_val = mixed_pdf.value(scattered.dir);
+                let scattering_pdf = hit.m.scattering_pdf(hit, &scattered);
+
+                Bounce {
+                    attenuation,
+                    weight: attenuation * scattering_pdf / pdf_val,
+                    ray: scattered,
+                    specular: false,
                 }
             }
+            ScatterRecord::Specular {
+                attenuation,
+                specular_ray,
+            } => Bounce {
+                attenuation,
+                weight: attenuation,
+                ray: specular_ray,
+                specular: true,
+            },
+            ScatterRecord::Isotropic {
+                attenuation,
+                scattered_ray,
+            } => Bounce {
+                attenuation,
+                weight: attenuation,
+                ray: scattered_ray,
+                specular: false,
+            },
         }
-
-        color
     }
-     */
 }
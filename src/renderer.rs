@@ -1,135 +1,392 @@
-use crate::{camera::Camera, hit::Hittable};
-use image::Rgb;
+use crate::{
+    aov::{Aov, AovPixel, AovSample},
+    camera::{Camera, StereoLayout},
+    hit::Hittable,
+    integrator::{Integrator, Scene, Splat},
+    output::{self, OutputError, OutputSettings},
+    sampler,
+};
+use image::{Rgb, Rgb32FImage};
 use nalgebra::Vector3;
 use rand::Rng;
 use rayon::prelude::{IntoParallelIterator, ParallelIterator};
+use std::ops::Range;
 use std::time::{Duration, SystemTime};
 
+/// Settings of a render, created with [`RenderSettings::new`] and adjusted field by field so that
+/// new settings do not break existing code.
+#[derive(Debug, Copy, Clone)]
+#[non_exhaustive]
+pub struct RenderSettings {
+    pub width: u32,
+    pub height: u32,
+    pub samples_per_pixel: u16,
+    pub max_depth: u8,
+    /// Seed for the per-pixel random number generators, `None` for a non-deterministic render.
+    pub seed: Option<u64>,
+    pub integrator: Integrator,
+    /// Print the render progress per line to stdout.
+    pub progress: bool,
+}
+
+impl RenderSettings {
+    /// Settings for an image of the given size, with 100 samples per pixel, a maximum depth of 50
+    /// and the default integrator, unseeded and without progress output.
+    pub fn new(width: u32, height: u32) -> Self {
+        Self {
+            width,
+            height,
+            samples_per_pixel: 100,
+            max_depth: 50,
+            seed: None,
+            integrator: Integrator::default(),
+            progress: false,
+        }
+    }
+}
+
 pub fn get_pixel_color(
-    image_width: u32,
-    image_height: u32,
-    samples_per_pixel: u16,
+    settings: &RenderSettings,
     cam: &Camera,
-    world: &Box<dyn Hittable>,
-    lights: &Box<dyn Hittable>,
+    world: &dyn Hittable,
+    lights: &dyn Hittable,
     background: &Vector3<f32>,
-    max_depth: u8,
     i: u32,
     j: u32,
 ) -> Vector3<f32> {
-    let mut rng = rand::thread_rng();
+    let scene = scene(settings, cam, world, lights, background);
+    let photons = match settings.integrator {
+        Integrator::PhotonMap(mapping) => Some(mapping.trace(&scene, settings.seed, 0)),
+        _ => None,
+    };
+    let scene = Scene {
+        photons: photons.as_ref(),
+        ..scene
+    };
+    let (color, samples) = sample_pixel(
+        settings,
+        &scene,
+        i,
+        j,
+        0..settings.samples_per_pixel,
+        &mut Vec::new(),
+        None,
+    );
+    color * cam.exposure() / samples.max(1) as f32
+}
+
+fn scene<'a>(
+    settings: &RenderSettings,
+    camera: &'a Camera,
+    world: &'a dyn Hittable,
+    lights: &'a dyn Hittable,
+    background: &'a Vector3<f32>,
+) -> Scene<'a> {
+    // the samples cover (width / (width - 1)) x (height / (height - 1)) of the image plane
+    let (width, height) = (settings.width as f32, settings.height as f32);
+    Scene {
+        camera,
+        world,
+        lights,
+        background,
+        max_depth: settings.max_depth,
+        image_area: width * height / ((width - 1.0) * (height - 1.0)),
+        photons: None,
+    }
+}
+
+/// Sum of the colors of the given samples of a pixel and the number of valid ones,
+/// accumulating the passes of the samples and the splats of their light paths.
+fn sample_pixel(
+    settings: &RenderSettings,
+    scene: &Scene,
+    i: u32,
+    j: u32,
+    samples: Range<u16>,
+    splats: &mut Vec<Splat>,
+    mut aovs: Option<&mut AovPixel>,
+) -> (Vector3<f32>, u32) {
+    // seed per pixel and render pass, so the result does not depend on the thread scheduling
+    if let Some(seed) = settings.seed {
+        sampler::seed(seed ^ ((samples.start as u64) << 48) ^ ((j as u64) << 32 | i as u64));
+    }
+
+    let mut rng = sampler::rng();
     let mut color: Vector3<f32> = Vector3::zeros();
 
     // launch parallel iterator
     let mut pixel_cnt = 0;
-    for _ in 0..samples_per_pixel {
-        // need a new rng for each thread
-        let u = (i as f32 + rng.gen::<f32>()) / (image_width - 1) as f32;
-        let v = (j as f32 + rng.gen::<f32>()) / (image_height - 1) as f32;
+    for _ in samples {
+        let u = (i as f32 + rng.gen::<f32>()) / (settings.width - 1) as f32;
+        let v = (j as f32 + rng.gen::<f32>()) / (settings.height - 1) as f32;
 
         // accumulate the color for each sample
-        let new_color = &cam.ray(u, v).color(background, &world, &lights, max_depth);
+        let mut sample = AovSample::default();
+        let new_color = settings.integrator.color(
+            scene,
+            &scene.camera.ray(u, v),
+            splats,
+            aovs.is_some().then_some(&mut sample),
+        );
 
         // cull pixel colors with nan's components (invalid calculations otherwise show up as black pixels)
-        if !(new_color.x != new_color.x || new_color.y != new_color.y || new_color.z != new_color.z)
-        {
+        if !new_color.iter().any(|c| c.is_nan()) {
             color += new_color;
             pixel_cnt += 1;
+            if let Some(aovs) = &mut aovs {
+                aovs.add(&sample);
+            }
         }
     }
 
-    // return the color normalized per sample
-    color / pixel_cnt.max(1) as f32
+    (color, pixel_cnt)
 }
 
-pub fn render(
+/// Render the scene into an in-memory 8-bit sRGB image, with values above one clipped.
+pub fn render_to_buffer(
     cam: &Camera,
-    world: &Box<dyn Hittable>,
-    lights: &Box<dyn Hittable>,
+    world: &dyn Hittable,
+    lights: &dyn Hittable,
     background: &Vector3<f32>,
-    path: &str,
-    samples_per_pixel: u16,
-) {
-    let max_depth = 50;
+    settings: &RenderSettings,
+) -> image::RgbImage {
+    output::preview(
+        &render_to_float_buffer(cam, world, lights, background, settings),
+        &OutputSettings::default(),
+    )
+}
+
+/// Render the scene into an in-memory image of linear color values.
+///
+/// A camera with a stereo rig renders both eyes with the same world, each at the size of the
+/// settings, and places them next to or above each other.
+pub fn render_to_float_buffer(
+    cam: &Camera,
+    world: &dyn Hittable,
+    lights: &dyn Hittable,
+    background: &Vector3<f32>,
+    settings: &RenderSettings,
+) -> Rgb32FImage {
+    let (image, _) = render_passes(cam, world, lights, background, settings, &[]);
+    image
+}
+
+/// Render the scene like [`render_to_float_buffer`], together with an image for each of the
+/// given passes.
+pub fn render_passes(
+    cam: &Camera,
+    world: &dyn Hittable,
+    lights: &dyn Hittable,
+    background: &Vector3<f32>,
+    settings: &RenderSettings,
+    aovs: &[Aov],
+) -> (Rgb32FImage, Vec<Rgb32FImage>) {
+    let (Some(rig), Some([left, right])) = (cam.stereo(), cam.stereo_eyes()) else {
+        return render_view(cam, world, lights, background, settings, aovs);
+    };
+
+    let (width, height) = (settings.width, settings.height);
+    let (buffer_width, buffer_height, offset) = match rig.layout {
+        StereoLayout::SideBySide => (2 * width, height, (width as i64, 0)),
+        StereoLayout::OverUnder => (width, 2 * height, (0, height as i64)),
+    };
+
+    let mut buffer = Rgb32FImage::new(buffer_width, buffer_height);
+    let mut passes = vec![buffer.clone(); aovs.len()];
+    for (eye, (x, y)) in [(left, (0, 0)), (right, offset)] {
+        let (view, view_passes) = render_view(&eye, world, lights, background, settings, aovs);
+        image::imageops::replace(&mut buffer, &view, x, y);
+        for (pass, view_pass) in passes.iter_mut().zip(&view_passes) {
+            image::imageops::replace(pass, view_pass, x, y);
+        }
+    }
+
+    (buffer, passes)
+}
+
+fn render_view(
+    cam: &Camera,
+    world: &dyn Hittable,
+    lights: &dyn Hittable,
+    background: &Vector3<f32>,
+    settings: &RenderSettings,
+    aovs: &[Aov],
+) -> (Rgb32FImage, Vec<Rgb32FImage>) {
+    // generate output buffers
+    let image_width = settings.width;
+    let image_height = settings.height;
+    let mut buffer = Rgb32FImage::new(image_width, image_height);
+    let mut passes = vec![buffer.clone(); aovs.len()];
 
-    // generate output buffer
-    let image_width = 800 as u32;
-    let image_height = (image_width as f32 / cam.aspect_ratio) as u32;
-    let mut buffer: image::RgbImage = image::ImageBuffer::new(image_width, image_height);
+    let scene = scene(settings, cam, world, lights, background);
+    // sums of the samples of the pixels with their number of valid ones, the passes and the
+    // light of the paths from the lights reaching the lens, row by row from the top
+    let pixel_count = (image_width * image_height) as usize;
+    let mut colors = vec![(Vector3::zeros(), 0); pixel_count];
+    let mut aov_pixels = vec![AovPixel::default(); if aovs.is_empty() { 0 } else { pixel_count }];
+    let mut splat_buffer = vec![Vector3::zeros(); pixel_count];
+
+    // progressive photon mapping traces new photon maps for every sample
+    let (photon_passes, samples) = match settings.integrator {
+        Integrator::PhotonMap(mapping) if mapping.progressive => (settings.samples_per_pixel, 1),
+        // the chains of Metropolis light transport cover the whole image at once, the camera
+        // samples of its path tracer only render the passes
+        Integrator::Metropolis(_) if aovs.is_empty() => (0, 0),
+        _ => (1, settings.samples_per_pixel),
+    };
 
     let total_time = SystemTime::now();
     let mut line_time_avg = 0.0;
-    for j in (0..image_height).rev() {
-        let line_time = SystemTime::now();
-
-        let pixels: Vec<(Vector3<f32>, Duration)> = (0..image_width)
-            .into_par_iter()
-            .map(|i| {
-                let t_pixel = SystemTime::now();
-                let color: Vector3<f32> = get_pixel_color(
-                    image_width,
-                    image_height,
-                    samples_per_pixel,
-                    &cam,
-                    &world,
-                    &lights,
-                    &background,
-                    max_depth,
-                    i,
-                    j,
-                );
-
-                (color, t_pixel.elapsed().unwrap())
-            })
-            .collect();
-
-        let line_time = line_time.elapsed().unwrap().as_micros() as f32 * 1.0e-3;
-
-        // low pass filter the average line time
-        if line_time_avg > 0.0 {
-            line_time_avg = line_time_avg + 0.1 * (line_time - line_time_avg);
-        } else {
-            line_time_avg = line_time;
-        }
+    for photon_pass in 0..photon_passes {
+        let photons = match settings.integrator {
+            Integrator::PhotonMap(mapping) => {
+                let photons = mapping.trace(&scene, settings.seed, photon_pass);
+                if settings.progress {
+                    let (global, caustic) = photons.counts();
+                    println!(
+                        "Photon pass {}: {} global and {} caustic photons",
+                        photon_pass, global, caustic
+                    );
+                }
+                Some(photons)
+            }
+            _ => None,
+        };
+        let scene = Scene {
+            photons: photons.as_ref(),
+            ..scene
+        };
+        let samples = photon_pass * samples..(photon_pass + 1) * samples;
 
-        let mut pix_time = 0;
-        for x in 0..image_width {
-            let (pix, duration) = pixels[x as usize];
-            buffer.put_pixel(
-                x,
-                image_height - 1 - j,
-                Rgb([
-                    (pix.x.sqrt() * 255.0) as u8,
-                    (pix.y.sqrt() * 255.0) as u8,
-                    (pix.z.sqrt() * 255.0) as u8,
-                ]),
-            );
-            pix_time += duration.as_micros();
-        }
+        for j in (0..image_height).rev() {
+            let line_time = SystemTime::now();
+            let row = ((image_height - 1 - j) * image_width) as usize;
+
+            type Pixel = ((Vector3<f32>, u32), Option<AovPixel>, Vec<Splat>, Duration);
+            let pixels: Vec<Pixel> = (0..image_width)
+                .into_par_iter()
+                .map(|i| {
+                    let t_pixel = SystemTime::now();
+                    let mut pixel = aov_pixels.get(row + i as usize).copied();
+                    let mut splats = Vec::new();
+                    let color = sample_pixel(
+                        settings,
+                        &scene,
+                        i,
+                        j,
+                        samples.clone(),
+                        &mut splats,
+                        pixel.as_mut(),
+                    );
+
+                    (color, pixel, splats, t_pixel.elapsed().unwrap())
+                })
+                .collect();
+
+            let line_time = line_time.elapsed().unwrap().as_micros() as f32 * 1.0e-3;
+
+            // low pass filter the average line time
+            if line_time_avg > 0.0 {
+                line_time_avg = line_time_avg + 0.1 * (line_time - line_time_avg);
+            } else {
+                line_time_avg = line_time;
+            }
+
+            let mut pix_time = 0;
+            for (x, ((color, count), aov_pixel, splats, duration)) in (0..image_width).zip(pixels) {
+                let pixel = &mut colors[row + x as usize];
+                pixel.0 += color;
+                pixel.1 += count;
+                if let Some(aov_pixel) = aov_pixel {
+                    aov_pixels[row + x as usize] = aov_pixel;
+                }
+                pix_time += duration.as_micros();
+
+                // the splats land on the pixel of their image position, like the camera samples
+                for splat in splats {
+                    let i = (splat.u * (image_width - 1) as f32).floor();
+                    let j = (splat.v * (image_height - 1) as f32).floor();
+                    let inside = (0.0..image_width as f32).contains(&i)
+                        && (0.0..image_height as f32).contains(&j);
+                    if inside && !splat.color.iter().any(|c| c.is_nan()) {
+                        let index = (image_height - 1 - j as u32) * image_width + i as u32;
+                        splat_buffer[index as usize] += splat.color;
+                    }
+                }
+            }
+
+            if !settings.progress {
+                continue;
+            }
+
+            let pix_time = pix_time as f32 * 1.0e-3;
 
-        let pix_time = pix_time as f32 * 1.0e-3;
+            let rem_rows = j + (photon_passes - 1 - photon_pass) as u32 * image_height;
 
-        let rem_rows = j;
+            let eta = line_time_avg * rem_rows as f32 * 1.0e-3;
 
-        let eta = line_time_avg * rem_rows as f32 * 1.0e-3;
+            println!(
+                "# {}\t| Line {:>10.3} [ms]\t| Pixel {:>10.3} [ms]\t| Ratio {:>6.3} \t| ETA: {:>9.2} [s] \t| ELA: {:>9.2} [s]",
+                j,
+                line_time,
+                pix_time,
+                pix_time / line_time,
+                eta,
+                total_time.elapsed().unwrap().as_millis() as f32 * 1.0e-3
+            );
+        }
+    }
+
+    let scale = match settings.integrator {
+        Integrator::Metropolis(metropolis) => {
+            splat_buffer = metropolis.render(&scene, settings);
+            colors.fill((Vector3::zeros(), 0));
+            cam.exposure()
+        }
+        // every camera sample traced one light path
+        _ => cam.exposure() / settings.samples_per_pixel as f32,
+    };
+    let pixels = colors.into_iter().zip(splat_buffer);
+    for (pixel, ((color, count), splat)) in buffer.pixels_mut().zip(pixels) {
+        let color = color * cam.exposure() / count.max(1) as f32 + splat * scale;
+        *pixel = Rgb([color.x, color.y, color.z]);
+    }
+    for (pass, &aov) in passes.iter_mut().zip(aovs) {
+        for (pixel, aov_pixel) in pass.pixels_mut().zip(&aov_pixels) {
+            *pixel = Rgb(aov_pixel.value(aov, cam.exposure()));
+        }
+    }
 
+    if settings.progress {
         println!(
-            "# {}\t| Line {:>10.3} [ms]\t| Pixel {:>10.3} [ms]\t| Ratio {:>6.3} \t| ETA: {:>9.2} [s] \t| ELA: {:>9.2} [s]",
-            j,
-            line_time,
-            pix_time,
-            pix_time / line_time,
-            eta,
+            "Total render time: {:?} [s]",
             total_time.elapsed().unwrap().as_millis() as f32 * 1.0e-3
         );
     }
 
-    println!(
-        "Total render time: {:?} [s]",
-        total_time.elapsed().unwrap().as_millis() as f32 * 1.0e-3
-    );
+    (buffer, passes)
+}
+
+/// Render the scene and save it to an image file with the given passes, see
+/// [`output::save_passes`] for the formats.
+#[allow(clippy::too_many_arguments)]
+pub fn render(
+    cam: &Camera,
+    world: &dyn Hittable,
+    lights: &dyn Hittable,
+    background: &Vector3<f32>,
+    path: &str,
+    settings: &RenderSettings,
+    output: &OutputSettings,
+    aovs: &[Aov],
+) -> Result<(), OutputError> {
+    let (image, passes) = render_passes(cam, world, lights, background, settings, aovs);
+    let passes: Vec<_> = aovs.iter().map(Aov::name).zip(&passes).collect();
+    output::save_passes(&image, &passes, path.as_ref(), output)?;
 
-    buffer.save(path).unwrap();
+    if settings.progress {
+        println!("\rDone!");
+    }
 
-    println!("\rDone!");
+    Ok(())
 }
@@ -0,0 +1,214 @@
+use rand::{rngs::SmallRng, Error, Rng, RngCore, SeedableRng};
+use std::cell::RefCell;
+use std::f32::consts::PI;
+
+thread_local! {
+    static RNG: RefCell<SmallRng> = RefCell::new(SmallRng::from_entropy());
+    static PRIMARY: RefCell<Option<PrimarySamples>> = const { RefCell::new(None) };
+}
+
+/// Standard deviation of a small step of the primary samples.
+const SIGMA: f32 = 0.01;
+
+/// Handle to the random number generator of the current thread.
+///
+/// All sampling decisions of the renderer are drawn from this generator, so seeding it
+/// makes a render reproducible. While primary samples are installed with
+/// [`with_primary_samples`], the numbers come from them instead.
+#[derive(Debug, Default, Copy, Clone)]
+pub struct SamplerRng;
+
+pub fn rng() -> SamplerRng {
+    SamplerRng
+}
+
+/// Reseed the generator of the current thread.
+pub fn seed(seed: u64) {
+    RNG.with(|rng| *rng.borrow_mut() = SmallRng::seed_from_u64(seed));
+}
+
+/// Draw the random numbers of the current thread from the primary samples while `f` runs,
+/// and hand them back with its result.
+pub fn with_primary_samples<R>(
+    samples: PrimarySamples,
+    f: impl FnOnce() -> R,
+) -> (PrimarySamples, R) {
+    let previous = PRIMARY.with(|primary| primary.replace(Some(samples)));
+    let result = f();
+    let samples = PRIMARY.with(|primary| primary.replace(previous));
+    (samples.unwrap(), result)
+}
+
+/// Next number of the installed primary samples scaled to `2^bits`, if there are any.
+fn next_primary(bits: i32) -> Option<f64> {
+    PRIMARY.with(|primary| {
+        let mut primary = primary.borrow_mut();
+        primary
+            .as_mut()
+            .map(|samples| samples.next() as f64 * 2f64.powi(bits))
+    })
+}
+
+impl RngCore for SamplerRng {
+    fn next_u32(&mut self) -> u32 {
+        match next_primary(32) {
+            Some(x) => x as u32,
+            None => RNG.with(|rng| rng.borrow_mut().next_u32()),
+        }
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        match next_primary(64) {
+            Some(x) => x as u64,
+            None => RNG.with(|rng| rng.borrow_mut().next_u64()),
+        }
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        for chunk in dest.chunks_mut(8) {
+            let bytes = self.next_u64().to_le_bytes();
+            chunk.copy_from_slice(&bytes[..chunk.len()]);
+        }
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), Error> {
+        self.fill_bytes(dest);
+        Ok(())
+    }
+}
+
+/// A point in the primary sample space of the renderer, the unit hypercube of the random
+/// numbers a sample draws, mutated by Metropolis light transport.
+///
+/// Every number drawn while the samples are installed is the next coordinate of the point, so
+/// a point always turns into the same camera path. An iteration either takes a large step,
+/// which draws all coordinates anew, or a small step, which moves each of them by a normal
+/// distribution and wraps it around the unit interval. Coordinates are only mutated when they
+/// are drawn, by all the small steps they missed at once, so paths of any length cost the same.
+#[derive(Debug, Clone)]
+pub struct PrimarySamples {
+    rng: SmallRng,
+    coordinates: Vec<Coordinate>,
+    large_step_probability: f32,
+    large_step: bool,
+    /// Number of accepted iterations.
+    iteration: u64,
+    last_large_step: u64,
+    /// Coordinate drawn next.
+    index: usize,
+}
+
+#[derive(Debug, Copy, Clone)]
+struct Coordinate {
+    value: f32,
+    /// Iteration of the last mutation.
+    modified: u64,
+    /// Value and iteration before the mutation of the current iteration, restored when it is
+    /// rejected.
+    backup: (f32, u64),
+}
+
+impl PrimarySamples {
+    /// A random point, drawn by the first iteration.
+    pub fn new(seed: u64, large_step_probability: f32) -> Self {
+        Self {
+            rng: SmallRng::seed_from_u64(seed),
+            coordinates: Vec::new(),
+            large_step_probability,
+            large_step: true,
+            iteration: 0,
+            last_large_step: 0,
+            index: 0,
+        }
+    }
+
+    /// Reseed the generator of the mutations, keeping the point.
+    pub fn reseed(&mut self, seed: u64) {
+        self.rng = SmallRng::seed_from_u64(seed);
+    }
+
+    /// Start the next iteration, a large step with the large step probability.
+    pub fn start_iteration(&mut self) {
+        self.iteration += 1;
+        self.large_step = self.rng.gen::<f32>() < self.large_step_probability;
+        self.index = 0;
+    }
+
+    /// Keep the mutations of the current iteration.
+    pub fn accept(&mut self) {
+        if self.large_step {
+            self.last_large_step = self.iteration;
+        }
+    }
+
+    /// Undo the mutations of the current iteration.
+    pub fn reject(&mut self) {
+        for coordinate in &mut self.coordinates {
+            if coordinate.modified == self.iteration {
+                (coordinate.value, coordinate.modified) = coordinate.backup;
+            }
+        }
+        self.iteration -= 1;
+    }
+
+    fn next(&mut self) -> f32 {
+        if self.index == self.coordinates.len() {
+            // a coordinate that was never drawn is uniform since the last large step
+            let value = self.rng.gen();
+            self.coordinates.push(Coordinate {
+                value,
+                modified: self.last_large_step,
+                backup: (value, self.last_large_step),
+            });
+        }
+
+        let coordinate = &mut self.coordinates[self.index];
+        self.index += 1;
+        if coordinate.modified < self.last_large_step {
+            coordinate.value = self.rng.gen();
+            coordinate.modified = self.last_large_step;
+        }
+        coordinate.backup = (coordinate.value, coordinate.modified);
+
+        if self.large_step {
+            coordinate.value = self.rng.gen();
+        } else {
+            // the sum of the missed small steps, by Box-Muller
+            let steps = (self.iteration - coordinate.modified) as f32;
+            let (a, b): (f32, f32) = self.rng.gen();
+            let normal = (-2.0 * (1.0 - a).ln()).sqrt() * (2.0 * PI * b).cos();
+            let value = coordinate.value + normal * SIGMA * steps.sqrt();
+            coordinate.value = value - value.floor();
+            if coordinate.value >= 1.0 {
+                coordinate.value = 0.0;
+            }
+        }
+        coordinate.modified = self.iteration;
+        coordinate.value
+    }
+}
+
+#[test]
+fn test_seed_is_reproducible() {
+    seed(42);
+    let a: [f32; 4] = rng().gen();
+    seed(42);
+    let b: [f32; 4] = rng().gen();
+    assert_eq!(a, b);
+
+    // the primary samples replay their point, and a rejected mutation restores it
+    let draw = |samples| with_primary_samples(samples, || rng().gen::<[f32; 4]>());
+    let (mut samples, a) = draw(PrimarySamples::new(42, 0.5));
+    let (_, b) = draw(PrimarySamples::new(42, 0.5));
+    assert_eq!(a, b);
+    for _ in 0..8 {
+        samples.start_iteration();
+        let (mutated, b) = draw(samples);
+        samples = mutated;
+        assert_ne!(a, b);
+        assert!(b.iter().all(|x| (0.0..1.0).contains(x)));
+        samples.reject();
+    }
+    let values: Vec<_> = samples.coordinates.iter().map(|c| c.value).collect();
+    assert_eq!(values, a);
+}
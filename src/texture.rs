@@ -1,9 +1,25 @@
-use crate::noise::Perlin;
+use crate::{hit::HitRecord, noise::Perlin};
 use image::{open, ImageBuffer, Rgb};
 use nalgebra::{clamp, Vector3};
+use std::{path::Path, sync::Arc};
 
-pub trait Texture: Sync {
+pub trait Texture: Send + Sync {
     fn value(&self, u: f32, v: f32, p: &Vector3<f32>) -> Vector3<f32>;
+
+    /// Texture value at a hit, textures using more than the texture coordinates and the hit
+    /// point override this.
+    fn sample(&self, rec: &HitRecord) -> Vector3<f32> {
+        self.value(rec.u, rec.v, &rec.p)
+    }
+}
+
+impl<T: Texture + ?Sized> Texture for Arc<T> {
+    fn value(&self, u: f32, v: f32, p: &Vector3<f32>) -> Vector3<f32> {
+        (**self).value(u, v, p)
+    }
+    fn sample(&self, rec: &HitRecord) -> Vector3<f32> {
+        (**self).sample(rec)
+    }
 }
 
 #[derive(Clone)]
@@ -37,13 +53,27 @@ impl<T: Texture, U: Texture> Checker<T, U> {
     }
 }
 
+impl<T: Texture, U: Texture> Checker<T, U> {
+    fn is_odd(p: &Vector3<f32>) -> bool {
+        let sines = f32::sin(10.0 * p.x) * f32::sin(10.0 * p.y) * f32::sin(10.0 * p.z);
+        sines < 0.0
+    }
+}
+
 impl<T: Texture, U: Texture> Texture for Checker<T, U> {
     fn value(&self, u: f32, v: f32, p: &Vector3<f32>) -> Vector3<f32> {
-        let sines = f32::sin(10.0 * p.x) * f32::sin(10.0 * p.y) * f32::sin(10.0 * p.z);
-        if sines < 0.0 {
-            return self.odd.value(u, v, p);
+        if Self::is_odd(p) {
+            self.odd.value(u, v, p)
+        } else {
+            self.even.value(u, v, p)
+        }
+    }
+
+    fn sample(&self, rec: &HitRecord) -> Vector3<f32> {
+        if Self::is_odd(&rec.p) {
+            self.odd.sample(rec)
         } else {
-            return self.even.value(u, v, p);
+            self.even.sample(rec)
         }
     }
 }
@@ -56,8 +86,18 @@ pub struct Image {
 }
 
 impl Image {
+    /// Load the texture from an image file, falling back to an empty texture (rendered in
+    /// solid cyan) when the file cannot be read.
     pub fn new(path: &str) -> Image {
-        let data = open(path).unwrap_or_default().into_rgb8();
+        Self::from_buffer(open(path).unwrap_or_default().into_rgb8())
+    }
+
+    /// Load the texture from an image file.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Image, image::ImageError> {
+        Ok(Self::from_buffer(open(path)?.into_rgb8()))
+    }
+
+    fn from_buffer(data: ImageBuffer<Rgb<u8>, Vec<u8>>) -> Image {
         let (width, height) = (data.width(), data.height());
         Image {
             data,
@@ -70,7 +110,7 @@ impl Image {
 impl Texture for Image {
     fn value(&self, u: f32, v: f32, _p: &Vector3<f32>) -> Vector3<f32> {
         // If we have no texture data, then return solid cyan as a debugging aid.
-        if self.data.len() == 0 {
+        if self.data.is_empty() {
             return Vector3::new(0.0, 1.0, 1.0);
         }
 
@@ -102,7 +142,7 @@ impl Noise {
     pub fn new(scale: f32) -> Self {
         Noise {
             noise: Perlin::new(),
-            scale: scale,
+            scale,
         }
     }
 }
@@ -110,6 +150,31 @@ impl Noise {
 impl Texture for Noise {
     fn value(&self, _u: f32, _v: f32, p: &Vector3<f32>) -> Vector3<f32> {
         Vector3::new(0.5, 0.5, 0.5)
-            * (1.0 + f32::sin(self.scale * p.z + 10.0 * self.noise.turb(&p, 7)))
+            * (1.0 + f32::sin(self.scale * p.z + 10.0 * self.noise.turb(p, 7)))
+    }
+}
+
+/// Interpolated vertex colors of a mesh, objects without vertex colors use the fallback texture.
+#[derive(Clone)]
+pub struct VertexColor<T: Texture> {
+    fallback: T,
+}
+
+impl<T: Texture> VertexColor<T> {
+    pub fn new(fallback: T) -> Self {
+        VertexColor { fallback }
+    }
+}
+
+impl<T: Texture> Texture for VertexColor<T> {
+    fn value(&self, u: f32, v: f32, p: &Vector3<f32>) -> Vector3<f32> {
+        self.fallback.value(u, v, p)
+    }
+
+    fn sample(&self, rec: &HitRecord) -> Vector3<f32> {
+        match rec.color {
+            Some(color) => color,
+            None => self.fallback.sample(rec),
+        }
     }
 }